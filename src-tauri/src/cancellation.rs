@@ -0,0 +1,159 @@
+// src-tauri/src/cancellation.rs
+// Generation IDs and cooperative cancellation for GGUF and HTTP generations
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+
+lazy_static::lazy_static! {
+    // Generation ID -> cancel token of every generation currently running
+    static ref ACTIVE_GENERATIONS: Mutex<HashMap<String, Arc<CancelToken>>> = Mutex::new(HashMap::new());
+}
+
+/// Shared flag checked by decode loops and HTTP streams
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Resolves once `cancel` has been called
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Registration of a running generation. Removes itself from the registry on drop.
+#[derive(Debug)]
+pub struct GenerationGuard {
+    pub id: String,
+    pub token: Arc<CancelToken>,
+}
+
+impl Drop for GenerationGuard {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_GENERATIONS.lock() {
+            // Only remove our own entry, a newer generation may have reused the ID
+            if active.get(&self.id).map(|t| Arc::ptr_eq(t, &self.token)).unwrap_or(false) {
+                active.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Register a generation under `id`, or under a fresh UUID when no ID is supplied
+pub fn register_generation(id: Option<String>) -> GenerationGuard {
+    let id = id
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let token = Arc::new(CancelToken::default());
+
+    ACTIVE_GENERATIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(id.clone(), token.clone());
+
+    GenerationGuard { id, token }
+}
+
+/// Request cancellation of a running generation. Returns false if the ID is unknown.
+pub fn cancel(id: &str) -> bool {
+    let active = ACTIVE_GENERATIONS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    match active.get(id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Tell the frontend which ID to pass to `cancel_generation`
+pub fn emit_generation_started(app: &AppHandle, generation_id: &str) {
+    if let Err(e) = app.emit("generation-started", serde_json::json!({ "generation_id": generation_id })) {
+        log::error!("❌ Event emit hatası: {}", e);
+    }
+}
+
+/// Distinct event for aborted generations, carries the partial text produced so far
+pub fn emit_generation_cancelled(app: &AppHandle, generation_id: &str, partial_text: &str) {
+    let payload = serde_json::json!({
+        "generation_id": generation_id,
+        "partial_text": partial_text,
+    });
+    if let Err(e) = app.emit("generation-cancelled", payload) {
+        log::error!("❌ Event emit hatası: {}", e);
+    }
+}
+
+/// Abort a running GGUF or HTTP generation. The generation returns its partial text.
+#[tauri::command]
+pub fn cancel_generation(generation_id: String) -> Result<bool, String> {
+    log::info!("🛑 Cancel requested for generation: {}", generation_id);
+
+    let found = cancel(&generation_id);
+    if !found {
+        log::warn!("⚠️ No running generation with id: {}", generation_id);
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_registered_generation() {
+        let guard = register_generation(Some("gen-cancel".to_string()));
+        assert!(!guard.token.is_cancelled());
+
+        assert!(cancel("gen-cancel"));
+        assert!(guard.token.is_cancelled());
+    }
+
+    #[test]
+    fn test_guard_unregisters_on_drop() {
+        let guard = register_generation(None);
+        let id = guard.id.clone();
+        assert!(!id.is_empty());
+
+        drop(guard);
+        assert!(!cancel(&id));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_future_resolves() {
+        let guard = register_generation(Some("gen-async".to_string()));
+        let token = guard.token.clone();
+
+        let waiter = tokio::spawn(async move { token.cancelled().await });
+        assert!(cancel("gen-async"));
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiter)
+            .await
+            .expect("cancelled() did not resolve")
+            .unwrap();
+    }
+}
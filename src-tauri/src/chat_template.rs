@@ -0,0 +1,237 @@
+// src-tauri/src/chat_template.rs
+// Built-in chat templates used when llama.cpp cannot apply a model's own template
+
+use crate::commands::ChatMessage;
+
+/// Prompt formats we know how to render without the model's Jinja template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateFamily {
+    ChatMl,
+    Llama3,
+    Mistral,
+    Gemma,
+    Phi3,
+}
+
+impl TemplateFamily {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateFamily::ChatMl => "chatml",
+            TemplateFamily::Llama3 => "llama3",
+            TemplateFamily::Mistral => "mistral",
+            TemplateFamily::Gemma => "gemma",
+            TemplateFamily::Phi3 => "phi3",
+        }
+    }
+
+    /// Pick a family from the template's marker tokens, then from the architecture name.
+    /// The `llama` architecture covers Llama 2, Mistral and many fine-tunes too, so it only
+    /// means Llama 3 when `has_token` finds its header token in the vocabulary.
+    /// ChatML is the default since most recent instruct models understand it.
+    pub fn detect(template: Option<&str>, architecture: Option<&str>, has_token: impl Fn(&str) -> bool) -> Self {
+        if let Some(template) = template {
+            if template.contains("<|im_start|>") {
+                return TemplateFamily::ChatMl;
+            }
+            if template.contains("<|start_header_id|>") {
+                return TemplateFamily::Llama3;
+            }
+            if template.contains("<start_of_turn>") {
+                return TemplateFamily::Gemma;
+            }
+            if template.contains("<|user|>") && template.contains("<|end|>") {
+                return TemplateFamily::Phi3;
+            }
+            if template.contains("[INST]") {
+                return TemplateFamily::Mistral;
+            }
+        }
+
+        let arch = architecture.unwrap_or("").to_lowercase();
+        if arch.starts_with("llama") && has_token("<|start_header_id|>") {
+            TemplateFamily::Llama3
+        } else if arch.starts_with("mistral") || arch.starts_with("mixtral") {
+            TemplateFamily::Mistral
+        } else if arch.starts_with("gemma") {
+            TemplateFamily::Gemma
+        } else if arch.starts_with("phi3") {
+            TemplateFamily::Phi3
+        } else {
+            TemplateFamily::ChatMl
+        }
+    }
+
+    /// Render the conversation. With `add_assistant` the prompt ends with an open assistant turn.
+    pub fn render(&self, messages: &[ChatMessage], add_assistant: bool) -> String {
+        match self {
+            TemplateFamily::ChatMl => render_chatml(messages, add_assistant),
+            TemplateFamily::Llama3 => render_llama3(messages, add_assistant),
+            TemplateFamily::Mistral => render_mistral(messages),
+            TemplateFamily::Gemma => render_gemma(messages, add_assistant),
+            TemplateFamily::Phi3 => render_phi3(messages, add_assistant),
+        }
+    }
+}
+
+fn render_chatml(messages: &[ChatMessage], add_assistant: bool) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        out.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", msg.role, msg.content));
+    }
+    if add_assistant {
+        out.push_str("<|im_start|>assistant\n");
+    }
+    out
+}
+
+fn render_llama3(messages: &[ChatMessage], add_assistant: bool) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        out.push_str(&format!(
+            "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+            msg.role,
+            msg.content.trim()
+        ));
+    }
+    if add_assistant {
+        out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    }
+    out
+}
+
+/// Mistral has no system role - system text is prepended to the first user turn.
+/// The prompt always ends ready for the assistant, so `add_assistant` is implied.
+fn render_mistral(messages: &[ChatMessage]) -> String {
+    let mut out = String::new();
+    let mut pending_system = String::new();
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "system" => {
+                pending_system.push_str(msg.content.trim());
+                pending_system.push_str("\n\n");
+            }
+            "assistant" => {
+                out.push_str(&format!(" {}</s>", msg.content.trim()));
+            }
+            _ => {
+                out.push_str(&format!("[INST] {}{} [/INST]", pending_system, msg.content.trim()));
+                pending_system.clear();
+            }
+        }
+    }
+    out
+}
+
+/// Gemma only knows `user` and `model`; system text is folded into the first user turn
+fn render_gemma(messages: &[ChatMessage], add_assistant: bool) -> String {
+    let mut out = String::new();
+    let mut pending_system = String::new();
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "system" => {
+                pending_system.push_str(msg.content.trim());
+                pending_system.push_str("\n\n");
+            }
+            "assistant" => {
+                out.push_str(&format!("<start_of_turn>model\n{}<end_of_turn>\n", msg.content.trim()));
+            }
+            _ => {
+                out.push_str(&format!(
+                    "<start_of_turn>user\n{}{}<end_of_turn>\n",
+                    pending_system,
+                    msg.content.trim()
+                ));
+                pending_system.clear();
+            }
+        }
+    }
+    if add_assistant {
+        out.push_str("<start_of_turn>model\n");
+    }
+    out
+}
+
+fn render_phi3(messages: &[ChatMessage], add_assistant: bool) -> String {
+    let mut out = String::new();
+    for msg in messages {
+        out.push_str(&format!("<|{}|>\n{}<|end|>\n", msg.role, msg.content));
+    }
+    if add_assistant {
+        out.push_str("<|assistant|>\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: content.to_string() }
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            msg("system", "You are a coder."),
+            msg("user", "Hi"),
+            msg("assistant", "Hello!"),
+            msg("user", "Write fizzbuzz"),
+        ]
+    }
+
+    #[test]
+    fn test_detect_family() {
+        let no_tokens = |_: &str| false;
+        assert_eq!(TemplateFamily::detect(Some("{{ '<|im_start|>' + role }}"), None, no_tokens), TemplateFamily::ChatMl);
+        assert_eq!(TemplateFamily::detect(Some("<|start_header_id|>"), Some("qwen2"), no_tokens), TemplateFamily::Llama3);
+        assert_eq!(TemplateFamily::detect(Some("<start_of_turn>"), None, no_tokens), TemplateFamily::Gemma);
+        assert_eq!(TemplateFamily::detect(Some("<|user|>{{ c }}<|end|>"), None, no_tokens), TemplateFamily::Phi3);
+        assert_eq!(TemplateFamily::detect(Some("[INST] {{ c }} [/INST]"), None, no_tokens), TemplateFamily::Mistral);
+        assert_eq!(TemplateFamily::detect(None, Some("gemma2"), no_tokens), TemplateFamily::Gemma);
+        assert_eq!(TemplateFamily::detect(None, Some("llama"), no_tokens), TemplateFamily::ChatMl);
+        let llama3_vocab = |token: &str| token == "<|start_header_id|>";
+        assert_eq!(TemplateFamily::detect(None, Some("llama"), llama3_vocab), TemplateFamily::Llama3);
+        assert_eq!(TemplateFamily::detect(None, None, no_tokens), TemplateFamily::ChatMl);
+    }
+
+    #[test]
+    fn test_render_chatml() {
+        let out = TemplateFamily::ChatMl.render(&conversation(), true);
+        assert!(out.starts_with("<|im_start|>system\nYou are a coder.<|im_end|>\n"));
+        assert!(out.contains("<|im_start|>assistant\nHello!<|im_end|>\n"));
+        assert!(out.ends_with("<|im_start|>user\nWrite fizzbuzz<|im_end|>\n<|im_start|>assistant\n"));
+    }
+
+    #[test]
+    fn test_render_llama3() {
+        let out = TemplateFamily::Llama3.render(&conversation(), true);
+        assert!(out.starts_with("<|start_header_id|>system<|end_header_id|>\n\nYou are a coder.<|eot_id|>"));
+        assert!(out.ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+    }
+
+    #[test]
+    fn test_render_mistral_folds_system() {
+        let out = TemplateFamily::Mistral.render(&conversation(), true);
+        assert_eq!(
+            out,
+            "[INST] You are a coder.\n\nHi [/INST] Hello!</s>[INST] Write fizzbuzz [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_render_gemma_folds_system() {
+        let out = TemplateFamily::Gemma.render(&conversation(), true);
+        assert!(out.starts_with("<start_of_turn>user\nYou are a coder.\n\nHi<end_of_turn>\n"));
+        assert!(out.contains("<start_of_turn>model\nHello!<end_of_turn>\n"));
+        assert!(out.ends_with("<start_of_turn>model\n"));
+    }
+
+    #[test]
+    fn test_render_phi3() {
+        let out = TemplateFamily::Phi3.render(&conversation(), false);
+        assert!(out.starts_with("<|system|>\nYou are a coder.<|end|>\n<|user|>\nHi<|end|>\n"));
+        assert!(!out.ends_with("<|assistant|>\n"));
+    }
+}
@@ -7,6 +7,8 @@ use reqwest::Client;
 use serde_json::json;
 use log::{info, error};
 use tauri::{AppHandle, Manager, Emitter};
+use crate::gguf_embedding::{EmbeddingSource, GgufEmbeddingOptions};
+use crate::stop_sequences::truncate_at_stop;
 
 // --------------------
 // SYSTEM UTILITIES
@@ -259,9 +261,11 @@ pub struct ProviderConfig {
     pub model_name: String,
     pub temperature: f32,
     pub max_tokens: i32,
+    #[serde(default)]
+    pub context_window: Option<usize>, // 🆕 Biliniyorsa geçmiş bu sınıra göre kırpılır, taşma istekten önce yakalanır
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
@@ -271,7 +275,8 @@ pub struct ChatMessage {
 pub async fn chat_with_dynamic_ai(
     message: String, 
     conversation_history: Vec<ChatMessage>,
-    provider_config: ProviderConfig
+    provider_config: ProviderConfig,
+    stop: Option<Vec<String>>, // 🆕 Stop sequences
 ) -> Result<String, String> {
     info!("🔵 Dinamik AI çağrısı: {} -> {}", provider_config.model_name, provider_config.base_url);
     info!("📤 Mesaj: {}", message);
@@ -291,6 +296,21 @@ pub async fn chat_with_dynamic_ai(
         }
     }
 
+    // ✂️ Context window biliniyorsa eski mesajları modelin tokenizer'ı ile sayarak kırp
+    let conversation_history = match provider_config.context_window {
+        Some(context_window) if !conversation_history.is_empty() => {
+            let max_output = provider_config.max_tokens.max(0) as usize;
+            let (counter, tokenizer, _) = crate::token_count::http_token_counter(&provider_config.model_name);
+            let fit = crate::token_count::fit_history(&conversation_history, context_window.saturating_sub(max_output), &*counter);
+            if fit.dropped > 0 {
+                info!("✂️ {} eski mesaj context'e sığmadığı için çıkarıldı ({:?})", fit.dropped, tokenizer);
+            }
+            crate::token_count::check_context_fits(fit.tokens, max_output, context_window)?;
+            fit.messages
+        }
+        _ => conversation_history,
+    };
+
     // 🔥 Conversation history kullan (eğer varsa), yoksa sadece user message
     let messages: Vec<serde_json::Value> = if !conversation_history.is_empty() {
         conversation_history.iter().map(|msg| {
@@ -306,7 +326,8 @@ pub async fn chat_with_dynamic_ai(
         })]
     };
 
-    let body = json!({
+    let stop = stop.unwrap_or_default();
+    let mut body = json!({
         "model": provider_config.model_name,
         "messages": messages,
         "temperature": provider_config.temperature,
@@ -317,6 +338,9 @@ pub async fn chat_with_dynamic_ai(
         },
         "stream": false
     });
+    if !stop.is_empty() {
+        body["stop"] = json!(stop);
+    }
 
     info!("📡 Endpoint: {}", endpoint);
     info!("🔧 Model: {}, Temp: {}, MaxTokens: {}, Messages: {}", 
@@ -362,7 +386,7 @@ pub async fn chat_with_dynamic_ai(
         if let Some(first_choice) = choices.first() {
             if let Some(content) = first_choice["message"]["content"].as_str() {
                 info!("📥 AI Yanıtı: {}", content);
-                return Ok(content.to_string());
+                return Ok(truncate_at_stop(content, &stop).to_string());
             }
         }
     }
@@ -372,7 +396,7 @@ pub async fn chat_with_dynamic_ai(
         if let Some(first_content) = content.first() {
             if let Some(text) = first_content["text"].as_str() {
                 info!("📥 AI Yanıtı (Anthropic): {}", text);
-                return Ok(text.to_string());
+                return Ok(truncate_at_stop(text, &stop).to_string());
             }
         }
     }
@@ -380,7 +404,7 @@ pub async fn chat_with_dynamic_ai(
     // Fallback - try to find any text content
     if let Some(text) = json["text"].as_str() {
         info!("📥 AI Yanıtı (Fallback): {}", text);
-        return Ok(text.to_string());
+        return Ok(truncate_at_stop(text, &stop).to_string());
     }
 
     error!("❌ AI yanıtı parse edilemedi: {}", response_text);
@@ -512,7 +536,35 @@ pub fn execute_terminal_command(command: String, path: String) -> Result<serde_j
 // BGE EMBEDDING API
 // --------------------
 #[tauri::command]
-pub async fn create_embedding_bge(text: String, endpoint: Option<String>) -> Result<Vec<f32>, String> {
+pub async fn create_embedding_bge(
+    app: AppHandle,
+    text: String,
+    endpoint: Option<String>,
+    gguf: Option<GgufEmbeddingOptions>, // 🆕 Set ise yerel GGUF embedding modeli kullanılır (offline)
+) -> Result<Vec<f32>, String> {
+    create_embedding(&app, text, endpoint, gguf).await
+}
+
+/// Embed with a pooled GGUF model when `gguf` is set, otherwise through the HTTP endpoint
+async fn create_embedding(
+    app: &AppHandle,
+    text: String,
+    endpoint: Option<String>,
+    gguf: Option<GgufEmbeddingOptions>,
+) -> Result<Vec<f32>, String> {
+    let Some(mut options) = gguf else {
+        return create_embedding_http(text, endpoint).await;
+    };
+    options.model_path = crate::gguf::pool_key(&options.model_path);
+
+    info!("🧩 GGUF Embedding oluşturuluyor: {}", options.model_path);
+    let state = app.state::<std::sync::Arc<std::sync::Mutex<crate::gguf::GgufState>>>().inner().clone();
+    tokio::task::spawn_blocking(move || crate::gguf::embed_with_gguf(&state, &options, &text))
+        .await
+        .map_err(|e| format!("Embedding task failed: {}", e))?
+}
+
+async fn create_embedding_http(text: String, endpoint: Option<String>) -> Result<Vec<f32>, String> {
     info!("🧩 BGE Embedding oluşturuluyor...");
     
     let client = Client::new();
@@ -885,97 +937,6 @@ pub async fn execute_command(command: String, args: Vec<String>, cwd: Option<Str
         "exit_code": output.status.code()
     }))
 }
-// GGUF MODEL DOWNLOAD
-// --------------------
-
-#[tauri::command]
-pub async fn download_gguf_model(
-    url: String,
-    destination: String,
-    app: AppHandle
-) -> Result<String, String> {
-    info!("🔵 GGUF model indiriliyor: {}", url);
-    info!("📁 Hedef: {}", destination);
-
-    let client = Client::new();
-    
-    // İndirme başlat
-    let response = client.get(&url)
-        .send()
-        .await
-        .map_err(|e| format!("İndirme başlatılamadı: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("HTTP hatası: {}", response.status()));
-    }
-
-    let total_size = response.content_length().unwrap_or(0);
-    info!("📊 Toplam boyut: {} bytes", total_size);
-
-    // Dosyayı oluştur
-    let dest_path = Path::new(&destination);
-    if let Some(parent) = dest_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Klasör oluşturulamadı: {}", e))?;
-    }
-
-    let mut file = fs::File::create(&destination)
-        .map_err(|e| format!("Dosya oluşturulamadı: {}", e))?;
-
-    // Stream ile indir
-    let mut downloaded: u64 = 0;
-    let mut stream = response.bytes_stream();
-    let mut last_progress_update: u64 = 0;
-
-    use futures_util::StreamExt;
-    use std::io::Write;
-
-    info!("📥 İndirme başladı, toplam: {} bytes", total_size);
-
-    while let Some(chunk_result) = stream.next().await {
-        let chunk = chunk_result.map_err(|e| format!("İndirme hatası: {}", e))?;
-        file.write_all(&chunk).map_err(|e| format!("Yazma hatası: {}", e))?;
-        
-        downloaded += chunk.len() as u64;
-        
-        // Progress event gönder (her 1MB'de bir veya %1 değişimde)
-        let progress = if total_size > 0 {
-            downloaded as f64 / total_size as f64 * 100.0
-        } else {
-            0.0
-        };
-
-        // Her 1MB'de bir veya ilk chunk'ta progress gönder
-        if downloaded - last_progress_update >= 1024 * 1024 || last_progress_update == 0 {
-            last_progress_update = downloaded;
-            
-            let progress_data = json!({
-                "url": url.clone(),
-                "downloaded": downloaded,
-                "total": total_size,
-                "progress": progress
-            });
-
-            info!("📊 Progress: {:.1}% ({} / {} bytes)", progress, downloaded, total_size);
-
-            // Frontend'e progress gönder
-            match app.emit("download-progress", progress_data) {
-                Ok(_) => {},
-                Err(e) => error!("❌ Event emit hatası: {}", e)
-            }
-        }
-    }
-
-    // Son progress'i gönder (100%)
-    let _ = app.emit("download-progress", json!({
-        "url": url.clone(),
-        "downloaded": total_size,
-        "total": total_size,
-        "progress": 100.0
-    }));
-
-    info!("✅ İndirme tamamlandı: {}", destination);
-    Ok(destination)
-}
 
 
 // --------------------
@@ -1009,11 +970,17 @@ pub async fn init_vector_db(db_path: String) -> Result<(), String> {
 
 /// Search vector database for similar code chunks
 #[tauri::command]
-pub async fn vector_search(query: String, top_k: u32, endpoint: Option<String>) -> Result<Vec<CodeChunk>, String> {
+pub async fn vector_search(
+    app: AppHandle,
+    query: String,
+    top_k: u32,
+    endpoint: Option<String>,
+    gguf: Option<GgufEmbeddingOptions>,
+) -> Result<Vec<CodeChunk>, String> {
     info!("🔍 Vector search: {} (top_k: {})", query, top_k);
     
     // Create embedding for query
-    let query_embedding = create_embedding_bge(query, endpoint).await?;
+    let query_embedding = create_embedding(&app, query, endpoint, gguf).await?;
     
     // Get VectorDB instance
     let global_db: tokio::sync::MutexGuard<Option<VectorDB>> = VECTOR_DB.lock().await;
@@ -1031,14 +998,19 @@ pub async fn vector_search(query: String, top_k: u32, endpoint: Option<String>)
 
 /// Index a file in the vector database
 #[tauri::command]
-pub async fn index_file_vector(file_path: String, endpoint: Option<String>) -> Result<(), String> {
+pub async fn index_file_vector(
+    app: AppHandle,
+    file_path: String,
+    endpoint: Option<String>,
+    gguf: Option<GgufEmbeddingOptions>,
+) -> Result<(), String> {
     info!("📇 Dosya indeksleniyor: {}", file_path);
     
     // Read file content
     let content = read_file(file_path.clone())?;
     
     // Create embedding
-    let embedding = create_embedding_bge(content.clone(), endpoint).await?;
+    let embedding = create_embedding(&app, content.clone(), endpoint, gguf).await?;
     
     // Create chunk
     let chunk = CodeChunk {
@@ -1071,17 +1043,19 @@ pub async fn index_file_vector(file_path: String, endpoint: Option<String>) -> R
 /// Index manual content (like git commits) in the vector database
 #[tauri::command]
 pub async fn index_manual_vector(
+    app: AppHandle,
     id: String,
     file_path: String,
     content: String,
     chunk_type: String,
     symbol_name: Option<String>,
-    endpoint: Option<String>
+    embedding: Option<EmbeddingSource>, // 🆕 endpoint ve/veya yerel GGUF embedding modeli
 ) -> Result<(), String> {
     info!("📇 Manuel veri indeksleniyor: {} ({})", id, chunk_type);
     
     // Create embedding
-    let embedding = create_embedding_bge(content.clone(), endpoint).await?;
+    let EmbeddingSource { endpoint, gguf } = embedding.unwrap_or_default();
+    let embedding = create_embedding(&app, content.clone(), endpoint, gguf).await?;
     
     // Create chunk
     let chunk = CodeChunk {
@@ -1151,12 +1125,19 @@ pub async fn analyze_query_intent(query: String) -> Result<QueryIntent, String>
 /// Build context from multiple sources
 #[tauri::command]
 pub async fn build_rag_context(
+    state: tauri::State<'_, std::sync::Arc<std::sync::Mutex<crate::gguf::GgufState>>>,
     query: String,
     max_tokens: Option<usize>,
+    model: Option<String>, // 🆕 GGUF model path veya HTTP model adı - token bütçesi bu modelin tokenizer'ı ile hesaplanır
 ) -> Result<serde_json::Value, String> {
     info!("🔨 RAG context oluşturuluyor: {}", query);
     
-    let pipeline = RAGPipeline::new(max_tokens.unwrap_or(170_000));
+    let mut pipeline = RAGPipeline::new(max_tokens.unwrap_or(170_000));
+    if let Some(model) = &model {
+        let (counter, tokenizer, _) = crate::token_count::token_counter(&state, model);
+        info!("🔢 Token bütçesi {:?} tokenizer ile hesaplanıyor", tokenizer);
+        pipeline = pipeline.with_token_counter(counter);
+    }
     
     // Analyze intent
     let intent = pipeline.analyze_intent(&query);
@@ -1165,13 +1146,14 @@ pub async fn build_rag_context(
     let result: Result<(String, Vec<ContextSource>), Box<dyn std::error::Error>> = pipeline.build_context(intent.clone(), &query).await;
     let (context, sources) = result.map_err(|e| format!("Context build hatası: {}", e))?;
     
-    info!("✅ Context oluşturuldu: {} tokens", RAGPipeline::estimate_tokens(&context));
+    let token_count = pipeline.count_tokens(&context);
+    info!("✅ Context oluşturuldu: {} tokens", token_count);
     
     Ok(json!({
         "context": context,
         "sources": sources,
         "intent": intent,
-        "token_count": RAGPipeline::estimate_tokens(&context)
+        "token_count": token_count
     }))
 }
 
@@ -1,31 +1,347 @@
 // GGUF System - Complete implementation in one file
-use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::context::params::{LlamaContextParams, LlamaPoolingType};
+use llama_cpp_2::context::LlamaContext;
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
-use llama_cpp_2::model::{LlamaModel, AddBos};
+use llama_cpp_2::model::{LlamaModel, AddBos, LlamaChatMessage, LlamaLoraAdapter};
+use llama_cpp_2::mtmd::{mtmd_default_marker, MtmdBitmap, MtmdContext, MtmdContextParams, MtmdInputText};
+use llama_cpp_2::token::LlamaToken;
 use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use log::{info, error, warn};
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, State};
+use serde::Serialize;
 use serde_json::json;
-use base64::{Engine as _, engine::general_purpose}; // 🆕 Base64 decoding
-use rand::Rng; // 🆕 Random number generation for sampling
+
+use crate::cancellation;
+use crate::chat_template::TemplateFamily;
+use crate::commands::ChatMessage;
+use crate::gguf_context_shift::{apply_shifts, truncate_prompt, ContextShiftReport};
+use crate::gguf_embedding::{l2_normalize, EmbeddingPooling, GgufEmbeddingOptions};
+use crate::gguf_fim::{clean_middle, fim_stop_strings, trim_context, FimCompletion, FimFamily, FimOptions, FimScope, FimTemplate, FimTokenIds};
+use crate::gguf_header::{file_type_name, format_parameter_count, GgufHeader, GgufModelHeader};
+use crate::gguf_inference::{generate, generate_after, reusable_prefix, GenerationOutput, GenerationParams, GgufRequestOptions, TokenLogprob, MAX_BATCH_SIZE};
+use crate::gguf_library::resolve_model_path;
+use crate::gguf_lora::{check_adapter_header, check_scale, default_lora_name, resolve_loras, ActiveLora, LoraInfo};
+use crate::gguf_memory::{bytes_to_gb, kv_cache_footprint, KvCacheType, MemoryBreakdown, ModelHyperparams};
+use crate::gguf_metrics::{benchmark_prompts, elapsed_ms, summarize, BenchmarkSummary, GenerationMetrics, StopReason};
+use crate::gguf_pool::{plan_eviction, weights_footprint, ModelFootprint, PoolBudget, PoolEntry};
+use crate::gguf_sampling::SamplingParams;
+use crate::gguf_scheduler::{RequestPriority, ScheduleOptions};
+use crate::gguf_session::SessionMeta;
+use crate::gguf_speculative::{generate_speculative, SpeculativeConfig, SpeculativeModel, SpeculativeStats};
+use crate::gguf_vision::{decode_image, find_mmproj, with_media_markers};
+use crate::gguf_worker::Worker;
+use crate::rag_pipeline::RAGPipeline;
+use crate::token_count::{fit_history, TokenCounter};
 
 use std::collections::HashMap;
 
 // State structure
 pub struct LoadedModel {
-    pub model: LlamaModel,
+    pub worker: Worker<ModelSlot>, // 🆕 Inference thread owning the model and its context
     pub model_path: String,
     pub n_ctx: u32,
     pub n_gpu_layers: u32,
+    pub n_vocab: i32,
+    pub footprint: ModelFootprint, // Estimated RAM/VRAM use, counted against the pool budget
+    pub weights: ModelFootprint,   // Tensor bytes, split by offloaded layers
+    pub hparams: Option<ModelHyperparams>,
+    pub pinned: bool,              // Pinned models are never evicted
+    pub last_used: u64,            // GgufState::use_counter value of the last request
+    pub loras: Vec<LoraInfo>,      // 🆕 LoRA adapters attached to the model
+    pub fim: Option<FimTemplate>,  // 🆕 Fill-in-the-middle format, None for non-code models
+    pub model: Arc<LlamaModel>,    // 🆕 Shared with the worker; tokenizing is safe from any thread
+    pub mmproj: Option<String>,    // 🆕 Vision projector attached to the worker's model
+    pub load_ms: f64,              // 🆕 How long load_gguf_model took, reported with every generation
+    kv_size: Arc<AtomicU32>,       // Size of the worker's cached context, 0 when there is none
+}
+
+/// Everything a model's worker thread owns
+pub struct ModelSlot {
+    // Field order matters: the cached contexts use `loras` and borrow `model`, both have to outlive them
+    cache: Option<CachedContext>,
+    embedder: Option<EmbeddingContext>, // Reused by `embed_with_gguf` calls
+    loras: HashMap<String, LoraAdapter>, // Adapter name -> adapter loaded onto `model`
+    projector: Option<Projector>, // mmproj image encoder, built on `model`
+    model: Arc<LlamaModel>, // Heap allocated so the address stays stable while the context borrows it
+    backend: Arc<LlamaBackend>,
+    kv_size: Arc<AtomicU32>,
+}
+
+/// Context kept alive between requests so a shared prompt prefix is not re-evaluated
+pub struct CachedContext {
+    context: LlamaContext<'static>,
+    /// Tokens currently in the KV cache, position by position
+    tokens: Vec<LlamaToken>,
+    kv_size: u32,
+    /// LoRA adapters set on the context, the KV cache was computed with them
+    loras: Vec<ActiveLora>,
+}
+
+// The context is only used by one thread at a time: its model's worker, or the
+// speculative request it was lent to
+unsafe impl Send for CachedContext {}
+
+/// Embedding context kept between requests, recreated for a longer input or another pooling
+struct EmbeddingContext {
+    context: LlamaContext<'static>,
+    /// Longest input that fits in one ubatch
+    n_tokens: u32,
+    pooling: EmbeddingPooling,
+}
+
+// Created, used and dropped on the owning model's worker thread only
+unsafe impl Send for EmbeddingContext {}
+
+/// A LoRA adapter loaded onto a slot's model
+struct LoraAdapter {
+    adapter: LlamaLoraAdapter,
+}
+
+// Created, applied and dropped on the owning model's worker thread only
+unsafe impl Send for LoraAdapter {}
+
+/// A multimodal projector (mmproj) loaded for a slot's model
+struct Projector {
+    context: MtmdContext,
+}
+
+// Created, used and dropped on the owning model's worker thread only
+unsafe impl Send for Projector {}
+
+/// A draft model's context handed to another model's worker for speculative decoding
+struct LentContext {
+    // Field order matters: `cache` borrows `model`
+    cache: CachedContext,
+    model: Arc<LlamaModel>,
+}
+
+/// A lent draft context that goes back to its worker when dropped, so it is
+/// returned whether the request ran, failed or was dropped from the queue
+struct DraftLease {
+    worker: Worker<ModelSlot>,
+    lent: Option<LentContext>,
+}
+
+impl Drop for DraftLease {
+    fn drop(&mut self) {
+        let Some(lent) = self.lent.take() else { return };
+        // The draft keeps the prefix for the next request; a closed worker just drops it here
+        let _ = self.worker.submit(Box::new(move |slot: &mut ModelSlot| {
+            let LentContext { cache, model } = lent;
+            slot.put_context(cache);
+            drop(model);
+        }));
+    }
+}
+
+impl CachedContext {
+    /// Keep the longest prefix shared with `tokens` in the KV cache and drop the rest.
+    /// Returns how many prompt tokens do not need to be evaluated again; none on a cold start.
+    fn reuse_prefix(&mut self, tokens: &[LlamaToken], cold_start: bool) -> usize {
+        if cold_start {
+            self.store(None);
+            return 0;
+        }
+        let reused = reusable_prefix(&self.tokens, tokens);
+        match self.context.clear_kv_cache_seq(Some(0), Some(reused as u32), None) {
+            Ok(true) => {
+                info!("♻️ Reusing {}/{} prompt tokens from KV cache", reused, tokens.len());
+                reused
+            }
+            other => {
+                // Recurrent models cannot drop a partial sequence
+                warn!("⚠️ KV cache trim failed, evaluating full prompt: {:?}", other);
+                self.context.clear_kv_cache();
+                0
+            }
+        }
+    }
+
+    /// Record what the KV cache holds after a run; `None` means unknown, so it is wiped
+    fn store(&mut self, kv_tokens: Option<&[LlamaToken]>) {
+        self.tokens.clear();
+        match kv_tokens {
+            Some(kv_tokens) => self.tokens.extend_from_slice(kv_tokens),
+            None => self.context.clear_kv_cache(),
+        }
+    }
+}
+
+impl LoadedModel {
+    /// Current memory use: weights plus the KV cache of the live context, if any
+    pub fn memory(&self) -> MemoryBreakdown {
+        let kv_size = self.kv_size.load(Ordering::SeqCst);
+        let kv_cache = match &self.hparams {
+            Some(hparams) if kv_size > 0 => {
+                kv_cache_footprint(hparams, kv_size, KvCacheType::default(), self.n_gpu_layers)
+            }
+            _ => ModelFootprint::default(),
+        };
+        MemoryBreakdown { weights: self.weights, kv_cache }
+    }
+}
+
+impl ModelSlot {
+    /// Hand out the cached context if it is large enough, otherwise create a new one
+    fn take_context(&mut self, kv_size: u32) -> Result<CachedContext, String> {
+        if let Some(cache) = self.cache.take() {
+            if cache.kv_size >= kv_size {
+                return Ok(cache);
+            }
+            info!("♻️ Cached context too small ({} < {}), recreating", cache.kv_size, kv_size);
+            self.kv_size.store(0, Ordering::SeqCst);
+        }
+
+        let ctx_params = LlamaContextParams::default()
+            .with_n_ctx(std::num::NonZero::new(kv_size)) // Use larger context for KV cache
+            .with_n_batch(MAX_BATCH_SIZE as u32);
+
+        let context = self.model.new_context(&self.backend, ctx_params)
+            .map_err(|e| {
+                error!("❌ Context creation failed: {:?}", e);
+                format!("Context creation failed: {:?}", e)
+            })?;
+
+        info!("✅ Context created with KV cache size: {}", kv_size);
+
+        // SAFETY: the model lives behind an Arc held by this slot (and by every
+        // LentContext), both drop `cache` before `model`, so the context never
+        // outlives what it borrows.
+        let context = unsafe { std::mem::transmute::<LlamaContext<'_>, LlamaContext<'static>>(context) };
+        Ok(CachedContext { context, tokens: Vec::new(), kv_size, loras: Vec::new() })
+    }
+
+    /// The cached embedding context if it takes `n_tokens` with `pooling`, otherwise a new one
+    fn embedding_context(&mut self, n_tokens: u32, pooling: EmbeddingPooling) -> Result<&mut EmbeddingContext, String> {
+        let reusable = self.embedder.as_ref()
+            .map(|e| e.n_tokens >= n_tokens && e.pooling == pooling)
+            .unwrap_or(false);
+        if !reusable {
+            // Free the old context before the new one is allocated
+            self.embedder = None;
+
+            // Non-causal embedding models need the whole input in a single ubatch;
+            // rounded up so slightly longer texts do not recreate it every time
+            let size = n_tokens.max(64).next_power_of_two()
+                .min(self.model.n_ctx_train().max(64))
+                .max(n_tokens);
+            let pooling_type = match pooling {
+                EmbeddingPooling::Model => LlamaPoolingType::Unspecified,
+                EmbeddingPooling::Mean => LlamaPoolingType::Mean,
+                EmbeddingPooling::Cls => LlamaPoolingType::Cls,
+                EmbeddingPooling::Last => LlamaPoolingType::Last,
+            };
+            let ctx_params = LlamaContextParams::default()
+                .with_n_ctx(std::num::NonZero::new(size))
+                .with_n_batch(size)
+                .with_n_ubatch(size)
+                .with_embeddings(true)
+                .with_pooling_type(pooling_type);
+
+            let context = self.model.new_context(&self.backend, ctx_params)
+                .map_err(|e| format!("Embedding context creation failed: {:?}", e))?;
+            info!("✅ Embedding context created for up to {} tokens", size);
+
+            // SAFETY: as in `take_context`, the slot drops `embedder` before `model`
+            let context = unsafe { std::mem::transmute::<LlamaContext<'_>, LlamaContext<'static>>(context) };
+            self.embedder = Some(EmbeddingContext { context, n_tokens: size, pooling });
+        }
+        self.embedder.as_mut().ok_or_else(|| "Embedding context yok".to_string())
+    }
+
+    /// `take_context` with exactly `loras` set on the context
+    fn prepare_context(&mut self, kv_size: u32, loras: &[ActiveLora]) -> Result<CachedContext, String> {
+        let mut cache = self.take_context(kv_size)?;
+        if let Err(e) = self.apply_loras(&mut cache, loras) {
+            self.put_context(cache);
+            return Err(e);
+        }
+        Ok(cache)
+    }
+
+    /// Switch the adapters set on `cache`. Tokens cached under another adapter set
+    /// would produce wrong continuations, so any change clears the KV cache.
+    fn apply_loras(&mut self, cache: &mut CachedContext, loras: &[ActiveLora]) -> Result<(), String> {
+        if cache.loras == loras {
+            return Ok(());
+        }
+        cache.store(None);
+
+        for active in std::mem::take(&mut cache.loras) {
+            if let Some(lora) = self.loras.get_mut(&active.name) {
+                if let Err(e) = cache.context.lora_adapter_remove(&mut lora.adapter) {
+                    warn!("⚠️ LoRA kaldırılamadı ({}): {:?}", active.name, e);
+                }
+            }
+        }
+
+        for active in loras {
+            let lora = self.loras.get_mut(&active.name)
+                .ok_or_else(|| format!("LoRA adapter yüklü değil: {}", active.name))?;
+            cache.context.lora_adapter_set(&mut lora.adapter, active.scale)
+                .map_err(|e| format!("LoRA uygulanamadı ({}): {:?}", active.name, e))?;
+            cache.loras.push(active.clone());
+        }
+
+        if !loras.is_empty() {
+            info!("🧬 LoRA adapters active: {:?}", loras.iter().map(|l| (&l.name, l.scale)).collect::<Vec<_>>());
+        }
+        Ok(())
+    }
+
+    /// Keep `cache` for the next request
+    fn put_context(&mut self, cache: CachedContext) {
+        self.kv_size.store(cache.kv_size, Ordering::SeqCst);
+        self.cache = Some(cache);
+    }
+
+    /// Load the adapter at `path` onto the model under `name`
+    fn init_lora(&mut self, name: String, path: &str) -> Result<(), String> {
+        if self.loras.contains_key(&name) {
+            return Err(format!("Bu isimde bir LoRA zaten yüklü: {}", name));
+        }
+
+        let header = GgufHeader::read_file(path)?;
+        let architecture = self.model.meta_val_str("general.architecture").ok();
+        check_adapter_header(&header, architecture.as_deref())?;
+
+        let adapter = self.model.lora_adapter_init(path)
+            .map_err(|e| {
+                error!("❌ LoRA init failed: {:?}", e);
+                format!("LoRA yüklenemedi: {:?}", e)
+            })?;
+        self.loras.insert(name, LoraAdapter { adapter });
+        Ok(())
+    }
+
+    /// Build the mmproj image encoder at `path` on the model
+    fn init_projector(&mut self, path: &str, use_gpu: bool) -> Result<(), String> {
+        let params = MtmdContextParams { use_gpu, ..MtmdContextParams::default() };
+        let context = MtmdContext::init_from_file(path, &self.model, &params)
+            .map_err(|e| {
+                error!("❌ mmproj init failed: {:?}", e);
+                format!("mmproj yüklenemedi, model ile eşleştiğinden emin olun: {:?}", e)
+            })?;
+        if !context.support_vision() {
+            return Err(format!("Bu projector görsel girdiyi desteklemiyor: {}", path));
+        }
+        self.projector = Some(Projector { context });
+        Ok(())
+    }
 }
 
 pub struct GgufState {
-    pub backend: Option<LlamaBackend>,
+    pub backend: Option<Arc<LlamaBackend>>,   // Shared with every model worker
     pub models: HashMap<String, LoadedModel>, // Model path -> Model info
     pub backend_initialized: bool,
+    pub budget: PoolBudget,                   // 🆕 RAM/VRAM limit for the whole pool
+    pub use_counter: u64,                     // 🆕 Bumped on every request, orders models for LRU eviction
+    pub loading: HashMap<String, ModelFootprint>, // 🆕 Budget reserved by loads in progress
 }
 
 impl Default for GgufState {
@@ -34,24 +350,131 @@ impl Default for GgufState {
             backend: None,
             models: HashMap::new(),
             backend_initialized: false,
+            budget: PoolBudget::default(),
+            use_counter: 0,
+            loading: HashMap::new(),
+        }
+    }
+}
+
+impl GgufState {
+    /// Mark a model as just used, for LRU eviction
+    fn touch(&mut self, model_path: &str) {
+        self.use_counter += 1;
+        let tick = self.use_counter;
+        if let Some(loaded_model) = self.models.get_mut(model_path) {
+            loaded_model.last_used = tick;
+        }
+    }
+
+    /// Unload the least recently used unpinned models until `incoming` fits the budget.
+    /// Loads in progress count against the budget and cannot be evicted. The pooled copy
+    /// a reload is `replacing` is left out: it goes away once the new one is inserted.
+    fn make_room(&mut self, app: &AppHandle, incoming: ModelFootprint, replacing: Option<&str>) -> Result<(), String> {
+        let entries: Vec<PoolEntry> = self.models.values()
+            .filter(|m| Some(m.model_path.as_str()) != replacing)
+            .map(|m| PoolEntry {
+                path: &m.model_path,
+                footprint: m.footprint,
+                last_used: m.last_used,
+                pinned: m.pinned,
+            })
+            .chain(self.loading.iter().map(|(path, footprint)| PoolEntry {
+                path,
+                footprint: *footprint,
+                last_used: u64::MAX,
+                pinned: true,
+            }))
+            .collect();
+        let evict = plan_eviction(&entries, &self.budget, incoming)?;
+
+        for path in evict {
+            if let Some(evicted) = self.models.remove(&path) {
+                info!("♻️ Evicted from pool (LRU): {}", path);
+                let payload = json!({
+                    "model_path": path,
+                    "reason": "memory_budget",
+                    "ram_bytes": evicted.footprint.ram_bytes,
+                    "vram_bytes": evicted.footprint.vram_bytes,
+                });
+                if let Err(e) = app.emit("gguf-model-evicted", payload) {
+                    error!("❌ Event emit hatası: {}", e);
+                }
+            }
         }
+        Ok(())
     }
+
+    /// Pooled models plus the reservations of loads in progress
+    fn usage(&self) -> ModelFootprint {
+        let footprints = self.models.values().map(|m| &m.footprint).chain(self.loading.values());
+        footprints.fold(ModelFootprint::default(), |acc, f| ModelFootprint {
+            ram_bytes: acc.ram_bytes + f.ram_bytes,
+            vram_bytes: acc.vram_bytes + f.vram_bytes,
+        })
+    }
+}
+
+/// Budget held in `GgufState::loading` while a model loads with the pool unlocked.
+/// Dropping it (a failed load) gives the budget back; a finished load calls `release`.
+struct LoadReservation {
+    state: Arc<Mutex<GgufState>>,
+    model_path: Option<String>,
+}
+
+impl LoadReservation {
+    /// Give the budget back under a lock the caller already holds
+    fn release(mut self, state: &mut GgufState) {
+        if let Some(model_path) = self.model_path.take() {
+            state.loading.remove(&model_path);
+        }
+    }
+}
+
+impl Drop for LoadReservation {
+    fn drop(&mut self) {
+        if let Some(model_path) = self.model_path.take() {
+            lock_state(&self.state).loading.remove(&model_path);
+        }
+    }
+}
+
+/// Weights from the tensor table (the file size when the header is unreadable) plus
+/// the KV cache a context of `n_ctx` cells will need
+fn estimate_footprint(
+    header: Option<&GgufModelHeader>,
+    hparams: Option<&ModelHyperparams>,
+    file_size: u64,
+    n_ctx: u32,
+    n_gpu_layers: u32,
+) -> MemoryBreakdown {
+    let weights = match header {
+        Some(h) => weights_footprint(&h.tensors, h.header.arch_u64("block_count").unwrap_or(0), n_gpu_layers),
+        None => ModelFootprint { ram_bytes: file_size, vram_bytes: 0 },
+    };
+    let kv_cache = hparams
+        .map(|hp| kv_cache_footprint(hp, n_ctx, KvCacheType::default(), n_gpu_layers))
+        .unwrap_or_default();
+    MemoryBreakdown { weights, kv_cache }
 }
 
 // Commands
 #[tauri::command]
 pub async fn load_gguf_model(
+    app: AppHandle,
     state: State<'_, Arc<Mutex<GgufState>>>,
-    model_path: String,
+    model_path: String, // 🆕 Dosya yolu veya model kütüphanesi ID'si
     n_ctx: u32,
     n_gpu_layers: u32,
+    pinned: Option<bool>, // 🆕 Pinned modeller LRU ile çıkarılmaz
 ) -> Result<String, String> {
     info!("🔵 GGUF model loading: {}", model_path);
     info!("📊 Context: {}, GPU Layers: {}", n_ctx, n_gpu_layers);
+    let load_started = Instant::now();
     
     // Split GGUF dosyalari icin ilk parcaya yonlendir
     // Ornek: model-00003-of-00004.gguf -> model-00001-of-00004.gguf
-    let model_path = resolve_split_gguf_path(&model_path);
+    let model_path = pool_key(&model_path);
     info!("📂 Resolved model path: {}", model_path);
     
     if !Path::new(&model_path).exists() {
@@ -76,7 +499,16 @@ pub async fn load_gguf_model(
         }
     }
 
-    let mut state_guard = state.lock().unwrap();
+    // Tensor table tells how much memory the model will take
+    let header = match GgufModelHeader::read(&model_path) {
+        Ok(header) => Some(header),
+        Err(e) => {
+            warn!("⚠️ GGUF header okunamadı, dosya boyutu ile tahmin ediliyor: {}", e);
+            None
+        }
+    };
+
+    let mut state_guard = lock_state(&state);
 
     // Initialize backend only once
     if !state_guard.backend_initialized {
@@ -89,7 +521,7 @@ pub async fn load_gguf_model(
                 format!("Backend init failed: {:?}", e)
             })?;
         
-        state_guard.backend = Some(backend);
+        state_guard.backend = Some(Arc::new(backend));
         state_guard.backend_initialized = true;
         info!("✅ Backend initialized");
         info!("✅ CUDA should be available if compiled with cublas feature");
@@ -97,8 +529,6 @@ pub async fn load_gguf_model(
         info!("✅ Backend already initialized, reusing...");
     }
 
-    let backend = state_guard.backend.as_ref().unwrap();
-
     // GPU layers parametresini ayarla
     // CUDA veya Vulkan yoksa otomatik olarak 0'a düşür
     let has_gpu = cfg!(feature = "cuda") || cfg!(feature = "vulkan");
@@ -117,6 +547,33 @@ pub async fn load_gguf_model(
         info!("⚠️ No GPU backend - Forcing CPU-only (GPU layers = 0)");
         0
     };
+
+    // ♻️ Bütçe aşılacaksa en az kullanılan modelleri çıkar
+    let hparams = header.as_ref().and_then(|h| ModelHyperparams::from_header(&h.header));
+    let footprint = estimate_footprint(header.as_ref(), hparams.as_ref(), metadata.len(), n_ctx, safe_gpu_layers);
+    info!(
+        "📐 Estimated footprint: RAM {} MB, VRAM {} MB",
+        footprint.total().ram_bytes / (1024 * 1024),
+        footprint.total().vram_bytes / (1024 * 1024)
+    );
+    if state_guard.loading.contains_key(&model_path) {
+        return Err(format!("Model zaten yükleniyor: {}", model_path));
+    }
+    // A reload keeps serving from the pooled copy until the new one is ready
+    if state_guard.models.contains_key(&model_path) {
+        info!("♻️ Reloading pooled model: {}", model_path);
+    }
+    state_guard.make_room(&app, footprint.total(), Some(&model_path))?;
+
+    // Loading takes a while - release the pool so status queries and other models keep going.
+    // The reservation keeps concurrent loads from planning against the same free memory.
+    state_guard.loading.insert(model_path.clone(), footprint.total());
+    let reservation = LoadReservation {
+        state: state.inner().clone(),
+        model_path: Some(model_path.clone()),
+    };
+    let backend = state_guard.backend.clone().unwrap();
+    drop(state_guard);
     
     let model_params = LlamaModelParams::default()
         .with_n_gpu_layers(safe_gpu_layers);
@@ -129,7 +586,7 @@ pub async fn load_gguf_model(
     // Bellek yetersiz ise CPU'ya otomatik fallback yapılır
     
     let mut final_gpu_layers = n_gpu_layers;
-    let model = match LlamaModel::load_from_file(backend, &model_path, &model_params) {
+    let model = match LlamaModel::load_from_file(&backend, &model_path, &model_params) {
         Ok(m) => m,
         Err(e) => {
             error!("❌ GPU yükleme başarısız: {:?}", e);
@@ -144,7 +601,7 @@ pub async fn load_gguf_model(
                 let cpu_model_params = LlamaModelParams::default()
                     .with_n_gpu_layers(0); // CPU-only
                 
-                match LlamaModel::load_from_file(backend, &model_path, &cpu_model_params) {
+                match LlamaModel::load_from_file(&backend, &model_path, &cpu_model_params) {
                     Ok(m) => {
                         info!("✅ Model CPU'da başarıyla yüklendi");
                         m
@@ -178,7 +635,8 @@ pub async fn load_gguf_model(
         }
     };
 
-    info!("✅ Model loaded successfully!");
+    let load_ms = elapsed_ms(load_started);
+    info!("✅ Model loaded successfully! ({:.0} ms)", load_ms);
     info!("📦 Model: {}", model_path);
     info!("🎮 GPU Layers: {}", final_gpu_layers);
     info!("📝 Context: {}", n_ctx);
@@ -190,12 +648,78 @@ pub async fn load_gguf_model(
         info!("⚠️ GPU offload kapalı - Model CPU'da çalışacak");
     }
 
-    // Save model to state pool
+    // CPU fallback moves everything to RAM
+    let footprint = if final_gpu_layers == 0 && safe_gpu_layers > 0 {
+        estimate_footprint(header.as_ref(), hparams.as_ref(), metadata.len(), n_ctx, 0)
+    } else {
+        footprint
+    };
+
+    let fim = match header.as_ref() {
+        Some(h) => FimTemplate::from_header(&h.header, &model_path),
+        None => FimTemplate::from_name(&model_path),
+    };
+    if let Some(fim) = &fim {
+        info!("🧩 FIM supported: {:?} (token ids: {})", fim.family, fim.token_ids.is_some());
+    }
+
+    // 🧵 Model gets its own worker thread, requests for it queue up there
+    let n_vocab = model.n_vocab();
+    let kv_size = Arc::new(AtomicU32::new(0));
+    let file_name = Path::new(&model_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("model");
+    let model = Arc::new(model);
+    let worker = Worker::spawn(file_name, ModelSlot {
+        cache: None,
+        embedder: None,
+        loras: HashMap::new(),
+        projector: None,
+        model: model.clone(),
+        backend,
+        kv_size: kv_size.clone(),
+    })?;
+
+    // A reload keeps the pin (unless the caller sets it), the adapters and the projector
+    let previous = lock_state(&state).models.get(&model_path)
+        .map(|m| (m.pinned, m.loras.clone(), m.mmproj.clone()));
+    let (pinned, loras, mmproj) = match previous {
+        Some((was_pinned, loras, mmproj)) => {
+            let loras = reattach_loras(&worker, loras).await;
+            let mmproj = match mmproj {
+                Some(path) => reattach_projector(&worker, path, final_gpu_layers > 0).await,
+                None => None,
+            };
+            (pinned.unwrap_or(was_pinned), loras, mmproj)
+        }
+        None => (pinned.unwrap_or(false), Vec::new(), None),
+    };
+
+    // Save model to state pool, checking the budget again with the final footprint.
+    // Inserting drops a replaced copy, its worker exits once its queue is empty.
+    let mut state_guard = lock_state(&state);
+    reservation.release(&mut state_guard);
+    state_guard.make_room(&app, footprint.total(), Some(&model_path))?;
+    state_guard.use_counter += 1;
+    let last_used = state_guard.use_counter;
     state_guard.models.insert(model_path.clone(), LoadedModel {
-        model,
+        worker,
         model_path: model_path.clone(),
         n_ctx,
         n_gpu_layers: final_gpu_layers,
+        n_vocab,
+        footprint: footprint.total(),
+        weights: footprint.weights,
+        hparams,
+        pinned,
+        last_used,
+        loras,
+        fim,
+        model,
+        mmproj,
+        load_ms,
+        kv_size,
     });
     
     info!("✅ Model saved to pool! Total models: {}", state_guard.models.len());
@@ -205,282 +729,35 @@ pub async fn load_gguf_model(
 
 #[tauri::command]
 pub async fn chat_with_gguf_model(
-    state: State<'_, Arc<Mutex<GgufState>>>,
+    app: AppHandle,
     model_path: String, // 🆕 Model path required
     prompt: String,
     max_tokens: u32,
     temperature: f32,
-) -> Result<String, String> {
-    info!("🔵 Starting inference...");
-    info!("📝 Prompt length: {} chars", prompt.len());
-    info!("⚙️ Max tokens: {}, Temperature: {}", max_tokens, temperature);
-
-    // 🔧 Mutex poisoned ise düzelt
-    let state_guard = match state.lock() {
-        Ok(guard) => guard,
-        Err(poisoned) => {
-            warn!("⚠️ Mutex was poisoned, recovering...");
-            poisoned.into_inner()
-        }
-    };
-    
-    // 🆕 Get model from pool
-    let loaded_model = state_guard.models.get(&model_path)
-        .ok_or_else(|| {
-            error!("❌ Model not found in pool: {}", model_path);
-            format!("Model havuzda bulunamadı: {}", model_path)
-        })?;
-
-    let backend = state_guard.backend.as_ref().unwrap();
-    let model = &loaded_model.model;
-    let n_ctx = loaded_model.n_ctx;
-
-    info!("📦 Using model from pool: {}", model_path);
-
-    // Create context with proper KV cache size
-    // KV cache should be at least n_ctx + max_tokens to avoid NoKvCacheSlot error
-    let kv_cache_size = (n_ctx + max_tokens).max(4096); // Minimum 4096
-    
-    info!("📊 KV Cache size: {}", kv_cache_size);
-    
-    let ctx_params = LlamaContextParams::default()
-        .with_n_ctx(std::num::NonZero::new(kv_cache_size)) // Use larger context for KV cache
-        .with_n_batch(8192); // Increase batch size to 8192 (was 512)
-
-    let mut context = model.new_context(backend, ctx_params)
-        .map_err(|e| {
-            error!("❌ Context creation failed: {:?}", e);
-            format!("Context creation failed: {:?}", e)
-        })?;
-
-    info!("✅ Context created with KV cache size: {}", kv_cache_size);
-
-    // Tokenize prompt with BOS token
-    info!("🔤 Tokenizing prompt with BOS token...");
-    let tokens = model.str_to_token(&prompt, AddBos::Always)
-        .map_err(|e| {
-            error!("❌ Tokenization failed: {:?}", e);
-            format!("Tokenization failed: {:?}", e)
-        })?;
-
-    info!("✅ Tokenized: {} tokens", tokens.len());
-    
-    // Log first few tokens for debugging
-    if tokens.len() > 0 {
-        info!("🔍 First 10 tokens: {:?}", &tokens[..tokens.len().min(10)]);
-    }
-    
-    // Check if prompt is too long
-    if tokens.len() > n_ctx as usize {
-        error!("❌ Prompt too long: {} tokens (max: {})", tokens.len(), n_ctx);
-        return Err(format!("Prompt too long: {} tokens (max: {})", tokens.len(), n_ctx));
-    }
-
-    // Create batch - MUST be at least as large as the number of prompt tokens
-    // But not too large (max 8192 for stability)
-    // If prompt is longer than 8192, we'll process it in chunks
-    let max_batch_size = 8192;
-    let batch_size = tokens.len().min(max_batch_size);
-    
-    info!("📦 Creating batch: prompt_tokens={}, batch_size={}, n_ctx={}", tokens.len(), batch_size, n_ctx);
-    
-    // Create batch outside if/else so it's available later
-    let mut batch = LlamaBatch::new(batch_size, 1);
-    
-    // Process prompt in chunks if necessary
-    if tokens.len() > max_batch_size {
-        info!("⚠️ Prompt too long for single batch, processing in chunks...");
-        
-        let mut processed = 0;
-        
-        while processed < tokens.len() {
-            batch.clear();
-            let chunk_size = (tokens.len() - processed).min(max_batch_size);
-            
-            for i in 0..chunk_size {
-                let token_idx = processed + i;
-                batch.add(
-                    tokens[token_idx], 
-                    token_idx as i32, 
-                    &[0], 
-                    token_idx == tokens.len() - 1
-                ).map_err(|e| format!("Batch add failed: {:?}", e))?;
-            }
-            
-            context.decode(&mut batch)
-                .map_err(|e| {
-                    error!("❌ Decode failed at chunk {}: {:?}", processed / max_batch_size, e);
-                    format!("Decode failed: {:?}", e)
-                })?;
-            
-            processed += chunk_size;
-            info!("📊 Processed {}/{} tokens", processed, tokens.len());
-        }
-        
-        info!("✅ All prompt chunks processed!");
-    } else {
-        // Single batch processing (normal case)
-        for (i, token) in tokens.iter().enumerate() {
-            batch.add(*token, i as i32, &[0], i == tokens.len() - 1)
-                .map_err(|e| format!("Batch add failed: {:?}", e))?;
-        }
-
-        // Initial decode (process prompt)
-        context.decode(&mut batch)
-            .map_err(|e| {
-                error!("❌ Decode failed: {:?}", e);
-                format!("Decode failed: {:?}", e)
-            })?;
-        
-        info!("✅ Prompt processed!");
-    }
-
-    // Token generation
-    let mut response_tokens = Vec::new();
-    let mut n_cur = batch.n_tokens();
-    
-    info!("🎲 Starting token generation...");
-
-    for i in 0..max_tokens {
-        // Get candidates
-        let candidates = context.candidates();
-        
-        // 🆕 Repetition Penalty ve Temperature Sampling
-        let candidates_vec: Vec<_> = candidates.into_iter().collect();
-        
-        // 🔄 Repetition Penalty Uygulama
-        let repeat_penalty = 1.15_f32;
-        let penalty_last_n = 64; 
-        
-        let mut recent_tokens = Vec::new();
-        let total_recent = tokens.len() + response_tokens.len();
-        let start_idx = if total_recent > penalty_last_n { total_recent - penalty_last_n } else { 0 };
-        
-        if tokens.len() > start_idx {
-            recent_tokens.extend_from_slice(&tokens[start_idx..]);
-            recent_tokens.extend_from_slice(&response_tokens);
-        } else {
-            let resp_start = start_idx - tokens.len();
-            recent_tokens.extend_from_slice(&response_tokens[resp_start..]);
-        }
-        
-        let adjusted_logits: Vec<(llama_cpp_2::token::LlamaToken, f32)> = candidates_vec.iter()
-            .map(|c| {
-                let id = c.id();
-                let mut logit = c.logit();
-                
-                if recent_tokens.contains(&id) {
-                    if logit <= 0.0 {
-                        logit *= repeat_penalty;
-                    } else {
-                        logit /= repeat_penalty;
-                    }
-                }
-                
-                (id, logit)
-            })
-            .collect();
-        
-        // Temperature-based sampling
-        let new_token_id = if temperature > 0.0 && temperature != 1.0 {
-            // Apply temperature scaling to logits
-            let scaled_logits: Vec<_> = adjusted_logits.iter()
-                .map(|(id, logit)| (*id, logit / temperature))
-                .collect();
-            
-            // Convert to probabilities using softmax
-            let max_logit = scaled_logits.iter()
-                .map(|(_, logit)| logit)
-                .fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-            
-            let exp_sum: f32 = scaled_logits.iter()
-                .map(|(_, logit)| (logit - max_logit).exp())
-                .sum();
-            
-            let probs: Vec<_> = scaled_logits.iter()
-                .map(|(id, logit)| (*id, (logit - max_logit).exp() / exp_sum))
-                .collect();
-            
-            // Sample from distribution
-            let mut rng = rand::thread_rng();
-            let random_val: f32 = rng.gen();
-            let mut cumulative = 0.0;
-            
-            let mut selected_id = probs[0].0;
-            for (id, prob) in probs.iter() {
-                cumulative += prob;
-                if random_val <= cumulative {
-                    selected_id = *id;
-                    break;
-                }
-            }
-            selected_id
-        } else {
-            // No temperature, just pick highest probability
-            adjusted_logits.into_iter()
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
-                .map(|(id, _)| id)
-                .unwrap_or(candidates_vec[0].id())
-        };
-
-        // Check for EOS (End of Sequence)
-        if model.is_eog_token(new_token_id) {
-            info!("✅ EOS token found at position {}, stopping", i);
-            break;
-        }
-
-        response_tokens.push(new_token_id);
-        
-        // Log first few tokens to debug
-        if i < 5 {
-            info!("🔤 Token {}: id={:?}", i, new_token_id);
-        }
+    options: Option<GgufRequestOptions>, // 🆕 sampling, grammar, stop, speculative, öncelik, LoRA, logprobs, context shift
+) -> Result<GgufChatResponse, String> {
+    let model_path = pool_key(&model_path);
+    let mut options = options.unwrap_or_default();
+    let generation_id = options.generation_id.take();
+    let mut params = options.into_params(max_tokens, temperature)?;
 
-        // Log every 50 tokens
-        if i % 50 == 0 && i > 0 {
-            info!("📊 Generated {}/{} tokens", i, max_tokens);
-        }
-
-        // Create new batch
-        batch.clear();
-        batch.add(new_token_id, n_cur, &[0], true)
-            .map_err(|e| format!("Batch add failed: {:?}", e))?;
+    let generation = cancellation::register_generation(generation_id);
+    cancellation::emit_generation_started(&app, &generation.id);
+    params.cancel = Some(generation.token.clone());
 
-        // Decode
-        context.decode(&mut batch)
-            .map_err(|e| format!("Decode failed at token {}: {:?}", i, e))?;
+    let state = app.state::<Arc<Mutex<GgufState>>>().inner().clone();
+    let output = tokio::task::spawn_blocking(move || {
+        run_gguf_chat(&state, &model_path, GgufPrompt::Raw(prompt), &params, &mut |_| {})
+    })
+    .await
+    .map_err(|e| format!("Inference task failed: {}", e))??;
 
-        n_cur += 1;
+    if output.cancelled {
+        cancellation::emit_generation_cancelled(&app, &generation.id, &output.text);
     }
 
-    let total_tokens = response_tokens.len();
-    info!("✅ Token generation completed: {} tokens", total_tokens);
-    
-    // 🆕 Token'ları tek tek decode et ve birleştir
-    info!("🔤 Decoding tokens...");
-    let mut response = String::new();
-    let mut decode_errors = 0;
-    
-    let mut decoder = encoding_rs::UTF_8.new_decoder();
-    for (idx, token_id) in response_tokens.iter().enumerate() {
-        // Use modern token_to_piece with 4 arguments as required by llama-cpp-2 v0.1.133
-        match model.token_to_piece(*token_id, &mut decoder, false, None) {
-            Ok(token_str) => {
-                response.push_str(&token_str);
-            },
-            Err(e) => {
-                decode_errors += 1;
-                if decode_errors <= 10 {
-                    info!("⏭️ Token {}: decode failed: {:?}", idx, e);
-                }
-            }
-        }
-    }
-    
-    info!("✅ Decoded: {} characters from {} tokens ({} decode errors)", response.len(), total_tokens, decode_errors);
-    
     // Clean up response (remove special tokens if any)
-    let cleaned_response = response
+    let cleaned_response = output.text
         .replace("<|im_start|>", "")
         .replace("<|im_end|>", "")
         .replace("<|endoftext|>", "")
@@ -496,162 +773,1275 @@ pub async fn chat_with_gguf_model(
         info!("📤 Response preview: {}", &cleaned_response[..preview_len]);
     }
 
-    Ok(cleaned_response)
+    Ok(GgufChatResponse::new(cleaned_response, output))
 }
 
-#[tauri::command]
-pub async fn unload_gguf_model(
-    state: State<'_, Arc<Mutex<GgufState>>>,
-) -> Result<String, String> {
-    info!("🔵 Unloading GGUF model - Starting cleanup...");
-    
-    let mut state_guard = state.lock().unwrap();
-    
-    state_guard.models.clear();
-    state_guard.backend = None;
-    state_guard.backend_initialized = false;
-    
-    // Clear state
-    // Clear state done above
-    
-    // Force garbage collection hint (Rust will handle it)
+/// Structured result of a GGUF chat
+#[derive(Debug, Clone, Serialize)]
+pub struct GgufChatResponse {
+    pub content: String,
+    pub prompt_tokens: usize,
+    /// Prompt tokens reused from the previous request's KV cache
+    pub cached_prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub cancelled: bool,
+    /// Stop string that ended the reply, if any
+    pub stop_sequence: Option<String>,
+    /// Draft acceptance statistics when speculative decoding was used
+    pub speculative: Option<SpeculativeStats>,
+    /// Per-token log-probabilities of the reply when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    /// Set when the sliding window had to move to fit the conversation and reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_shift: Option<ContextShiftReport>,
+    /// Load time, prompt evaluation and generation speed, time to first token, stop reason
+    pub metrics: GenerationMetrics,
+}
+
+impl GgufChatResponse {
+    fn new(content: String, output: GenerationOutput) -> Self {
+        let context_shift = output.context_shift();
+        Self {
+            content,
+            prompt_tokens: output.prompt_tokens,
+            cached_prompt_tokens: output.reused_tokens,
+            completion_tokens: output.tokens.len(),
+            cancelled: output.cancelled,
+            stop_sequence: output.stop_sequence,
+            speculative: output.speculative,
+            context_shift,
+            metrics: output.metrics,
+            logprobs: output.logprobs,
+        }
+    }
+}
+
+/// Report a generation's timings to the frontend, for commands that only return text
+pub(crate) fn emit_metrics(app: &AppHandle, generation_id: &str, metrics: &GenerationMetrics) {
+    let payload = json!({ "generation_id": generation_id, "metrics": metrics });
+    if let Err(e) = app.emit("gguf-metrics", payload) {
+        warn!("⚠️ Failed to emit gguf-metrics: {}", e);
+    }
+}
+
+/// Tell the frontend that the sliding window moved during a generation
+pub(crate) fn emit_context_shift(app: &AppHandle, generation_id: &str, output: &GenerationOutput) {
+    if let Some(report) = output.context_shift() {
+        let payload = json!({ "generation_id": generation_id, "context_shift": report });
+        if let Err(e) = app.emit("gguf-context-shift", payload) {
+            warn!("⚠️ Failed to emit gguf-context-shift: {}", e);
+        }
+    }
+}
+
+/// 🆕 Multi-turn chat - mesajlar modelin kendi chat template'i ile render edilir
+#[tauri::command]
+pub async fn chat_with_gguf_messages(
+    app: AppHandle,
+    model_path: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    temperature: f32,
+    options: Option<GgufRequestOptions>,
+) -> Result<GgufChatResponse, String> {
+    info!("💬 GGUF chat with {} messages", messages.len());
+    let model_path = pool_key(&model_path);
+    let mut options = options.unwrap_or_default();
+    let generation_id = options.generation_id.take();
+    let mut params = options.into_params(max_tokens, temperature)?;
+
+    let generation = cancellation::register_generation(generation_id);
+    cancellation::emit_generation_started(&app, &generation.id);
+    params.cancel = Some(generation.token.clone());
+
+    let state = app.state::<Arc<Mutex<GgufState>>>().inner().clone();
+    let output = tokio::task::spawn_blocking(move || {
+        let messages = fit_messages_to_context(&state, &model_path, messages);
+        run_gguf_chat(&state, &model_path, GgufPrompt::Chat(messages), &params, &mut |_| {})
+    })
+    .await
+    .map_err(|e| format!("Inference task failed: {}", e))??;
+
+    if output.cancelled {
+        cancellation::emit_generation_cancelled(&app, &generation.id, &output.text);
+    }
+
+    Ok(GgufChatResponse::new(output.text.trim().to_string(), output))
+}
+
+/// Tokens generated per benchmark prompt unless the request says otherwise
+const BENCHMARK_MAX_TOKENS: u32 = 128;
+
+/// One prompt run of `benchmark_gguf_model`
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkRun {
+    pub name: String,
+    pub repetition: u32,
+    pub metrics: GenerationMetrics,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkReport {
+    pub model_path: String,
+    pub n_ctx: u32,
+    pub n_gpu_layers: u32,
+    pub load_ms: f64,
+    pub max_tokens: u32,
+    pub runs: Vec<BenchmarkRun>,
+    pub summary: BenchmarkSummary,
+    pub cancelled: bool,
+}
+
+/// ⏱️ Run the standard prompt set on a pooled model and report its speed. Every run
+/// decodes greedily from an empty KV cache, so results compare across quantizations and
+/// GPU layer settings. Each finished run is emitted as `gguf-benchmark-progress`.
+#[tauri::command]
+pub async fn benchmark_gguf_model(
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    max_tokens: Option<u32>,
+    repetitions: Option<u32>, // Her prompt kaç kez çalıştırılsın (1-10)
+    generation_id: Option<String>, // cancel_generation ile kalan çalıştırmalar durdurulur
+) -> Result<BenchmarkReport, String> {
+    let model_path = pool_key(&model_path);
+    let max_tokens = max_tokens.unwrap_or(BENCHMARK_MAX_TOKENS);
+    let repetitions = repetitions.unwrap_or(1).clamp(1, 10);
+    let (n_ctx, n_gpu_layers, load_ms) = {
+        let state_guard = lock_state(&state);
+        let loaded_model = state_guard.models.get(&model_path)
+            .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+        (loaded_model.n_ctx, loaded_model.n_gpu_layers, loaded_model.load_ms)
+    };
+
+    let generation = cancellation::register_generation(generation_id);
+    cancellation::emit_generation_started(&app, &generation.id);
+
+    let prompts = benchmark_prompts();
+    let total = prompts.len() * repetitions as usize;
+    info!("⏱️ Benchmark: {} ({} runs, max {} tokens)", model_path, total, max_tokens);
+
+    let state = state.inner().clone();
+    let mut runs: Vec<BenchmarkRun> = Vec::new();
+    let mut cancelled = false;
+    'runs: for repetition in 1..=repetitions {
+        for (name, prompt) in &prompts {
+            let params = GenerationParams {
+                max_tokens,
+                temperature: 0.0,
+                sampling: SamplingParams::default(),
+                grammar: None,
+                stop: Vec::new(),
+                speculative: None,
+                schedule: ScheduleOptions::default(),
+                loras: None,
+                cancel: Some(generation.token.clone()),
+                logprobs: None,
+                context_shift: None,
+                // A cached prefix would make prompt evaluation look faster than it is
+                cold_start: true,
+            };
+            let messages = vec![ChatMessage { role: "user".to_string(), content: prompt.clone() }];
+            let (state, model_path) = (state.clone(), model_path.clone());
+            let output = tokio::task::spawn_blocking(move || {
+                run_gguf_chat(&state, &model_path, GgufPrompt::Chat(messages), &params, &mut |_| {})
+            })
+            .await
+            .map_err(|e| format!("Benchmark task failed: {}", e))??;
+
+            if output.cancelled {
+                cancelled = true;
+                break 'runs;
+            }
+            let run = BenchmarkRun { name: name.to_string(), repetition, metrics: output.metrics };
+            let payload = json!({ "generation_id": generation.id, "done": runs.len() + 1, "total": total, "run": run });
+            if let Err(e) = app.emit("gguf-benchmark-progress", payload) {
+                warn!("⚠️ Failed to emit gguf-benchmark-progress: {}", e);
+            }
+            runs.push(run);
+        }
+    }
+    if cancelled {
+        cancellation::emit_generation_cancelled(&app, &generation.id, "");
+    }
+
+    let summary = summarize(&runs.iter().map(|run| run.metrics).collect::<Vec<_>>());
+    info!(
+        "✅ Benchmark: prompt {:.1} t/s, generation {:.1} t/s, TTFT {:.0} ms over {} runs",
+        summary.prompt_tokens_per_second, summary.generation_tokens_per_second, summary.time_to_first_token_ms, summary.runs
+    );
+    Ok(BenchmarkReport {
+        model_path,
+        n_ctx,
+        n_gpu_layers,
+        load_ms,
+        max_tokens,
+        runs,
+        summary,
+        cancelled,
+    })
+}
+
+/// Default completion length for `complete_fim`, ghost text is short
+const FIM_MAX_TOKENS: u32 = 128;
+/// Default temperature for `complete_fim`, low to keep completions predictable
+const FIM_TEMPERATURE: f32 = 0.2;
+/// Prompt tokens a FIM template adds around the code: BOS and the prefix, suffix and middle markers
+const FIM_TEMPLATE_TOKENS: usize = 8;
+
+/// 🧩 Fill-in-the-middle completion for inline ghost text. Runs on a pooled GGUF model,
+/// or on an HTTP provider's `/v1/completions` when `base_url` is set (`model_path` is then
+/// the provider's model name). The completion holds only the text that goes between `prefix` and `suffix`.
+#[tauri::command]
+pub async fn complete_fim(
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    prefix: String,
+    suffix: String,
+    model_path: String,
+    options: Option<FimOptions>, // 🆕 base_url ile LM Studio / Ollama / llama.cpp server, max_tokens, temperature, öncelik
+) -> Result<FimCompletion, String> {
+    let FimOptions { base_url, max_tokens, temperature, generation_id, schedule } = options.unwrap_or_default();
+    let max_tokens = max_tokens.unwrap_or(FIM_MAX_TOKENS);
+    let temperature = temperature.unwrap_or(FIM_TEMPERATURE);
+    let scope = FimScope::detect(&suffix);
+
+    let generation = cancellation::register_generation(generation_id);
+    cancellation::emit_generation_started(&app, &generation.id);
+
+    let completion = match base_url {
+        Some(base_url) => {
+            let request = complete_fim_http(&base_url, &prefix, &suffix, &model_path, max_tokens, temperature, scope);
+            tokio::select! {
+                middle = request => FimCompletion { content: middle?, ..Default::default() },
+                _ = generation.token.cancelled() => {
+                    cancellation::emit_generation_cancelled(&app, &generation.id, "");
+                    return Ok(FimCompletion { cancelled: true, ..Default::default() });
+                }
+            }
+        }
+        None => {
+            let model_path = pool_key(&model_path);
+            let (template, n_ctx) = {
+                let state_guard = lock_state(&state);
+                let loaded_model = state_guard.models.get(&model_path)
+                    .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+                let template = loaded_model.fim
+                    .ok_or_else(|| format!("Model fill-in-the-middle desteklemiyor: {}", model_path))?;
+                (template, loaded_model.n_ctx)
+            };
+
+            // Measured with the model's own tokenizer, leaving room for the completion
+            let budget = (n_ctx as usize).saturating_sub(max_tokens as usize + FIM_TEMPLATE_TOKENS);
+            if budget == 0 {
+                return Err(format!("FIM için context'te yer kalmadı: n_ctx={}, max_tokens={}", n_ctx, max_tokens));
+            }
+            let counter = gguf_token_counter(&state, &model_path)
+                .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+            let (fim_prefix, fim_suffix) = trim_context(&prefix, &suffix, budget, &*counter);
+            let prompt = GgufPrompt::Fim { prefix: fim_prefix.to_string(), suffix: fim_suffix.to_string(), template };
+
+            let params = GenerationParams {
+                max_tokens,
+                temperature,
+                sampling: SamplingParams::default(),
+                grammar: None,
+                stop: fim_stop_strings(template.family, scope),
+                speculative: None,
+                schedule: schedule.unwrap_or(ScheduleOptions { priority: RequestPriority::Interactive, supersede_key: None }),
+                loras: None,
+                cancel: Some(generation.token.clone()),
+                logprobs: None,
+                context_shift: None,
+                cold_start: false,
+            };
+            let state = state.inner().clone();
+            let output = tokio::task::spawn_blocking(move || {
+                run_gguf_chat(&state, &model_path, prompt, &params, &mut |_| {})
+            })
+            .await
+            .map_err(|e| format!("Inference task failed: {}", e))??;
+
+            if output.cancelled {
+                cancellation::emit_generation_cancelled(&app, &generation.id, &output.text);
+            }
+            FimCompletion { content: output.text, cancelled: output.cancelled, metrics: Some(output.metrics) }
+        }
+    };
+
+    let content = clean_middle(&prefix, &completion.content, &suffix);
+    info!("🧩 FIM completion: {} chars ({:?})", content.len(), scope);
+    Ok(FimCompletion { content, ..completion })
+}
+
+/// FIM through an OpenAI-style `/v1/completions` endpoint. Known families get the rendered
+/// prompt; for other models the server applies its own FIM template through `suffix`.
+async fn complete_fim_http(
+    base_url: &str,
+    prefix: &str,
+    suffix: &str,
+    model: &str,
+    max_tokens: u32,
+    temperature: f32,
+    scope: FimScope,
+) -> Result<String, String> {
+    let family = FimFamily::detect(model);
+    let stop = fim_stop_strings(family, scope);
+    let mut body = json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "temperature": temperature,
+        "stop": stop,
+        "stream": false,
+    });
+    match family {
+        Some(family) => body["prompt"] = json!(family.render(prefix, suffix)),
+        None => {
+            body["prompt"] = json!(prefix);
+            body["suffix"] = json!(suffix);
+        }
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/completions", base_url.trim_end_matches('/')))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP error: {}", response.status()));
+    }
+    let json: serde_json::Value = response.json().await
+        .map_err(|e| format!("Geçersiz yanıt: {}", e))?;
+    let text = json["choices"][0]["text"].as_str().unwrap_or_default();
+
+    // Also enforced here, for servers that ignore `stop`
+    Ok(crate::stop_sequences::truncate_at_stop(text, &stop).to_string())
+}
+
+/// Prompt input for `run_gguf_chat`
+pub(crate) enum GgufPrompt {
+    /// Already formatted text, tokenized as-is
+    Raw(String),
+    /// Conversation rendered with the model's chat template
+    Chat(Vec<ChatMessage>),
+    /// Code before and after the cursor, the model generates what goes in between
+    Fim { prefix: String, suffix: String, template: FimTemplate },
+}
+
+/// Lock the pool, recovering from a poisoned mutex
+pub(crate) fn lock_state(state: &Mutex<GgufState>) -> std::sync::MutexGuard<'_, GgufState> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => {
+            warn!("⚠️ Mutex was poisoned, recovering...");
+            poisoned.into_inner()
+        }
+    }
+}
+
+/// Worker and context size of a pooled model, marking it as used
+fn pooled_worker(state: &Mutex<GgufState>, model_path: &str) -> Result<(Worker<ModelSlot>, u32), String> {
+    let mut state_guard = lock_state(state);
+    state_guard.touch(model_path);
+    let loaded_model = state_guard.models.get(model_path)
+        .ok_or_else(|| {
+            error!("❌ Model not found in pool: {}", model_path);
+            format!("Model havuzda bulunamadı: {}", model_path)
+        })?;
+    Ok((loaded_model.worker.clone(), loaded_model.n_ctx))
+}
+
+/// Token counter backed by a pooled model's vocabulary, None when the model is not loaded
+pub(crate) fn gguf_token_counter(state: &Mutex<GgufState>, model_path: &str) -> Option<TokenCounter> {
+    let model = lock_state(state).models.get(model_path)?.model.clone();
+    Some(Arc::new(move |text: &str| {
+        model.str_to_token(text, AddBos::Never)
+            .map(|tokens| tokens.len())
+            .unwrap_or_else(|_| RAGPipeline::estimate_tokens(text))
+    }))
+}
+
+/// ✂️ Drop the oldest turns that no longer fit the model's context. Counted per message,
+/// the rendered prompt is checked exactly before it is queued.
+fn fit_messages_to_context(state: &Mutex<GgufState>, model_path: &str, messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let Some(counter) = gguf_token_counter(state, model_path) else {
+        return messages;
+    };
+    let n_ctx = lock_state(state).models.get(model_path).map(|m| m.n_ctx).unwrap_or(0) as usize;
+    let fit = fit_history(&messages, n_ctx, &*counter);
+    if fit.dropped > 0 {
+        info!("✂️ {} old message(s) dropped to fit the {} token context", fit.dropped, n_ctx);
+    }
+    fit.messages
+}
+
+/// Load a replaced model's adapters onto its reloaded copy; ones that no longer fit are dropped
+async fn reattach_loras(worker: &Worker<ModelSlot>, loras: Vec<LoraInfo>) -> Vec<LoraInfo> {
+    let mut attached = Vec::new();
+    for lora in loras {
+        let (name, path) = (lora.name.clone(), lora.path.clone());
+        match call_worker(worker.clone(), move |slot| slot.init_lora(name, &path)).await {
+            Ok(()) => attached.push(lora),
+            Err(e) => warn!("⚠️ LoRA yeniden yüklenemedi ({}): {}", lora.name, e),
+        }
+    }
+    attached
+}
+
+/// Build a replaced model's projector on its reloaded copy, None when that fails
+async fn reattach_projector(worker: &Worker<ModelSlot>, mmproj_path: String, use_gpu: bool) -> Option<String> {
+    let job_path = mmproj_path.clone();
+    match call_worker(worker.clone(), move |slot| slot.init_projector(&job_path, use_gpu)).await {
+        Ok(()) => Some(mmproj_path),
+        Err(e) => {
+            warn!("⚠️ mmproj yeniden yüklenemedi ({}): {}", mmproj_path, e);
+            None
+        }
+    }
+}
+
+/// Run `f` on a model's worker without blocking the async runtime
+async fn call_worker<R: Send + 'static>(
+    worker: Worker<ModelSlot>,
+    f: impl FnOnce(&mut ModelSlot) -> Result<R, String> + Send + 'static,
+) -> Result<R, String> {
+    tokio::task::spawn_blocking(move || worker.call(f))
+        .await
+        .map_err(|e| format!("Worker task failed: {}", e))??
+}
+
+/// Run a prompt through a pooled model, handing every decoded piece to `on_piece`
+/// as it is generated. The returned text is raw and uncleaned; on cancellation it
+/// holds whatever was produced before the token was set.
+///
+/// Decoding happens on the model's worker thread: requests for the same model
+/// queue up, other models run in parallel and the pool lock is only held for the
+/// lookup. Blocks the calling thread until the request is done.
+pub(crate) fn run_gguf_chat(
+    state: &Arc<Mutex<GgufState>>,
+    model_path: &str,
+    prompt: GgufPrompt,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let request_started = Instant::now();
+    let max_tokens = params.max_tokens;
+    info!("🔵 Starting inference...");
+    info!("⚙️ Max tokens: {}, Temperature: {}", max_tokens, params.temperature);
+    info!("🎛️ Sampling: {:?}", params.sampling);
+
+    // 🆕 Get model from pool
+    let (worker, n_ctx) = pooled_worker(state, model_path)?;
+    let (loras, tokenizer) = {
+        let state_guard = lock_state(state);
+        let loaded_model = state_guard.models.get(model_path)
+            .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+        (resolve_loras(&loaded_model.loras, params.loras.as_deref())?, loaded_model.model.clone())
+    };
+
+    // Tokenize here so an oversized prompt fails before it waits in the queue
+    let mut tokens = tokenize_prompt(&tokenizer, prompt)?;
+    info!("✅ Tokenized: {} tokens", tokens.len());
+    let mut truncated_prompt_tokens = 0;
+    if tokens.len() > n_ctx as usize {
+        let Some(shift) = params.context_shift else {
+            error!("❌ Prompt too long: {} tokens (max: {})", tokens.len(), n_ctx);
+            return Err(format!("Prompt too long: {} tokens (max: {})", tokens.len(), n_ctx));
+        };
+        // Sliding window: keep the start, cut the oldest middle, leave room for the reply
+        let keep = shift.keep as usize + usize::from(tokens.first() == Some(&tokenizer.token_bos()));
+        let reserve = (max_tokens as usize).min(n_ctx as usize / 4);
+        let (kept, cut) = truncate_prompt(&tokens, n_ctx as usize, keep, reserve);
+        warn!("✂️ Prompt too long: {} tokens, {} cut from the middle to fit n_ctx={}", tokens.len(), cut, n_ctx);
+        tokens = kept;
+        truncated_prompt_tokens = cut;
+    }
+    let draft_worker = match &params.speculative {
+        // Drafted tokens are verified without the full distribution, so logprobs need plain decoding
+        Some(_) if params.logprobs.is_some() => {
+            info!("ℹ️ Logprobs requested, speculative decoding skipped");
+            None
+        }
+        // The draft context cannot follow a shifted KV cache
+        Some(_) if params.context_shift.is_some() => {
+            info!("ℹ️ Context shift requested, speculative decoding skipped");
+            None
+        }
+        Some(speculative) => Some(speculative_draft(state, model_path, speculative)?),
+        None => None,
+    };
+    info!("📦 Using model from pool: {}", model_path);
+
+    let status = worker.status();
+    if status.busy || status.queued > 0 {
+        info!("⏳ Model busy ({} queued), scheduling as {:?}", status.queued, params.schedule.priority);
+    }
+
+    // KV cache should be at least n_ctx + max_tokens to avoid NoKvCacheSlot error;
+    // with a sliding window it stays at n_ctx and is shifted once full
+    let kv_cache_size = match params.context_shift {
+        Some(_) => n_ctx,
+        None => (n_ctx + max_tokens).max(4096), // Minimum 4096
+    };
+    info!("📊 KV Cache size: {}", kv_cache_size);
+
+    // Borrow the draft's context from its own worker; done here, on the caller's
+    // thread, so two workers never wait on each other
+    let lease = match draft_worker {
+        // The draft always runs as its plain base model
+        Some(draft_worker) => {
+            let lent = draft_worker.call(move |slot| {
+                let cache = slot.prepare_context(kv_cache_size, &[])?;
+                Ok::<_, String>(LentContext { cache, model: slot.model.clone() })
+            })??;
+            Some(DraftLease { worker: draft_worker, lent: Some(lent) })
+        }
+        None => None,
+    };
+
+    let job_params = params.clone();
+    let submitted = Instant::now();
+    let queued = worker.call_streaming(
+        &params.schedule,
+        params.cancel.clone(),
+        move |slot, emit: &mut dyn FnMut(String)| {
+            let started = Instant::now();
+            let mut lease = lease;
+            let draft = lease.as_mut().and_then(|lease| lease.lent.as_mut());
+            let result = run_on_slot(slot, kv_cache_size, &tokens, &job_params, &loras, draft, &mut |piece| {
+                emit(piece.to_string())
+            });
+            (result, started)
+        },
+        &mut |piece: String| on_piece(&piece),
+    );
+
+    let (result, started) = match queued {
+        Ok(done) => done,
+        // Superseded or cancelled before it got to run: nothing was generated
+        Err(_) if params.cancel.as_ref().map(|c| c.is_cancelled()).unwrap_or(false) => {
+            return Ok(dropped_from_queue(request_started, submitted));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let load_ms = lock_state(state).models.get(model_path).map(|m| m.load_ms).unwrap_or(0.0);
+    result.map(|mut output| {
+        output.truncated_prompt_tokens = truncated_prompt_tokens;
+        record_timings(&mut output, load_ms, request_started, submitted, started);
+        output
+    })
+}
+
+/// Result of a request that was superseded or cancelled before it got to run
+fn dropped_from_queue(request_started: Instant, submitted: Instant) -> GenerationOutput {
+    info!("🛑 Request dropped from the queue before it started");
+    let mut output = GenerationOutput { cancelled: true, ..Default::default() };
+    output.metrics.stop_reason = StopReason::Cancelled;
+    output.metrics.queue_ms = elapsed_ms(submitted);
+    output.metrics.total_ms = elapsed_ms(request_started);
+    output
+}
+
+/// Fill in the request-level timings of a finished run: `submitted` is when it was
+/// queued, `started` when the worker picked it up
+fn record_timings(output: &mut GenerationOutput, load_ms: f64, request_started: Instant, submitted: Instant, started: Instant) {
+    let metrics = &mut output.metrics;
+    metrics.load_ms = load_ms;
+    metrics.queue_ms = started.duration_since(submitted).as_secs_f64() * 1000.0;
+    metrics.time_to_first_token_ms = output.first_token_at
+        .map(|at| at.duration_since(request_started).as_secs_f64() * 1000.0);
+    metrics.total_ms = elapsed_ms(request_started);
+    metrics.finish();
+    info!(
+        "⏱️ Prompt: {} tokens @ {:.1} t/s, generation: {} tokens @ {:.1} t/s, TTFT {:.0} ms, {:?}",
+        metrics.prompt_eval_tokens,
+        metrics.prompt_tokens_per_second,
+        metrics.generated_tokens,
+        metrics.generation_tokens_per_second,
+        metrics.time_to_first_token_ms.unwrap_or(0.0),
+        metrics.stop_reason
+    );
+}
+
+/// Validate a speculative request's draft model and return its worker
+fn speculative_draft(
+    state: &Mutex<GgufState>,
+    model_path: &str,
+    speculative: &SpeculativeConfig,
+) -> Result<Worker<ModelSlot>, String> {
+    let draft_path = pool_key(&speculative.draft_model_path);
+    if draft_path == model_path {
+        return Err("Draft model hedef modelden farklı olmalı".to_string());
+    }
+
+    let mut state_guard = lock_state(state);
+    state_guard.touch(&draft_path);
+    let target = state_guard.models.get(model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+    let draft = state_guard.models.get(&draft_path)
+        .ok_or_else(|| format!("Draft model havuzda bulunamadı: {}", draft_path))?;
+    if target.n_vocab != draft.n_vocab {
+        return Err(format!(
+            "Draft model aynı vocabulary'yi paylaşmalı (hedef: {}, draft: {})",
+            target.n_vocab,
+            draft.n_vocab
+        ));
+    }
+    Ok(draft.worker.clone())
+}
+
+/// Worker side of `run_gguf_chat`
+fn run_on_slot(
+    slot: &mut ModelSlot,
+    kv_cache_size: u32,
+    tokens: &[LlamaToken],
+    params: &GenerationParams,
+    loras: &[ActiveLora],
+    draft: Option<&mut LentContext>,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    if let (Some(_), Some(draft)) = (&params.speculative, draft) {
+        return run_speculative(slot, draft, tokens, kv_cache_size, loras, params, on_piece);
+    }
+
+    // ♻️ Reuse the longest prefix shared with the previous request, drop the rest of the KV cache
+    let mut cache = slot.prepare_context(kv_cache_size, loras)?;
+    let reused = cache.reuse_prefix(tokens, params.cold_start);
+
+    finish_with_cache(slot, cache, tokens, reused, params, on_piece)
+}
+
+/// Turn a prompt into tokens, rendering chat messages and FIM prompts first
+fn tokenize_prompt(model: &LlamaModel, prompt: GgufPrompt) -> Result<Vec<LlamaToken>, String> {
+    let (prompt, add_bos) = match prompt {
+        GgufPrompt::Raw(text) => (text, AddBos::Always),
+        GgufPrompt::Chat(messages) => (render_chat_prompt(model, &messages)?, chat_add_bos(model)),
+        GgufPrompt::Fim { prefix, suffix, template } => match (template.token_ids, template.family) {
+            (Some(ids), _) => return fim_tokens(model, &prefix, &suffix, ids),
+            (None, Some(family)) => (family.render(&prefix, &suffix), chat_add_bos(model)),
+            (None, None) => return Err("Model FIM formatı bilinmiyor".to_string()),
+        },
+    };
+    info!("📝 Prompt length: {} chars", prompt.len());
+
+    // Tokenize prompt with BOS token
+    info!("🔤 Tokenizing prompt...");
+    model.str_to_token(&prompt, add_bos)
+        .map_err(|e| {
+            error!("❌ Tokenization failed: {:?}", e);
+            format!("Tokenization failed: {:?}", e)
+        })
+}
+
+/// `<fim_pre> prefix <fim_suf> suffix <fim_mid>` with the special tokens inserted by ID,
+/// which also works for models whose marker text is unknown
+fn fim_tokens(model: &LlamaModel, prefix: &str, suffix: &str, ids: FimTokenIds) -> Result<Vec<LlamaToken>, String> {
+    let tokenize = |text: &str| model.str_to_token(text, AddBos::Never)
+        .map_err(|e| format!("Tokenization failed: {:?}", e));
+
+    let mut tokens = Vec::new();
+    if matches!(chat_add_bos(model), AddBos::Always) {
+        tokens.push(model.token_bos());
+    }
+    tokens.push(LlamaToken::new(ids.prefix));
+    tokens.extend(tokenize(prefix)?);
+    tokens.push(LlamaToken::new(ids.suffix));
+    tokens.extend(tokenize(suffix)?);
+    tokens.push(LlamaToken::new(ids.middle));
+    Ok(tokens)
+}
+
+/// 🏎️ Greedy generation verified against a draft model's lent context, as `params.speculative` says
+fn run_speculative(
+    slot: &mut ModelSlot,
+    draft: &mut LentContext,
+    tokens: &[LlamaToken],
+    kv_cache_size: u32,
+    loras: &[ActiveLora],
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let speculative = params.speculative.as_ref()
+        .ok_or_else(|| "Speculative decoding ayarları eksik".to_string())?;
+    let mut target_cache = slot.prepare_context(kv_cache_size, loras)?;
+    let target_reused = target_cache.reuse_prefix(tokens, params.cold_start);
+    let draft_reused = draft.cache.reuse_prefix(tokens, params.cold_start);
+
+    let result = generate_speculative(
+        SpeculativeModel { model: &slot.model, context: &mut target_cache.context, reused: target_reused },
+        SpeculativeModel { model: &draft.model, context: &mut draft.cache.context, reused: draft_reused },
+        tokens,
+        speculative,
+        params,
+        on_piece,
+    );
+
+    match &result {
+        Ok((output, draft_len)) => {
+            let mut sequence = tokens.to_vec();
+            sequence.extend_from_slice(&output.tokens);
+            target_cache.store(Some(&sequence));
+            draft.cache.store(Some(&sequence[..*draft_len]));
+        }
+        Err(_) => {
+            target_cache.store(None);
+            draft.cache.store(None);
+        }
+    }
+    slot.put_context(target_cache);
+
+    result.map(|(output, _)| output)
+}
+
+/// Run generation on `cache` and put it back into the model slot. The token list
+/// is only kept when the run succeeded, a failed decode leaves the KV cache unknown.
+fn finish_with_cache(
+    slot: &mut ModelSlot,
+    mut cache: CachedContext,
+    tokens: &[LlamaToken],
+    reused: usize,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let result = generate(&slot.model, &mut cache.context, tokens, reused, params, on_piece);
+
+    match &result {
+        Ok(output) => {
+            let mut sequence = tokens.to_vec();
+            sequence.extend_from_slice(&output.tokens);
+            apply_shifts(&mut sequence, &output.shifts);
+            cache.store(Some(&sequence));
+        }
+        Err(_) => cache.store(None),
+    }
+    slot.put_context(cache);
+
+    result
+}
+
+/// 🧩 Embed `text` with a pooled GGUF embedding model, entirely in-process.
+/// Input longer than the model's training context is truncated.
+pub(crate) fn embed_with_gguf(
+    state: &Arc<Mutex<GgufState>>,
+    options: &GgufEmbeddingOptions,
+    text: &str,
+) -> Result<Vec<f32>, String> {
+    let (worker, n_ctx) = pooled_worker(state, &options.model_path)
+        .map_err(|_| format!("Embedding modeli havuzda bulunamadı: {}", options.model_path))?;
+    let options = options.clone();
+    let text = text.to_string();
+    worker.call(move |slot| embed_on_slot(slot, n_ctx, &options, &text))?
+}
+
+/// Worker side of `embed_with_gguf`, on the slot's embedding context
+fn embed_on_slot(
+    slot: &mut ModelSlot,
+    n_ctx: u32,
+    options: &GgufEmbeddingOptions,
+    text: &str,
+) -> Result<Vec<f32>, String> {
+    let model = &slot.model;
+
+    let mut tokens = model.str_to_token(text, AddBos::Always)
+        .map_err(|e| format!("Tokenization failed: {:?}", e))?;
+    if tokens.is_empty() {
+        return Err("Embedding için metin boş".to_string());
+    }
+
+    let max_tokens = model.n_ctx_train().min(n_ctx.max(512)) as usize;
+    if tokens.len() > max_tokens {
+        warn!("⚠️ Embedding input truncated: {} -> {} tokens", tokens.len(), max_tokens);
+        tokens.truncate(max_tokens);
+    }
+
+    let context = &mut slot.embedding_context(tokens.len() as u32, options.pooling)?.context;
+    // Each text is embedded on its own
+    context.clear_kv_cache();
+
+    let mut batch = LlamaBatch::new(tokens.len(), 1);
+    batch.add_sequence(&tokens, 0, false)
+        .map_err(|e| format!("Batch add failed: {:?}", e))?;
+    context.decode(&mut batch)
+        .map_err(|e| format!("Embedding decode failed: {:?}", e))?;
+
+    let mut embedding = context.embeddings_seq_ith(0)
+        .map_err(|e| format!("Embedding okunamadı (model pooling desteklemiyor olabilir): {:?}", e))?
+        .to_vec();
+
+    if options.normalize {
+        l2_normalize(&mut embedding);
+    }
+
+    info!("✅ GGUF embedding: {} tokens -> {} dims", tokens.len(), embedding.len());
+    Ok(embedding)
+}
+
+/// Render messages with the model's own `tokenizer.chat_template` through llama.cpp.
+/// Templates llama.cpp does not recognise fall back to a built-in family template.
+fn render_chat_prompt(model: &LlamaModel, messages: &[ChatMessage]) -> Result<String, String> {
+    if messages.is_empty() {
+        return Err("Mesaj listesi boş".to_string());
+    }
+
+    if let Ok(template) = model.chat_template(None) {
+        let chat: Result<Vec<LlamaChatMessage>, _> = messages
+            .iter()
+            .map(|m| LlamaChatMessage::new(m.role.clone(), m.content.clone()))
+            .collect();
+
+        match chat.map(|chat| model.apply_chat_template(&template, &chat, true)) {
+            Ok(Ok(rendered)) => {
+                info!("📋 Rendered {} messages with the model chat template", messages.len());
+                return Ok(rendered);
+            }
+            Ok(Err(e)) => warn!("⚠️ Model chat template could not be applied: {:?}", e),
+            Err(e) => warn!("⚠️ Invalid chat message: {:?}", e),
+        }
+    }
+
+    let template = model.meta_val_str("tokenizer.chat_template").ok();
+    let architecture = model.meta_val_str("general.architecture").ok();
+    // A special token in the vocabulary tokenizes to exactly one token
+    let has_token = |token: &str| model.str_to_token(token, AddBos::Never).map(|t| t.len() == 1).unwrap_or(false);
+    let family = TemplateFamily::detect(template.as_deref(), architecture.as_deref(), has_token);
+    info!("📋 Using built-in {} chat template", family.name());
+
+    Ok(family.render(messages, true))
+}
+
+/// Honour `tokenizer.ggml.add_bos_token` - some tokenizers (e.g. Qwen) expect no BOS
+fn chat_add_bos(model: &LlamaModel) -> AddBos {
+    match model.meta_val_str("tokenizer.ggml.add_bos_token") {
+        Ok(value) if value == "false" => AddBos::Never,
+        _ => AddBos::Always,
+    }
+}
+
+#[tauri::command]
+pub async fn unload_gguf_model(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: Option<String>, // 🆕 Verilirse sadece bu model çıkarılır, backend korunur
+) -> Result<String, String> {
+    let mut state_guard = lock_state(&state);
+
+    if let Some(model_path) = model_path {
+        info!("🔵 Unloading GGUF model: {}", model_path);
+        let model_path = pool_key(&model_path);
+        return match state_guard.models.remove(&model_path) {
+            Some(unloaded) => {
+                // The worker exits (and frees the model) once its queue is empty
+                let status = unloaded.worker.status();
+                if status.busy || status.queued > 0 {
+                    info!("⏳ Model is freed after {} pending request(s)", status.queued + status.busy as usize);
+                }
+                info!("✅ Model unloaded, {} model(s) left in pool", state_guard.models.len());
+                Ok(format!("✅ Model unloaded: {}", model_path))
+            }
+            None => Err(format!("Model havuzda bulunamadı: {}", model_path)),
+        };
+    }
+
+    info!("🔵 Unloading GGUF model - Starting cleanup...");
+    
+    state_guard.models.clear();
+    state_guard.backend = None;
+    state_guard.backend_initialized = false;
+    
+    // Force garbage collection hint (Rust will handle it)
     drop(state_guard);
     
     info!("✅ GGUF model fully unloaded - GPU memory should be freed");
     Ok("✅ Model unloaded - GPU memory freed".to_string())
 }
 
+/// 📌 Pin/unpin a pooled model - pinned models are never evicted
+#[tauri::command]
+pub async fn pin_gguf_model(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    pinned: bool,
+) -> Result<bool, String> {
+    let model_path = pool_key(&model_path);
+    let mut state_guard = lock_state(&state);
+    let loaded_model = state_guard.models.get_mut(&model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+
+    loaded_model.pinned = pinned;
+    info!("📌 {} {}", if pinned { "Pinned" } else { "Unpinned" }, model_path);
+    Ok(pinned)
+}
+
+/// 🧬 Load a GGUF LoRA adapter onto a pooled model. It applies to every request
+/// that does not pick its own adapters.
+#[tauri::command]
+pub async fn load_gguf_lora(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    lora_path: String,
+    name: Option<String>, // Varsayılan: dosya adı
+    scale: Option<f32>,   // Varsayılan: 1.0
+) -> Result<LoraInfo, String> {
+    let model_path = pool_key(&model_path);
+    info!("🧬 Loading LoRA adapter: {} -> {}", lora_path, model_path);
+
+    if !Path::new(&lora_path).exists() {
+        return Err(format!("LoRA dosyası bulunamadı: {}", lora_path));
+    }
+    let name = name.unwrap_or_else(|| default_lora_name(&lora_path));
+    let scale = check_scale(scale.unwrap_or(1.0))?;
+    let size_bytes = std::fs::metadata(&lora_path).map(|m| m.len()).unwrap_or(0);
+
+    let (worker, _) = pooled_worker(&state, &model_path)?;
+    let (job_name, job_path) = (name.clone(), lora_path.clone());
+    call_worker(worker, move |slot| slot.init_lora(job_name, &job_path)).await?;
+
+    let lora = LoraInfo { name, path: lora_path, scale, size_bytes };
+    let mut state_guard = lock_state(&state);
+    let loaded_model = state_guard.models.get_mut(&model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+    loaded_model.loras.push(lora.clone());
+
+    info!("✅ LoRA attached: {} (scale {})", lora.name, lora.scale);
+    Ok(lora)
+}
+
+/// 🎚️ Change an attached adapter's default scale, used from the next request on
+#[tauri::command]
+pub async fn set_gguf_lora_scale(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    name: String,
+    scale: f32,
+) -> Result<LoraInfo, String> {
+    let model_path = pool_key(&model_path);
+    let scale = check_scale(scale)?;
+    let mut state_guard = lock_state(&state);
+    let loaded_model = state_guard.models.get_mut(&model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+    let lora = loaded_model.loras.iter_mut().find(|l| l.name == name)
+        .ok_or_else(|| format!("LoRA adapter yüklü değil: {}", name))?;
+
+    lora.scale = scale;
+    info!("🎚️ LoRA scale: {} = {}", name, scale);
+    Ok(lora.clone())
+}
+
+#[tauri::command]
+pub async fn list_gguf_loras(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+) -> Result<Vec<LoraInfo>, String> {
+    let model_path = pool_key(&model_path);
+    let state_guard = lock_state(&state);
+    let loaded_model = state_guard.models.get(&model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+    Ok(loaded_model.loras.clone())
+}
+
+/// ✂️ Detach an adapter from a pooled model and free it
+#[tauri::command]
+pub async fn unload_gguf_lora(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    name: String,
+) -> Result<String, String> {
+    let model_path = pool_key(&model_path);
+    let worker = {
+        let mut state_guard = lock_state(&state);
+        let loaded_model = state_guard.models.get_mut(&model_path)
+            .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+        let before = loaded_model.loras.len();
+        loaded_model.loras.retain(|l| l.name != name);
+        if loaded_model.loras.len() == before {
+            return Err(format!("LoRA adapter yüklü değil: {}", name));
+        }
+        loaded_model.worker.clone()
+    };
+
+    // Runs after any request still using the adapter; it comes off the context before it is freed
+    let job_name = name.clone();
+    call_worker(worker, move |slot| {
+        if let Some(mut cache) = slot.cache.take() {
+            let remaining: Vec<ActiveLora> = cache.loras.iter().filter(|l| l.name != job_name).cloned().collect();
+            let result = slot.apply_loras(&mut cache, &remaining);
+            slot.put_context(cache);
+            result?;
+        }
+        slot.loras.remove(&job_name);
+        Ok(())
+    }).await?;
+
+    info!("✅ LoRA detached: {}", name);
+    Ok(format!("✅ LoRA detached: {}", name))
+}
+
+/// 🧮 Set the pool's RAM/VRAM budget in MB (None = unlimited). Models over the new budget are evicted right away.
+#[tauri::command]
+pub async fn set_gguf_memory_budget(
+    app: AppHandle,
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    ram_budget_mb: Option<u64>,
+    vram_budget_mb: Option<u64>,
+) -> Result<PoolBudget, String> {
+    let mut state_guard = lock_state(&state);
+    state_guard.budget = PoolBudget {
+        ram_bytes: ram_budget_mb.map(|mb| mb * 1024 * 1024),
+        vram_bytes: vram_budget_mb.map(|mb| mb * 1024 * 1024),
+    };
+    info!("🧮 GGUF pool budget: RAM {:?} MB, VRAM {:?} MB", ram_budget_mb, vram_budget_mb);
+
+    if let Err(e) = state_guard.make_room(&app, ModelFootprint::default(), None) {
+        warn!("⚠️ Pinned models exceed the new budget: {}", e);
+    }
+
+    Ok(state_guard.budget)
+}
+
 #[tauri::command]
 pub async fn get_gguf_model_status(
     state: State<'_, Arc<Mutex<GgufState>>>,
 ) -> Result<serde_json::Value, String> {
-    let state_guard = state.lock().unwrap();
+    let state_guard = lock_state(&state);
     let loaded_models: Vec<String> = state_guard.models.keys().cloned().collect();
+
+    // Most recently used first
+    let mut pooled: Vec<&LoadedModel> = state_guard.models.values().collect();
+    pooled.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    let models: Vec<serde_json::Value> = pooled.iter().map(|m| {
+        let worker = m.worker.status();
+        json!({
+            "model_path": m.model_path,
+            "n_ctx": m.n_ctx,
+            "n_gpu_layers": m.n_gpu_layers,
+            "pinned": m.pinned,
+            "last_used": m.last_used,
+            "ram_bytes": m.footprint.ram_bytes,
+            "vram_bytes": m.footprint.vram_bytes,
+            "loras": m.loras,
+            "fim": m.fim.is_some(),
+            "mmproj": m.mmproj,
+            "busy": worker.busy,
+            "queued_requests": worker.queued,
+            "completed_requests": worker.completed,
+        })
+    }).collect();
     
     Ok(json!({
         "loaded": !loaded_models.is_empty(),
-        "loaded_models": loaded_models
+        "loaded_models": loaded_models,
+        "models": models,
+        "budget": state_guard.budget,
+        "usage": state_guard.usage(),
     }))
 }
 
-// 🆕 GPU Memory bilgisi al
+/// 🚦 Per-model queue depth and wait times by priority class
+#[tauri::command]
+pub async fn get_gguf_queue_stats(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+) -> Result<serde_json::Value, String> {
+    let state_guard = lock_state(&state);
+
+    let models: Vec<serde_json::Value> = state_guard.models.values().map(|m| json!({
+        "model_path": m.model_path,
+        "completed_requests": m.worker.status().completed,
+        "queue": m.worker.queue_stats(),
+    })).collect();
+
+    Ok(json!({ "models": models }))
+}
+
+/// 💾 Konuşmanın KV cache durumunu diske kaydet
+#[tauri::command]
+pub async fn save_gguf_session(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    session_path: String,
+) -> Result<SessionMeta, String> {
+    let model_path = pool_key(&model_path);
+    info!("💾 Saving GGUF session: {} -> {}", model_path, session_path);
+
+    let (worker, n_ctx) = pooled_worker(&state, &model_path)?;
+
+    // Runs on the worker, so it waits for a generation in progress to finish
+    call_worker(worker, move |slot| {
+        let cache = slot.cache.as_mut()
+            .filter(|cache| !cache.tokens.is_empty())
+            .ok_or("Kaydedilecek konuşma durumu yok - önce bir mesaj gönderin")?;
+
+        let mut meta = SessionMeta::for_model(&model_path, n_ctx, cache.kv_size)?;
+        meta.n_tokens = cache.tokens.len();
+        meta.loras = cache.loras.clone();
+
+        cache.context.save_session_file(&session_path, &cache.tokens)
+            .map_err(|e| {
+                error!("❌ Session save failed: {:?}", e);
+                format!("Session kaydedilemedi: {:?}", e)
+            })?;
+        meta.write(&session_path)?;
+
+        info!("✅ Session saved: {} tokens", meta.n_tokens);
+        Ok(meta)
+    }).await
+}
+
+/// 📂 Kaydedilmiş KV cache durumunu geri yükle - sonraki istek geçmişi yeniden işlemez
+#[tauri::command]
+pub async fn load_gguf_session(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    session_path: String,
+) -> Result<SessionMeta, String> {
+    let model_path = pool_key(&model_path);
+    info!("📂 Loading GGUF session: {} -> {}", session_path, model_path);
+
+    let saved = SessionMeta::read(&session_path)?;
+
+    let (worker, n_ctx) = pooled_worker(&state, &model_path)?;
+
+    let current = SessionMeta::for_model(&model_path, n_ctx, saved.kv_size)?;
+    saved.check_compatible(&current)?;
+
+    call_worker(worker, move |slot| {
+        // Restore into a context of exactly the saved KV size
+        if slot.cache.as_ref().map(|c| c.kv_size != saved.kv_size).unwrap_or(false) {
+            slot.cache = None;
+            slot.kv_size.store(0, Ordering::SeqCst);
+        }
+        // The saved KV state only continues correctly under the adapters it was computed with
+        let mut cache = slot.prepare_context(saved.kv_size, &saved.loras)
+            .map_err(|e| format!("Session LoRA adapter'ları uygulanamadı: {}", e))?;
+        cache.context.clear_kv_cache();
+        cache.tokens.clear();
+
+        let result = cache.context.load_session_file(&session_path, saved.kv_size as usize);
+        match result {
+            Ok(tokens) => {
+                if tokens.len() != saved.n_tokens {
+                    warn!("⚠️ Session has {} tokens, metadata says {}", tokens.len(), saved.n_tokens);
+                }
+                info!("✅ Session restored: {} tokens", tokens.len());
+                cache.tokens = tokens;
+                slot.put_context(cache);
+                Ok(saved)
+            }
+            Err(e) => {
+                error!("❌ Session load failed: {:?}", e);
+                cache.context.clear_kv_cache();
+                slot.put_context(cache);
+                Err(format!("Session yüklenemedi: {:?}", e))
+            }
+        }
+    }).await
+}
+
+// 🆕 GPU Memory bilgisi al - her modelin tensor boyutları ve hiperparametrelerinden hesaplanır
 #[tauri::command]
 pub async fn get_gpu_memory_info(
     state: State<'_, Arc<Mutex<GgufState>>>,
 ) -> Result<serde_json::Value, String> {
-    let state_guard = state.lock().unwrap();
-    
-    // Note: This returns generic GPU info if any model is loaded
-    if state_guard.models.is_empty() {
-        return Ok(json!({
-            "available": false,
-            "total_vram_gb": 0.0,
-            "used_vram_gb": 0.0,
-            "free_vram_gb": 0.0,
-            "usage_percent": 0.0,
-            "model_size_gb": 0.0,
-            "kv_cache_size_gb": 0.0
+    let state_guard = lock_state(&state);
+
+    let has_gpu_backend = cfg!(feature = "cuda") || cfg!(feature = "vulkan");
+    let detected_vram_gb = if has_gpu_backend { detect_gpu_vram() } else { None };
+
+    use sysinfo::System;
+    let mut sys = System::new();
+    sys.refresh_memory();
+    let total_ram_gb = bytes_to_gb(sys.total_memory());
+    let available_ram_gb = bytes_to_gb(sys.available_memory());
+
+    let mut total = MemoryBreakdown::default();
+    let mut models = Vec::new();
+    for loaded_model in state_guard.models.values() {
+        let memory = loaded_model.memory();
+        total.weights.ram_bytes += memory.weights.ram_bytes;
+        total.weights.vram_bytes += memory.weights.vram_bytes;
+        total.kv_cache.ram_bytes += memory.kv_cache.ram_bytes;
+        total.kv_cache.vram_bytes += memory.kv_cache.vram_bytes;
+
+        models.push(json!({
+            "model_path": loaded_model.model_path,
+            "n_ctx": loaded_model.n_ctx,
+            "kv_size": loaded_model.kv_size.load(Ordering::SeqCst),
+            "kv_cache_type": KvCacheType::default(),
+            "n_gpu_layers": loaded_model.n_gpu_layers,
+            "hparams": loaded_model.hparams,
+            "weights_ram_gb": bytes_to_gb(memory.weights.ram_bytes),
+            "weights_vram_gb": bytes_to_gb(memory.weights.vram_bytes),
+            "kv_cache_ram_gb": bytes_to_gb(memory.kv_cache.ram_bytes),
+            "kv_cache_vram_gb": bytes_to_gb(memory.kv_cache.vram_bytes),
+            "total_ram_gb": bytes_to_gb(memory.total().ram_bytes),
+            "total_vram_gb": bytes_to_gb(memory.total().vram_bytes),
         }));
     }
-    
-    // Use the first model for estimation or sum them up
-    // For now, let's just use representative values
-    let representative_model = state_guard.models.values().next().unwrap();
-    let n_ctx = representative_model.n_ctx;
-    let n_gpu_layers = representative_model.n_gpu_layers;
-    
-    // 🔥 Dinamik sistem VRAM bilgisi al
-    // Eğer GPU yüklü değilse sistem RAM'ı kullan
-    use sysinfo::System;
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    
-    // Eğer GPU varsa (CUDA/Vulkan), varsayılan olarak daha düşük bir değer al
-    // Gerçek VRAM bilgisi almak için platform-specific kod gerekli
-    let total_vram_gb = if cfg!(feature = "cuda") || cfg!(feature = "vulkan") {
-        // GPU var - ancak gerçek VRAM alamıyoruz, bu nedenle bir tahmin yap
-        // NVIDIA için nvidia-smi, AMD için rocm-smi, Intel için level-zero kullanılabilir
-        // Şimdilik 12GB varsayılan (değiştirilebilir)
-        let detected_vram = detect_gpu_vram();
-        detected_vram
-    } else {
-        // GPU yok - sistem RAM'ını kullan (CPU mode)
-        let total_memory_bytes = sys.total_memory();
-        let total_memory_gb = total_memory_bytes as f64 / (1024.0 * 1024.0 * 1024.0);
-        total_memory_gb * 0.7 // CPU mode için %70'ini kullan
-    };
-    
-    let _free_vram_gb = if cfg!(feature = "cuda") || cfg!(feature = "vulkan") {
-        total_vram_gb // GPU serbest bellek - tahmin yap
-    } else {
-        let available_memory_bytes = sys.available_memory();
-        let available_memory_gb = available_memory_bytes as f64 / (1024.0 * 1024.0 * 1024.0);
-        available_memory_gb * 0.7
+    let used = total.total();
+
+    // CPU-only: the "VRAM" figures describe system RAM so the UI keeps working
+    let (capacity_gb, used_gb) = match detected_vram_gb {
+        Some(vram_gb) => (vram_gb, bytes_to_gb(used.vram_bytes)),
+        None => (total_ram_gb, bytes_to_gb(used.ram_bytes + used.vram_bytes)),
     };
-    
-    // 🔥 Düzeltilmiş hesaplamalar
-    // Model size: Q4 quantization için ~0.5-0.6 GB per billion parameters
-    // 7B model Q4 = ~4.2 GB
-    let estimated_model_size = 4.2; // GB
-    
-    // KV Cache hesaplama (daha doğru):
-    // KV cache = 2 (K + V) * n_layers * n_ctx * hidden_size * bytes_per_element / 1e9
-    // Qwen 7B: 28 layers, 4096 hidden_size, fp16 = 2 bytes
-    let n_layers = n_gpu_layers.min(28) as f64; // Maksimum 28 layer
-    let hidden_size = 4096.0;
-    let bytes_per_element = 2.0; // fp16
-    
-    let kv_cache_gb = (2.0 * n_layers * n_ctx as f64 * hidden_size * bytes_per_element) / 1_000_000_000.0;
-    
-    let used_vram = estimated_model_size + kv_cache_gb;
-    let safe_used_vram = used_vram.min(total_vram_gb);
-    let safe_free_vram = (total_vram_gb - safe_used_vram).max(0.0); // Negatif olmasın
-    let usage_percent = ((safe_used_vram / total_vram_gb) * 100.0).min(100.0); // Max %100
-    
-    info!("📊 GPU Memory: {:.1} GB / {:.1} GB ({:.1}%)", safe_used_vram, total_vram_gb, usage_percent);
-    info!("   Model: {:.1} GB, KV Cache: {:.1} GB", estimated_model_size, kv_cache_gb);
-    
+    let free_gb = (capacity_gb - used_gb).max(0.0);
+    let usage_percent = if capacity_gb > 0.0 { (used_gb / capacity_gb * 100.0).min(100.0) } else { 0.0 };
+
+    info!("📊 Memory: {:.2} GB / {:.2} GB ({:.1}%) across {} model(s)", used_gb, capacity_gb, usage_percent, models.len());
+
     Ok(json!({
-        "available": true,
-        "total_vram_gb": total_vram_gb,
-        "used_vram_gb": safe_used_vram,
-        "free_vram_gb": safe_free_vram,
+        "available": !state_guard.models.is_empty(),
+        "gpu_detected": detected_vram_gb.is_some(),
+        "total_vram_gb": capacity_gb,
+        "used_vram_gb": used_gb,
+        "free_vram_gb": free_gb,
         "usage_percent": usage_percent,
-        "model_size_gb": estimated_model_size,
-        "kv_cache_size_gb": kv_cache_gb
+        "model_size_gb": bytes_to_gb(total.weights.ram_bytes + total.weights.vram_bytes),
+        "kv_cache_size_gb": bytes_to_gb(total.kv_cache.ram_bytes + total.kv_cache.vram_bytes),
+        "models": models,
+        "totals": {
+            "ram_gb": bytes_to_gb(used.ram_bytes),
+            "vram_gb": bytes_to_gb(used.vram_bytes),
+            "weights_ram_gb": bytes_to_gb(total.weights.ram_bytes),
+            "weights_vram_gb": bytes_to_gb(total.weights.vram_bytes),
+            "kv_cache_ram_gb": bytes_to_gb(total.kv_cache.ram_bytes),
+            "kv_cache_vram_gb": bytes_to_gb(total.kv_cache.vram_bytes),
+        },
+        "system_ram": {
+            "total_gb": total_ram_gb,
+            "available_gb": available_ram_gb,
+        },
     }))
 }
 
-/// GPU VRAM bilgisini algıla (platform-specific)
-fn detect_gpu_vram() -> f64 {
-    // 🎮 NVIDIA GPU - nvidia-smi ile kontrol et
-    if cfg!(target_os = "windows") {
-        if let Ok(output) = std::process::Command::new("nvidia-smi")
-            .args(&["--query-gpu=memory.total", "--format=csv,noheader,nounits"])
-            .output()
-        {
-            if output.status.success() {
-                if let Ok(output_str) = String::from_utf8(output.stdout) {
-                    if let Ok(vram_mb) = output_str.trim().parse::<f64>() {
-                        let vram_gb = vram_mb / 1024.0;
-                        info!("🎮 Detected GPU VRAM: {:.1} GB", vram_gb);
-                        return vram_gb;
-                    }
-                }
-            }
-        }
+/// Total VRAM of the first NVIDIA GPU in GB, via nvidia-smi
+fn detect_gpu_vram() -> Option<f64> {
+    let output = std::process::Command::new("nvidia-smi")
+        .args(["--query-gpu=memory.total", "--format=csv,noheader,nounits"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
     }
-    
-    // Varsayılan olarak 12GB döndür (kullanıcı ayarlayabilir)
-    info!("⚠️ GPU VRAM alınamadı, 12GB varsayılan kullanılıyor");
-    12.0
+
+    let vram_mb: f64 = String::from_utf8(output.stdout).ok()?
+        .lines()
+        .next()?
+        .trim()
+        .parse()
+        .ok()?;
+    let vram_gb = vram_mb / 1024.0;
+    info!("🎮 Detected GPU VRAM: {:.1} GB", vram_gb);
+    Some(vram_gb)
 }
 
-// 🆕 GGUF Metadata Okuyucu
+// 🆕 GGUF Metadata Okuyucu - gerçek header'dan okur (dosya adından tahmin etmez)
 #[tauri::command]
 pub async fn read_gguf_metadata(
     model_path: String,
 ) -> Result<serde_json::Value, String> {
+    let model_path = pool_key(&model_path);
     info!("📖 Reading GGUF metadata from: {}", model_path);
     
     if !Path::new(&model_path).exists() {
@@ -659,187 +2049,334 @@ pub async fn read_gguf_metadata(
         return Err(format!("Model file not found: {}", model_path));
     }
     
-    // Dosya boyutunu al
-    let file_size = std::fs::metadata(&model_path)
-        .map_err(|e| format!("Failed to get file size: {}", e))?
-        .len();
+    let model_header = tokio::task::spawn_blocking(move || GgufModelHeader::read(&model_path))
+        .await
+        .map_err(|e| format!("Metadata task failed: {}", e))?
+        .map_err(|e| {
+            error!("❌ GGUF header okunamadı: {}", e);
+            e
+        })?;
     
-    let file_size_gb = file_size as f64 / (1024.0 * 1024.0 * 1024.0);
+    Ok(gguf_metadata_json(&model_header))
+}
+
+/// JSON view of a GGUF header: every raw metadata key at the top level plus summary fields
+fn gguf_metadata_json(model_header: &GgufModelHeader) -> serde_json::Value {
+    let header = &model_header.header;
     
-    // Model parametrelerini dosya adından çıkar
-    let file_name = Path::new(&model_path)
+    let file_name = Path::new(&model_header.shard_paths[0])
         .file_name()
         .and_then(|n| n.to_str())
-        .unwrap_or("unknown");
-    
-    // Parametre sayısını tahmin et (dosya adından)
-    let parameters = if file_name.contains("3b") || file_name.contains("3B") {
-        "3B"
-    } else if file_name.contains("7b") || file_name.contains("7B") {
-        "7B"
-    } else if file_name.contains("8b") || file_name.contains("8B") {
-        "8B"
-    } else if file_name.contains("13b") || file_name.contains("13B") {
-        "13B"
-    } else if file_name.contains("70b") || file_name.contains("70B") {
-        "70B"
-    } else {
-        "Unknown"
-    };
-    
-    // Quantization'ı dosya adından çıkar
-    let quantization = if file_name.contains("q4_k_m") || file_name.contains("Q4_K_M") {
-        "Q4_K_M"
-    } else if file_name.contains("q5_k_m") || file_name.contains("Q5_K_M") {
-        "Q5_K_M"
-    } else if file_name.contains("q6_k") || file_name.contains("Q6_K") {
-        "Q6_K"
-    } else if file_name.contains("q8") || file_name.contains("Q8") {
-        "Q8_0"
-    } else if file_name.contains("q4") || file_name.contains("Q4") {
-        "Q4_0"
-    } else if file_name.contains("q5") || file_name.contains("Q5") {
-        "Q5_0"
-    } else {
-        "Unknown"
-    };
-    
-    // Model architecture'ı tahmin et
-    let architecture = if file_name.to_lowercase().contains("llama") {
-        "Llama"
-    } else if file_name.to_lowercase().contains("qwen") {
-        "Qwen"
-    } else if file_name.to_lowercase().contains("mistral") {
-        "Mistral"
-    } else if file_name.to_lowercase().contains("phi") {
-        "Phi"
-    } else if file_name.to_lowercase().contains("gemma") {
-        "Gemma"
-    } else {
-        "Unknown"
-    };
-    
-    // Tahmini layer sayısı (parametre sayısına göre)
-    let estimated_layers = match parameters {
-        "3B" => 26,
-        "7B" | "8B" => 32,
-        "13B" => 40,
-        "70B" => 80,
-        _ => 32,
-    };
+        .unwrap_or("unknown")
+        .to_string();
     
-    // Tahmini vocab size
-    let estimated_vocab_size = match architecture {
-        "Qwen" => 151936,
-        "Llama" => 32000,
-        "Mistral" => 32000,
-        "Phi" => 51200,
-        "Gemma" => 256000,
-        _ => 32000,
-    };
+    let file_size = model_header.total_file_size;
+    let file_size_gb = file_size as f64 / (1024.0 * 1024.0 * 1024.0);
     
-    // Tahmini context length (modern modeller genelde 4K-128K arası)
-    let estimated_context = match architecture {
-        "Qwen" => 32768,
-        "Llama" => 8192,
-        "Mistral" => 32768,
-        "Phi" => 4096,
-        "Gemma" => 8192,
-        _ => 4096,
-    };
+    let architecture = header.architecture().unwrap_or("unknown").to_string();
+    let parameter_count = model_header.parameter_count();
+    let parameters = header.get_str("general.size_label")
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format_parameter_count(parameter_count));
+    let quantization = header.file_type().map(file_type_name).unwrap_or("Unknown");
+    let block_count = header.arch_u64("block_count");
+    let context_length = header.arch_u64("context_length");
+    let vocab_size = header.vocab_size();
     
     info!("✅ Metadata extracted:");
+    info!("   GGUF v{}, {} keys, {} tensors", header.version, header.metadata.len(), model_header.tensors.len());
     info!("   File Size: {:.2} GB", file_size_gb);
     info!("   Parameters: {}", parameters);
     info!("   Quantization: {}", quantization);
     info!("   Architecture: {}", architecture);
-    info!("   Estimated Layers: {}", estimated_layers);
+    info!("   Layers: {:?}, Context: {:?}", block_count, context_length);
     
-    Ok(json!({
+    let mut result = serde_json::Map::new();
+    for (key, value) in &header.metadata {
+        result.insert(key.clone(), value.to_json());
+    }
+    
+    let summary = json!({
         "file_name": file_name,
         "file_size_bytes": file_size,
         "file_size_gb": format!("{:.2}", file_size_gb),
+        "gguf_version": header.version,
         "parameters": parameters,
+        "parameter_count": parameter_count,
         "quantization": quantization,
+        "file_type": header.file_type(),
         "architecture": architecture,
-        "estimated_layers": estimated_layers,
-        "estimated_vocab_size": estimated_vocab_size,
-        "estimated_context_length": estimated_context,
+        "block_count": block_count,
+        "context_length": context_length,
+        "vocab_size": vocab_size,
+        "embedding_length": header.arch_u64("embedding_length"),
+        "chat_template": header.get_str("tokenizer.chat_template"),
+        "tensor_count": model_header.tensors.len(),
+        "tensor_types": model_header.tensor_type_counts(),
+        "tensor_data_bytes": model_header.tensor_bytes(),
+        "split_count": model_header.shard_paths.len(),
+        "shard_paths": model_header.shard_paths,
+        // Eski alan adları (frontend uyumluluğu)
+        "estimated_layers": block_count,
+        "estimated_vocab_size": vocab_size,
+        "estimated_context_length": context_length,
         "model_type": format!("{} {}", architecture, parameters),
-    }))
+    });
+    if let serde_json::Value::Object(summary) = summary {
+        result.extend(summary);
+    }
+    
+    serde_json::Value::Object(result)
 }
 
 
 // 🆕 Vision AI Support - Chat with images
+/// 📷 Ask about images with a multimodal GGUF model (LLaVA, Qwen2-VL, ...). Its mmproj
+/// projector encodes the images; unless `load_gguf_mmproj` attached one, it is looked up
+/// next to the model file on first use. The prompt is sent as a user message through the
+/// model's chat template and may place the images itself with `<__media__>` markers.
 #[tauri::command]
 pub async fn chat_with_gguf_vision(
-    state: State<'_, Arc<Mutex<GgufState>>>,
+    app: AppHandle,
     model_path: String, // 🆕 Model path required
     prompt: String,
     images: Vec<String>, // Base64 encoded images
     max_tokens: u32,
     temperature: f32,
-) -> Result<String, String> {
+    options: Option<GgufRequestOptions>,
+) -> Result<GgufChatResponse, String> {
+    let model_path = pool_key(&model_path);
     info!("📷 Starting vision inference...");
     info!("📝 Prompt length: {} chars", prompt.len());
     info!("🖼️ Images: {}", images.len());
     info!("⚙️ Max tokens: {}, Temperature: {}", max_tokens, temperature);
 
-    // ⚠️ IMPORTANT: Vision support requires:
-    // 1. Vision-capable GGUF model (LLaVA, Qwen2-VL, Bakllava)
-    // 2. mmproj file (vision projector) - must match the model
-    // 3. llama.cpp compiled with vision support
-    
-    // Check if model is loaded (in a separate scope to drop the lock immediately)
-    {
-        let state_guard = state.lock().unwrap();
-        if !state_guard.models.contains_key(&model_path) {
-            error!("❌ Model not found in pool: {}", model_path);
-            return Err(format!("Vision model pool'da bulunamadı: {}", model_path));
-        }
-    } // Lock is dropped here
-    
-    // Decode base64 images (validation only for now)
-    let mut decoded_images = Vec::new();
-    for (idx, img_data) in images.iter().enumerate() {
-        // Remove data:image/...;base64, prefix if present
-        let base64_data = if img_data.contains("base64,") {
-            img_data.split("base64,").nth(1).unwrap_or(img_data)
-        } else {
-            img_data
-        };
-        
-        match general_purpose::STANDARD.decode(base64_data) {
-            Ok(bytes) => {
-                decoded_images.push(bytes);
-            }
-            Err(e) => {
-                error!("❌ Failed to decode image {}: {:?}", idx, e);
-                return Err(format!("Failed to decode image {}: {:?}", idx, e));
-            }
+    let images = images
+        .iter()
+        .enumerate()
+        .map(|(idx, data)| decode_image(data).map_err(|e| format!("Görsel {}: {}", idx, e)))
+        .collect::<Result<Vec<_>, String>>()?;
+    info!("✅ All images decoded successfully");
+
+    let mut options = options.unwrap_or_default();
+    let generation_id = options.generation_id.take();
+    let mut params = options.into_params(max_tokens, temperature)?;
+    if params.speculative.take().is_some() {
+        info!("ℹ️ Speculative decoding is not used with images");
+    }
+    let logprobs = params.logprobs.take();
+    let context_shift = params.context_shift.take();
+    if logprobs.is_some() || context_shift.is_some() {
+        info!("ℹ️ Logprobs and context shift are not used with images");
+    }
+    let state = app.state::<Arc<Mutex<GgufState>>>().inner().clone();
+    let mmproj = ensure_projector(&state, &model_path).await?;
+    info!("🖼️ Using projector: {}", mmproj);
+
+    let generation = cancellation::register_generation(generation_id);
+    cancellation::emit_generation_started(&app, &generation.id);
+    params.cancel = Some(generation.token.clone());
+
+    let output = tokio::task::spawn_blocking(move || {
+        run_gguf_vision(&state, &model_path, &prompt, images, &params)
+    })
+    .await
+    .map_err(|e| format!("Inference task failed: {}", e))??;
+
+    if output.cancelled {
+        cancellation::emit_generation_cancelled(&app, &generation.id, &output.text);
+    }
+
+    info!("📤 Vision response: {} characters", output.text.len());
+    Ok(GgufChatResponse::new(output.text.trim().to_string(), output))
+}
+
+/// 🖼️ Attach a vision projector (mmproj) to a pooled model. Without a path it is
+/// looked up next to the model file. Returns the projector's path.
+#[tauri::command]
+pub async fn load_gguf_mmproj(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_path: String,
+    mmproj_path: Option<String>, // Varsayılan: model ile aynı klasördeki mmproj-*.gguf
+) -> Result<String, String> {
+    let model_path = pool_key(&model_path);
+    let mmproj_path = match mmproj_path {
+        Some(path) => path,
+        None => find_mmproj(&model_path).ok_or_else(|| no_projector_error(&model_path))?,
+    };
+    attach_projector(&state, &model_path, mmproj_path).await
+}
+
+fn no_projector_error(model_path: &str) -> String {
+    format!(
+        "Bu model için mmproj projector bulunamadı: {}. Görsel desteği için modelin mmproj dosyasını aynı klasöre koyun veya load_gguf_mmproj ile yükleyin",
+        model_path
+    )
+}
+
+/// Projector of a pooled model, attaching the one next to the model file if there is none yet
+async fn ensure_projector(state: &Mutex<GgufState>, model_path: &str) -> Result<String, String> {
+    let attached = {
+        let state_guard = lock_state(state);
+        let loaded_model = state_guard.models.get(model_path)
+            .ok_or_else(|| format!("Vision model pool'da bulunamadı: {}", model_path))?;
+        loaded_model.mmproj.clone()
+    };
+    match attached {
+        Some(path) => Ok(path),
+        None => {
+            let mmproj_path = find_mmproj(model_path).ok_or_else(|| no_projector_error(model_path))?;
+            attach_projector(state, model_path, mmproj_path).await
         }
     }
-    
-    info!("✅ All images decoded successfully");
-    
-    // TODO: Full vision implementation
-    // 1. Load mmproj file (vision encoder)
-    // 2. Convert images to embeddings using mmproj
-    // 3. Combine text prompt + image embeddings
-    // 4. Pass to model for inference
-    
-    // For now, fall back to text-only chat with a note about images
-    info!("⚠️ Vision support not fully implemented yet");
-    info!("📝 Falling back to text-only mode with image count note");
-    
-    let vision_prompt = format!(
-        "[System: User sent {} image(s) but vision processing is not yet implemented. Please acknowledge the images and respond based on the text prompt.]\n\n{}",
-        images.len(),
-        prompt
+}
+
+/// Load `mmproj_path` on the model's worker, replacing any projector it had
+async fn attach_projector(state: &Mutex<GgufState>, model_path: &str, mmproj_path: String) -> Result<String, String> {
+    info!("🖼️ Loading mmproj: {} -> {}", mmproj_path, model_path);
+    if !Path::new(&mmproj_path).exists() {
+        return Err(format!("mmproj dosyası bulunamadı: {}", mmproj_path));
+    }
+
+    let (worker, _) = pooled_worker(state, model_path)?;
+    // The image encoder follows the model: on the GPU only when the model is offloaded
+    let use_gpu = lock_state(state).models.get(model_path).map(|m| m.n_gpu_layers > 0).unwrap_or(false);
+    let job_path = mmproj_path.clone();
+    call_worker(worker, move |slot| slot.init_projector(&job_path, use_gpu)).await?;
+
+    let mut state_guard = lock_state(state);
+    let loaded_model = state_guard.models.get_mut(model_path)
+        .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+    loaded_model.mmproj = Some(mmproj_path.clone());
+
+    info!("✅ mmproj attached (GPU: {})", use_gpu);
+    Ok(mmproj_path)
+}
+
+/// Run a prompt with images through a pooled model's projector, queued on its worker
+/// like `run_gguf_chat`. Blocks the calling thread until the request is done.
+fn run_gguf_vision(
+    state: &Arc<Mutex<GgufState>>,
+    model_path: &str,
+    prompt: &str,
+    images: Vec<Vec<u8>>,
+    params: &GenerationParams,
+) -> Result<GenerationOutput, String> {
+    let request_started = Instant::now();
+    let (worker, n_ctx) = pooled_worker(state, model_path)?;
+    let (loras, model) = {
+        let state_guard = lock_state(state);
+        let loaded_model = state_guard.models.get(model_path)
+            .ok_or_else(|| format!("Model havuzda bulunamadı: {}", model_path))?;
+        (resolve_loras(&loaded_model.loras, params.loras.as_deref())?, loaded_model.model.clone())
+    };
+
+    let content = with_media_markers(prompt, images.len(), mtmd_default_marker())?;
+    let prompt = render_chat_prompt(&model, &[ChatMessage { role: "user".to_string(), content }])?;
+    let job = VisionJob {
+        prompt,
+        images,
+        kv_cache_size: (n_ctx + params.max_tokens).max(4096),
+        loras,
+        params: params.clone(),
+    };
+    let submitted = Instant::now();
+    let queued = worker.call_streaming(
+        &params.schedule,
+        params.cancel.clone(),
+        move |slot, emit: &mut dyn FnMut(String)| {
+            let started = Instant::now();
+            // Taken out so the slot can be borrowed mutably next to it. It goes back even when
+            // the run panics: the worker survives panics, and `LoadedModel.mmproj` still says attached.
+            let Some(projector) = slot.projector.take() else {
+                return (Err("Model için mmproj projector yüklü değil".to_string()), started);
+            };
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                run_vision_on_slot(slot, &projector, &job, &mut |piece| emit(piece.to_string()))
+            }));
+            slot.projector = Some(projector);
+            (result.unwrap_or_else(|panic| std::panic::resume_unwind(panic)), started)
+        },
+        &mut |_: String| {},
     );
-    
-    // Use the existing text chat function
-    chat_with_gguf_model(state, model_path, vision_prompt, max_tokens, temperature).await
+
+    let (result, started) = match queued {
+        Ok(done) => done,
+        Err(_) if params.cancel.as_ref().map(|c| c.is_cancelled()).unwrap_or(false) => {
+            return Ok(dropped_from_queue(request_started, submitted));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let load_ms = lock_state(state).models.get(model_path).map(|m| m.load_ms).unwrap_or(0.0);
+    result.map(|mut output| {
+        record_timings(&mut output, load_ms, request_started, submitted, started);
+        output
+    })
+}
+
+/// A vision request as handed to the model's worker
+struct VisionJob {
+    /// Rendered chat prompt with a media marker per image
+    prompt: String,
+    images: Vec<Vec<u8>>,
+    kv_cache_size: u32,
+    loras: Vec<ActiveLora>,
+    params: GenerationParams,
+}
+
+/// Worker side of `run_gguf_vision`. Image positions have no token ids, so the KV cache
+/// is neither reused for nor kept after a vision request.
+fn run_vision_on_slot(
+    slot: &mut ModelSlot,
+    projector: &Projector,
+    job: &VisionJob,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let VisionJob { prompt, images, kv_cache_size, loras, params } = job;
+    let kv_cache_size = *kv_cache_size;
+    let bitmaps = images
+        .iter()
+        .enumerate()
+        .map(|(idx, bytes)| {
+            MtmdBitmap::from_buffer(&projector.context, bytes)
+                .map_err(|e| format!("Görsel {} okunamadı: {:?}", idx, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let bitmap_refs: Vec<&MtmdBitmap> = bitmaps.iter().collect();
+
+    let text = MtmdInputText {
+        text: prompt.clone(),
+        add_special: matches!(chat_add_bos(&slot.model), AddBos::Always),
+        parse_special: true,
+    };
+    let chunks = projector.context.tokenize(text, &bitmap_refs)
+        .map_err(|e| format!("Görsel prompt hazırlanamadı: {:?}", e))?;
+    let n_prompt = chunks.total_tokens();
+    info!("🖼️ {} image(s) + text: {} prompt positions", images.len(), n_prompt);
+    if n_prompt + params.max_tokens as usize > kv_cache_size as usize {
+        error!("❌ Prompt too long: {} tokens (max: {})", n_prompt, kv_cache_size);
+        return Err(format!("Prompt too long: {} tokens (max: {})", n_prompt, kv_cache_size));
+    }
+
+    let mut cache = slot.prepare_context(kv_cache_size, loras)?;
+    cache.store(None);
+    let eval_started = Instant::now();
+    let result = chunks
+        .eval_chunks(&projector.context, &cache.context, 0, 0, MAX_BATCH_SIZE as i32, true)
+        .map_err(|e| format!("Görsel encode başarısız: {:?}", e))
+        .and_then(|n_past| {
+            let prompt_eval_ms = elapsed_ms(eval_started);
+            info!("✅ Images encoded, {} positions in the KV cache", n_past);
+            let mut output = generate_after(&slot.model, &mut cache.context, n_past, params, on_piece)?;
+            output.metrics.prompt_eval_tokens = output.prompt_tokens;
+            output.metrics.prompt_eval_ms = prompt_eval_ms;
+            Ok(output)
+        });
+    cache.store(None);
+    slot.put_context(cache);
+
+    result
 }
 
 // Check if CUDA is available
@@ -875,10 +2412,16 @@ pub fn check_cuda_support() -> Result<serde_json::Value, String> {
     }))
 }
 
+/// Havuz anahtarı: dosya yolu (split modelde herhangi bir parça) veya model kütüphanesi ID'si
+/// ilk parçanın yoluna çevrilir, böylece tüm komutlar aynı modeli bulur.
+pub(crate) fn pool_key(model: &str) -> String {
+    resolve_split_gguf_path(&resolve_model_path(model))
+}
+
 /// Split GGUF dosyalarini tespit edip ilk parcaya yonlendirir.
 /// Ornek: "model-00003-of-00004.gguf" -> "model-00001-of-00004.gguf"
 /// Tek parca dosyalarda ayni yolu dondurur.
-fn resolve_split_gguf_path(path: &str) -> String {
+pub(crate) fn resolve_split_gguf_path(path: &str) -> String {
     let re = regex::Regex::new(r"(-\d{5})-of-(\d{5})\.gguf$").ok();
     if let Some(re) = re {
         if let Some(caps) = re.captures(path) {
@@ -0,0 +1,111 @@
+// src-tauri/src/gguf_context_shift.rs
+// Sliding-window context: drop the oldest middle of the KV cache so generation can run past n_ctx
+
+use serde::{Deserialize, Serialize};
+
+/// Opt-in sliding window for a GGUF request. When the KV cache is full, the first `keep`
+/// tokens stay, the older half of the rest is discarded and the newer half slides back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContextShift {
+    /// Prompt tokens at the start that are never discarded (BOS not counted), e.g. the system prompt
+    #[serde(default)]
+    pub keep: u32,
+}
+
+/// What the sliding window did during a request
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ContextShiftReport {
+    /// Times the KV cache was shifted during generation
+    pub shifts: usize,
+    /// Tokens dropped from the KV cache by those shifts
+    pub discarded_tokens: usize,
+    /// Prompt tokens cut before evaluation because the prompt alone did not fit
+    pub truncated_prompt_tokens: usize,
+}
+
+impl ContextShiftReport {
+    /// None when the window never had to move
+    pub fn new(shifts: &[(usize, usize)], truncated_prompt_tokens: usize) -> Option<Self> {
+        if shifts.is_empty() && truncated_prompt_tokens == 0 {
+            return None;
+        }
+        Some(Self {
+            shifts: shifts.len(),
+            discarded_tokens: shifts.iter().map(|(_, count)| count).sum(),
+            truncated_prompt_tokens,
+        })
+    }
+}
+
+/// Positions to drop from a full cache of `n_past` tokens, as (first position, count).
+/// Half of what follows the kept tokens goes; `keep` is capped at half the cache so a
+/// shift always frees room.
+pub fn shift_plan(n_past: usize, keep: usize) -> Option<(usize, usize)> {
+    let keep = keep.min(n_past / 2);
+    let discard = (n_past - keep) / 2;
+    (discard > 0).then_some((keep, discard))
+}
+
+/// Cut the middle of a prompt longer than `window`, keeping the first `keep` tokens and the
+/// newest tail, so `reserve` positions stay free for the reply. Returns the tokens and how
+/// many were cut.
+pub fn truncate_prompt<T: Copy>(tokens: &[T], window: usize, keep: usize, reserve: usize) -> (Vec<T>, usize) {
+    // At least half the window is prompt, the reply then starts with room to grow
+    let budget = window.saturating_sub(reserve).max(window / 2);
+    if tokens.len() <= budget {
+        return (tokens.to_vec(), 0);
+    }
+    let keep = keep.min(budget / 2);
+    let tail = budget - keep;
+    let mut kept = tokens[..keep].to_vec();
+    kept.extend_from_slice(&tokens[tokens.len() - tail..]);
+    (kept, tokens.len() - budget)
+}
+
+/// The cached token list after the shifts of a run, applied in the order they happened
+pub fn apply_shifts<T>(tokens: &mut Vec<T>, shifts: &[(usize, usize)]) {
+    for &(start, count) in shifts {
+        let end = (start + count).min(tokens.len());
+        tokens.drain(start.min(end)..end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_plan() {
+        assert_eq!(shift_plan(4096, 100), Some((100, 1998)));
+        assert_eq!(shift_plan(4096, 0), Some((0, 2048)));
+        // An oversized keep still leaves something to discard
+        assert_eq!(shift_plan(100, 1000), Some((50, 25)));
+        assert_eq!(shift_plan(1, 0), None);
+    }
+
+    #[test]
+    fn test_truncate_prompt() {
+        let tokens: Vec<u32> = (0..100).collect();
+        let (kept, cut) = truncate_prompt(&tokens, 64, 4, 16);
+        assert_eq!(kept.len(), 48);
+        assert_eq!(cut, 52);
+        assert_eq!(&kept[..4], &[0, 1, 2, 3]);
+        assert_eq!(kept[4], 56);
+        assert_eq!(*kept.last().unwrap(), 99);
+
+        assert_eq!(truncate_prompt(&tokens, 128, 4, 16), (tokens.clone(), 0));
+        // A reserve larger than the window still leaves half of it for the prompt
+        assert_eq!(truncate_prompt(&tokens, 64, 0, 1000).0.len(), 32);
+    }
+
+    #[test]
+    fn test_apply_shifts() {
+        let mut tokens: Vec<u32> = (0..10).collect();
+        apply_shifts(&mut tokens, &[(2, 3), (2, 2)]);
+        assert_eq!(tokens, vec![0, 1, 7, 8, 9]);
+
+        assert_eq!(ContextShiftReport::new(&[], 0), None);
+        let report = ContextShiftReport::new(&[(2, 3), (2, 2)], 5).unwrap();
+        assert_eq!((report.shifts, report.discarded_tokens, report.truncated_prompt_tokens), (2, 5, 5));
+    }
+}
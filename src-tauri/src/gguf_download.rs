@@ -0,0 +1,619 @@
+// src-tauri/src/gguf_download.rs
+// Resumable GGUF downloads: .part files with HTTP Range resume, SHA-256 verification,
+// parallel split shards and cancellation
+
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::cancellation::CancelToken;
+
+/// Suffix of the file a download is written to until it is complete and verified
+pub const PART_SUFFIX: &str = ".part";
+/// Attempts per file after a connection drops, each resuming where the last one stopped
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled for every further one
+const RETRY_DELAY_MS: u64 = 500;
+/// Progress is reported once per this many downloaded bytes
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+lazy_static::lazy_static! {
+    // Download ID -> cancel token of every download currently running
+    static ref ACTIVE_DOWNLOADS: Mutex<HashMap<String, Arc<CancelToken>>> = Mutex::new(HashMap::new());
+}
+
+/// Registration of a running download, removed from the registry on drop
+struct DownloadGuard {
+    id: String,
+    token: Arc<CancelToken>,
+}
+
+impl Drop for DownloadGuard {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE_DOWNLOADS.lock() {
+            active.remove(&self.id);
+        }
+    }
+}
+
+fn register_download(id: &str) -> Result<DownloadGuard, String> {
+    let mut active = ACTIVE_DOWNLOADS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if active.contains_key(id) {
+        return Err(format!("Bu indirme zaten sürüyor: {}", id));
+    }
+    let token = Arc::new(CancelToken::default());
+    active.insert(id.to_string(), token.clone());
+    Ok(DownloadGuard { id: id.to_string(), token })
+}
+
+pub fn part_path(destination: &str) -> String {
+    format!("{}{}", destination, PART_SUFFIX)
+}
+
+/// Every shard of a split GGUF ("model-00001-of-00003.gguf", also as a URL with a query),
+/// in order. None for a single-file model.
+pub fn split_shards(path: &str) -> Option<Vec<String>> {
+    let re = regex::Regex::new(r"-(\d{5})-of-(\d{5})\.gguf(\?.*)?$").ok()?;
+    let caps = re.captures(path)?;
+    let total: u32 = caps[2].parse().ok()?;
+    if total < 2 {
+        return None;
+    }
+    let query = caps.get(3).map(|m| m.as_str()).unwrap_or("");
+    let prefix = &path[..caps.get(0)?.start()];
+    Some((1..=total).map(|i| format!("{}-{:05}-of-{}.gguf{}", prefix, i, &caps[2], query)).collect())
+}
+
+/// (url, destination) of every file `download_model` fetches: all shards when both
+/// the URL and the destination are named as the same split, otherwise just the one file
+pub fn download_files(url: &str, destination: &str) -> Vec<(String, String)> {
+    match (split_shards(url), split_shards(destination)) {
+        (Some(urls), Some(destinations)) if urls.len() == destinations.len() => urls.into_iter().zip(destinations).collect(),
+        _ => vec![(url.to_string(), destination.to_string())],
+    }
+}
+
+/// First 64-character hex token of a `.sha256` sidecar ("<hash>  <file name>" or just the hash)
+pub fn parse_sha256(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .next()
+        .filter(|token| token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|token| token.to_lowercase())
+}
+
+/// `Content-Range: bytes 100-199/200` -> (100, Some(200)); `bytes */200` -> (0, Some(200))
+pub fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let total = total.trim().parse().ok();
+    if range.trim() == "*" {
+        return Some((0, total));
+    }
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total))
+}
+
+/// Byte counts of every file of a download, reported as one combined total
+pub struct DownloadProgress {
+    downloaded: Vec<AtomicU64>,
+    totals: Vec<AtomicU64>,
+    last_reported: AtomicU64,
+    on_progress: Box<dyn Fn(u64, u64) + Send + Sync>,
+}
+
+impl DownloadProgress {
+    /// `on_progress(downloaded, total)`; total stays 0 until every file's size is known
+    pub fn new(files: usize, on_progress: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        Self {
+            downloaded: (0..files).map(|_| AtomicU64::new(0)).collect(),
+            totals: (0..files).map(|_| AtomicU64::new(0)).collect(),
+            last_reported: AtomicU64::new(0),
+            on_progress: Box::new(on_progress),
+        }
+    }
+
+    fn set_total(&self, file: usize, total: u64) {
+        self.totals[file].store(total, Ordering::SeqCst);
+    }
+
+    fn set_downloaded(&self, file: usize, downloaded: u64) {
+        self.downloaded[file].store(downloaded, Ordering::SeqCst);
+        let (downloaded, total) = self.combined();
+        let last = self.last_reported.load(Ordering::SeqCst);
+        if downloaded.saturating_sub(last) >= PROGRESS_INTERVAL || last == 0 || downloaded == total {
+            self.last_reported.store(downloaded.max(1), Ordering::SeqCst);
+            (self.on_progress)(downloaded, total);
+        }
+    }
+
+    pub fn combined(&self) -> (u64, u64) {
+        let downloaded = self.downloaded.iter().map(|d| d.load(Ordering::SeqCst)).sum();
+        let totals: Vec<u64> = self.totals.iter().map(|t| t.load(Ordering::SeqCst)).collect();
+        let total = if totals.contains(&0) { 0 } else { totals.iter().sum() };
+        (downloaded, total)
+    }
+}
+
+enum FetchError {
+    Cancelled,
+    /// Connection problems; the next attempt resumes from the `.part` file
+    Retry(String),
+    Fatal(String),
+}
+
+/// Download `url` to `destination`. Split models fetch all their shards in parallel.
+/// Each file goes to a `.part` file first, resuming any earlier attempt with an HTTP
+/// Range request, and is renamed into place only after every file is complete and
+/// matches its SHA-256: `sha256` for a single file, otherwise the server's
+/// `<file>.sha256` sidecar when there is one. Cancelling keeps the `.part` files so a
+/// later call resumes them. Returns the path to load (the first shard for split models).
+pub async fn download_model(
+    client: &Client,
+    url: &str,
+    destination: &str,
+    sha256: Option<String>,
+    token: &CancelToken,
+    progress: &DownloadProgress,
+) -> Result<String, String> {
+    let files = download_files(url, destination);
+    if files.len() > 1 && sha256.is_some() {
+        return Err("sha256 yalnızca tek dosyalı modellerde kullanılabilir, shard'lar .sha256 sidecar dosyalarıyla doğrulanır".to_string());
+    }
+    if let Some(parent) = Path::new(destination).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Klasör oluşturulamadı: {}", e))?;
+    }
+    if files.len() > 1 {
+        info!("🧩 Split GGUF: {} shard paralel indirilecek", files.len());
+    }
+
+    let downloads = files.iter().enumerate().map(|(index, (url, destination))| {
+        let supplied = sha256.clone();
+        async move {
+            let expected = match supplied {
+                Some(hash) => Some(parse_sha256(&hash).ok_or_else(|| format!("Geçersiz SHA-256: {}", hash))?),
+                None => sidecar_sha256(client, url).await,
+            };
+            download_file(client, url, destination, expected, token, progress, index).await
+        }
+    });
+    futures_util::future::try_join_all(downloads).await?;
+
+    // Only complete, verified sets of files ever appear under the final names
+    for (_, destination) in &files {
+        fs::rename(part_path(destination), destination)
+            .map_err(|e| format!("Dosya yerine taşınamadı ({}): {}", destination, e))?;
+    }
+    info!("✅ İndirme tamamlandı: {}", files[0].1);
+    Ok(files[0].1.clone())
+}
+
+/// Hash published next to the file, e.g. `model.gguf.sha256`
+async fn sidecar_sha256(client: &Client, url: &str) -> Option<String> {
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, format!("?{}", query)),
+        None => (url, String::new()),
+    };
+    let response = client.get(format!("{}.sha256{}", path, query)).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let hash = parse_sha256(&response.text().await.ok()?);
+    if hash.is_some() {
+        info!("🔐 SHA-256 sidecar bulundu: {}.sha256", path);
+    }
+    hash
+}
+
+/// Fetch one file into its `.part` file with retries, then verify it
+async fn download_file(
+    client: &Client,
+    url: &str,
+    destination: &str,
+    expected_sha256: Option<String>,
+    token: &CancelToken,
+    progress: &DownloadProgress,
+    index: usize,
+) -> Result<(), String> {
+    let part = part_path(destination);
+    let mut attempt = 1;
+    loop {
+        match fetch_into_part(client, url, &part, token, progress, index).await {
+            Ok(()) => break,
+            Err(FetchError::Cancelled) => {
+                info!("🛑 İndirme iptal edildi, {} devam ettirilebilir", part);
+                return Err("İndirme iptal edildi".to_string());
+            }
+            Err(FetchError::Retry(e)) if attempt < MAX_ATTEMPTS => {
+                let delay = Duration::from_millis(RETRY_DELAY_MS << (attempt - 1));
+                warn!("⚠️ İndirme kesildi ({}), {:?} sonra kaldığı yerden devam edilecek: {}", e, delay, url);
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = token.cancelled() => return Err("İndirme iptal edildi".to_string()),
+                }
+                attempt += 1;
+            }
+            Err(FetchError::Retry(e)) | Err(FetchError::Fatal(e)) => {
+                error!("❌ İndirme başarısız: {} ({})", url, e);
+                return Err(e);
+            }
+        }
+    }
+
+    if let Some(expected) = expected_sha256 {
+        info!("🔐 SHA-256 doğrulanıyor: {}", destination);
+        let hash_path = part.clone();
+        let actual = tokio::task::spawn_blocking(move || sha256_file(&hash_path))
+            .await
+            .map_err(|e| format!("Hash hesaplanamadı: {}", e))?
+            .map_err(|e| format!("Hash hesaplanamadı: {}", e))?;
+        if actual != expected {
+            // A corrupt file cannot be resumed into a good one
+            let _ = fs::remove_file(&part);
+            return Err(format!("SHA-256 uyuşmuyor ({}): beklenen {}, inen {}", destination, expected, actual));
+        }
+        info!("✅ SHA-256 doğrulandı");
+    }
+    Ok(())
+}
+
+/// One request: resume the `.part` file from its current length, or start it over when
+/// the server does not support ranges
+async fn fetch_into_part(
+    client: &Client,
+    url: &str,
+    part: &str,
+    token: &CancelToken,
+    progress: &DownloadProgress,
+    index: usize,
+) -> Result<(), FetchError> {
+    if token.is_cancelled() {
+        return Err(FetchError::Cancelled);
+    }
+    let existing = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if existing > 0 {
+        info!("⏯️ {} byte mevcut, kaldığı yerden devam: {}", existing, url);
+        request = request.header(RANGE, format!("bytes={}-", existing));
+    }
+
+    let response = tokio::select! {
+        biased;
+        _ = token.cancelled() => return Err(FetchError::Cancelled),
+        response = request.send() => response.map_err(|e| FetchError::Retry(format!("İndirme başlatılamadı: {}", e)))?,
+    };
+    let content_range = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_content_range);
+
+    let (mut file, start, total) = match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            match content_range {
+                Some((start, total)) if start == existing => {
+                    let file = fs::OpenOptions::new()
+                        .append(true)
+                        .open(part)
+                        .map_err(|e| FetchError::Fatal(format!("Dosya açılamadı: {}", e)))?;
+                    let total = total.or(response.content_length().map(|len| existing + len));
+                    (file, existing, total)
+                }
+                _ => {
+                    let _ = fs::remove_file(part);
+                    return Err(FetchError::Retry("Sunucu beklenmeyen bir aralık döndürdü".to_string()));
+                }
+            }
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            // The part file already holds the whole file
+            if let Some((_, Some(total))) = content_range {
+                if total == existing {
+                    progress.set_total(index, total);
+                    progress.set_downloaded(index, total);
+                    return Ok(());
+                }
+            }
+            let _ = fs::remove_file(part);
+            return Err(FetchError::Retry("Kısmi dosya sunucudakiyle uyuşmuyor, baştan indiriliyor".to_string()));
+        }
+        status if status.is_success() => {
+            if existing > 0 {
+                info!("ℹ️ Sunucu Range desteklemiyor, baştan indiriliyor");
+            }
+            let file = fs::File::create(part).map_err(|e| FetchError::Fatal(format!("Dosya oluşturulamadı: {}", e)))?;
+            (file, 0, response.content_length())
+        }
+        status if status.is_server_error() => return Err(FetchError::Retry(format!("HTTP hatası: {}", status))),
+        status => return Err(FetchError::Fatal(format!("HTTP hatası: {}", status))),
+    };
+
+    if let Some(total) = total {
+        progress.set_total(index, total);
+    }
+    progress.set_downloaded(index, start);
+
+    let mut downloaded = start;
+    let mut stream = response.bytes_stream();
+    loop {
+        let chunk = tokio::select! {
+            biased;
+            _ = token.cancelled() => {
+                let _ = file.flush();
+                return Err(FetchError::Cancelled);
+            }
+            chunk = stream.next() => chunk,
+        };
+        let Some(chunk) = chunk else { break };
+        let chunk = chunk.map_err(|e| FetchError::Retry(format!("İndirme hatası: {}", e)))?;
+        file.write_all(&chunk).map_err(|e| FetchError::Fatal(format!("Yazma hatası: {}", e)))?;
+        downloaded += chunk.len() as u64;
+        progress.set_downloaded(index, downloaded);
+    }
+    file.sync_all().map_err(|e| FetchError::Fatal(format!("Yazma hatası: {}", e)))?;
+
+    match total {
+        Some(total) if downloaded != total => {
+            Err(FetchError::Retry(format!("Bağlantı erken kapandı ({} / {} byte)", downloaded, total)))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn sha256_file(path: &str) -> std::io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// --------------------
+// GGUF MODEL DOWNLOAD
+// --------------------
+
+/// 📥 Download a GGUF model (all shards of a split model) with resume, optional SHA-256
+/// verification and `cancel_download` support. Progress goes out as `download-progress`.
+#[tauri::command]
+pub async fn download_gguf_model(
+    url: String,
+    destination: String,
+    app: AppHandle,
+    sha256: Option<String>,      // 🆕 Beklenen hash; yoksa sunucudaki .sha256 sidecar denenir
+    download_id: Option<String>, // 🆕 cancel_download için, varsayılan: url
+) -> Result<String, String> {
+    info!("🔵 GGUF model indiriliyor: {}", url);
+    info!("📁 Hedef: {}", destination);
+
+    let id = download_id.unwrap_or_else(|| url.clone());
+    let download = register_download(&id)?;
+
+    let (event_url, event_id, emitter) = (url.clone(), id.clone(), app.clone());
+    let progress = DownloadProgress::new(download_files(&url, &destination).len(), move |downloaded, total| {
+        let percent = if total > 0 { downloaded as f64 / total as f64 * 100.0 } else { 0.0 };
+        info!("📊 Progress: {:.1}% ({} / {} bytes)", percent, downloaded, total);
+        let payload = json!({
+            "url": event_url,
+            "download_id": event_id,
+            "downloaded": downloaded,
+            "total": total,
+            // 100 only once the files are verified and in place
+            "progress": percent.min(99.9),
+        });
+        if let Err(e) = emitter.emit("download-progress", payload) {
+            error!("❌ Event emit hatası: {}", e);
+        }
+    });
+
+    let path = download_model(&Client::new(), &url, &destination, sha256, &download.token, &progress).await?;
+
+    let (downloaded, total) = progress.combined();
+    let _ = app.emit("download-progress", json!({
+        "url": url,
+        "download_id": id,
+        "downloaded": downloaded,
+        "total": total.max(downloaded),
+        "progress": 100.0
+    }));
+    Ok(path)
+}
+
+/// 🛑 Stop a running download; its `.part` files stay so the next call resumes them
+#[tauri::command]
+pub async fn cancel_download(download_id: String) -> Result<bool, String> {
+    let token = ACTIVE_DOWNLOADS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(&download_id)
+        .cloned();
+    match token {
+        Some(token) => {
+            info!("🛑 İndirme iptal ediliyor: {}", download_id);
+            token.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_split_shards() {
+        let shards = split_shards("https://hf.co/m/resolve/main/qwen-q4-00001-of-00003.gguf?download=true").unwrap();
+        assert_eq!(shards.len(), 3);
+        assert_eq!(shards[2], "https://hf.co/m/resolve/main/qwen-q4-00003-of-00003.gguf?download=true");
+        assert_eq!(split_shards("/models/qwen-00002-of-00002.gguf").unwrap()[0], "/models/qwen-00001-of-00002.gguf");
+        assert!(split_shards("/models/qwen.gguf").is_none());
+
+        // A split URL saved under a plain name is fetched as that one file
+        let url = "https://hf.co/m/resolve/main/qwen-00001-of-00003.gguf";
+        assert_eq!(download_files(url, "/models/qwen-00001-of-00003.gguf").len(), 3);
+        assert_eq!(download_files(url, "/models/qwen.gguf"), vec![(url.to_string(), "/models/qwen.gguf".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(parse_content_range("bytes 100-199/200"), Some((100, Some(200))));
+        assert_eq!(parse_content_range("bytes 0-9/*"), Some((0, None)));
+        assert_eq!(parse_content_range("bytes */200"), Some((0, Some(200))));
+        assert_eq!(parse_sha256(&format!("{}  model.gguf\n", "AB".repeat(32))), Some("ab".repeat(32)));
+        assert_eq!(parse_sha256("not-a-hash"), None);
+    }
+
+    /// Serves `files` with Range support. The first plain request for each path is cut
+    /// off halfway when `drop_first` is set. Returns the base URL and the request log.
+    fn serve(files: HashMap<String, Vec<u8>>, drop_first: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let requests = log.clone();
+        std::thread::spawn(move || {
+            let mut dropped = std::collections::HashSet::new();
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let mut range_start = None;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        range_start = value.trim().trim_end_matches('-').parse::<usize>().ok();
+                    }
+                }
+                requests.lock().unwrap().push(format!("{} {:?}", path, range_start));
+
+                let Some(body) = files.get(&path) else {
+                    let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                    continue;
+                };
+                let head = match range_start {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                        body.len() - start, start, body.len() - 1, body.len()
+                    ),
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()),
+                };
+                let start = range_start.unwrap_or(0);
+                let end = if drop_first && range_start.is_none() && dropped.insert(path.clone()) { body.len() / 2 } else { body.len() };
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body[start..end]);
+            }
+        });
+        (base, log)
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("corex-download-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn body(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+    }
+
+    fn hex_sha256(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_dropped_connection() {
+        let data = body(300_000, 1);
+        let (base, log) = serve(HashMap::from([("/model.gguf".to_string(), data.clone())]), true);
+        let dest = temp_dir().join("model.gguf").to_string_lossy().to_string();
+
+        let progress = DownloadProgress::new(1, |_, _| {});
+        let token = CancelToken::default();
+        let path = download_model(&Client::new(), &format!("{}/model.gguf", base), &dest, Some(hex_sha256(&data)), &token, &progress)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), data);
+        assert!(!Path::new(&part_path(&dest)).exists());
+        // Second request resumed at the half that had arrived
+        assert!(log.lock().unwrap().contains(&"/model.gguf Some(150000)".to_string()));
+        assert_eq!(progress.combined(), (300_000, 300_000));
+    }
+
+    #[tokio::test]
+    async fn test_sidecar_hash_mismatch_is_rejected() {
+        let data = body(10_000, 2);
+        let files = HashMap::from([
+            ("/model.gguf".to_string(), data),
+            ("/model.gguf.sha256".to_string(), format!("{}  model.gguf\n", "0".repeat(64)).into_bytes()),
+        ]);
+        let (base, _) = serve(files, false);
+        let dest = temp_dir().join("model.gguf").to_string_lossy().to_string();
+
+        let progress = DownloadProgress::new(1, |_, _| {});
+        let result = download_model(&Client::new(), &format!("{}/model.gguf", base), &dest, None, &CancelToken::default(), &progress).await;
+
+        assert!(result.unwrap_err().contains("SHA-256"));
+        assert!(!Path::new(&dest).exists());
+        assert!(!Path::new(&part_path(&dest)).exists());
+    }
+
+    #[tokio::test]
+    async fn test_split_shards_download_in_parallel() {
+        let (first, second) = (body(50_000, 3), body(20_000, 4));
+        let files = HashMap::from([
+            ("/m-00001-of-00002.gguf".to_string(), first.clone()),
+            ("/m-00002-of-00002.gguf".to_string(), second.clone()),
+        ]);
+        let (base, _) = serve(files, true);
+        let dir = temp_dir();
+        let dest = dir.join("m-00001-of-00002.gguf").to_string_lossy().to_string();
+
+        let progress = DownloadProgress::new(2, |_, _| {});
+        let url = format!("{}/m-00001-of-00002.gguf", base);
+        let path = download_model(&Client::new(), &url, &dest, None, &CancelToken::default(), &progress).await.unwrap();
+
+        assert_eq!(path, dest);
+        assert_eq!(fs::read(dir.join("m-00001-of-00002.gguf")).unwrap(), first);
+        assert_eq!(fs::read(dir.join("m-00002-of-00002.gguf")).unwrap(), second);
+        assert_eq!(progress.combined(), (70_000, 70_000));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_download_keeps_nothing_in_place() {
+        let (base, _) = serve(HashMap::from([("/model.gguf".to_string(), body(1000, 5))]), false);
+        let dest = temp_dir().join("model.gguf").to_string_lossy().to_string();
+
+        let guard = register_download("test-cancel").unwrap();
+        assert!(register_download("test-cancel").is_err());
+        assert!(cancel_download("test-cancel".to_string()).await.unwrap());
+
+        let progress = DownloadProgress::new(1, |_, _| {});
+        let result = download_model(&Client::new(), &format!("{}/model.gguf", base), &dest, None, &guard.token, &progress).await;
+        assert!(result.unwrap_err().contains("iptal"));
+        assert!(!Path::new(&dest).exists());
+
+        drop(guard);
+        assert!(!cancel_download("test-cancel".to_string()).await.unwrap());
+    }
+}
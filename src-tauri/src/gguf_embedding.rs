@@ -0,0 +1,80 @@
+// src-tauri/src/gguf_embedding.rs
+// Options for in-process embeddings with a GGUF embedding model (bge, nomic-embed, ...)
+
+use serde::{Deserialize, Serialize};
+
+/// How token embeddings are pooled into one vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingPooling {
+    /// Whatever the GGUF metadata declares (`<arch>.pooling_type`)
+    #[default]
+    Model,
+    Mean,
+    /// First token, used by BERT-style models such as bge
+    Cls,
+    /// Last token, used by decoder-based embedding models
+    Last,
+}
+
+/// Selects a GGUF model from the pool instead of the HTTP embeddings endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GgufEmbeddingOptions {
+    /// Model path as passed to `load_gguf_model`
+    pub model_path: String,
+    #[serde(default)]
+    pub pooling: EmbeddingPooling,
+    /// L2-normalize the vector so dot product equals cosine similarity
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+}
+
+fn default_normalize() -> bool {
+    true
+}
+
+/// Where an embedding comes from: the pooled GGUF model in `gguf` when set, otherwise the HTTP `endpoint`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingSource {
+    /// OpenAI-style `/v1/embeddings` URL, LM Studio on localhost by default
+    pub endpoint: Option<String>,
+    pub gguf: Option<GgufEmbeddingOptions>,
+}
+
+/// Scale `v` to unit length; zero vectors are left unchanged
+pub fn l2_normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_l2_normalize() {
+        let mut v = vec![3.0, 4.0];
+        l2_normalize(&mut v);
+        assert_eq!(v, vec![0.6, 0.8]);
+
+        let mut zero = vec![0.0, 0.0];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_options_defaults() {
+        let options: GgufEmbeddingOptions =
+            serde_json::from_str(r#"{ "model_path": "/models/bge-small-en-v1.5-q8_0.gguf" }"#).unwrap();
+        assert_eq!(options.pooling, EmbeddingPooling::Model);
+        assert!(options.normalize);
+
+        let options: GgufEmbeddingOptions =
+            serde_json::from_str(r#"{ "model_path": "m.gguf", "pooling": "cls", "normalize": false }"#).unwrap();
+        assert_eq!(options.pooling, EmbeddingPooling::Cls);
+        assert!(!options.normalize);
+    }
+}
@@ -0,0 +1,387 @@
+// src-tauri/src/gguf_fim.rs
+// Fill-in-the-middle prompting for code models (Qwen2.5-Coder, DeepSeek-Coder, StarCoder, CodeLlama)
+
+use serde::{Deserialize, Serialize};
+
+use crate::gguf_header::GgufHeader;
+use crate::gguf_metrics::GenerationMetrics;
+use crate::gguf_scheduler::ScheduleOptions;
+
+/// Code model families with a known FIM prompt format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FimFamily {
+    QwenCoder,
+    DeepSeekCoder,
+    /// Also CodeQwen 1.5, which reuses the StarCoder tokens
+    StarCoder,
+    CodeLlama,
+}
+
+impl FimFamily {
+    /// Guess the family from a model name, file name or architecture
+    pub fn detect(name: &str) -> Option<Self> {
+        let name: String = name.to_lowercase().chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        if name.contains("codeqwen") || name.contains("starcoder") {
+            Some(FimFamily::StarCoder)
+        } else if name.contains("qwen") && name.contains("coder") {
+            Some(FimFamily::QwenCoder)
+        } else if name.contains("deepseek") && name.contains("coder") {
+            Some(FimFamily::DeepSeekCoder)
+        } else if name.contains("codellama") {
+            Some(FimFamily::CodeLlama)
+        } else {
+            None
+        }
+    }
+
+    /// Prefix-suffix-middle prompt with the family's special tokens written out
+    pub fn render(self, prefix: &str, suffix: &str) -> String {
+        match self {
+            FimFamily::QwenCoder => format!("<|fim_prefix|>{}<|fim_suffix|>{}<|fim_middle|>", prefix, suffix),
+            FimFamily::DeepSeekCoder => format!("<｜fim▁begin｜>{}<｜fim▁hole｜>{}<｜fim▁end｜>", prefix, suffix),
+            FimFamily::StarCoder => format!("<fim_prefix>{}<fim_suffix>{}<fim_middle>", prefix, suffix),
+            FimFamily::CodeLlama => format!("<PRE> {} <SUF>{} <MID>", prefix, suffix),
+        }
+    }
+
+    /// Tokens that end the middle, for servers that print special tokens as text
+    fn stop_strings(self) -> &'static [&'static str] {
+        match self {
+            FimFamily::QwenCoder => &["<|endoftext|>", "<|fim_pad|>", "<|file_sep|>", "<|repo_name|>", "<|fim_prefix|>", "<|im_end|>"],
+            FimFamily::DeepSeekCoder => &["<|EOT|>", "<｜end▁of▁sentence｜>", "<｜fim▁begin｜>"],
+            FimFamily::StarCoder => &["<|endoftext|>", "<file_sep>", "<fim_prefix>", "<fim_pad>"],
+            FimFamily::CodeLlama => &["<EOT>", "<PRE>"],
+        }
+    }
+}
+
+/// FIM special token IDs from the GGUF tokenizer metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FimTokenIds {
+    pub prefix: i32,
+    pub suffix: i32,
+    pub middle: i32,
+}
+
+/// How a pooled model is prompted for FIM: by token ID when the GGUF declares
+/// its FIM tokens, otherwise by the family's text format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FimTemplate {
+    pub family: Option<FimFamily>,
+    pub token_ids: Option<FimTokenIds>,
+}
+
+impl FimTemplate {
+    /// None when the model has neither FIM metadata nor a recognised name
+    pub fn from_header(header: &GgufHeader, file_name: &str) -> Option<Self> {
+        let id = |keys: [&str; 2]| keys.iter().find_map(|key| header.get_u64(key)).map(|id| id as i32);
+        let token_ids = match (
+            id(["tokenizer.ggml.fim_pre_token_id", "tokenizer.ggml.prefix_token_id"]),
+            id(["tokenizer.ggml.fim_suf_token_id", "tokenizer.ggml.suffix_token_id"]),
+            id(["tokenizer.ggml.fim_mid_token_id", "tokenizer.ggml.middle_token_id"]),
+        ) {
+            (Some(prefix), Some(suffix), Some(middle)) => Some(FimTokenIds { prefix, suffix, middle }),
+            _ => None,
+        };
+
+        let family = ["general.name", "general.basename"]
+            .iter()
+            .filter_map(|key| header.get_str(key))
+            .chain(header.architecture())
+            .chain(std::iter::once(file_name))
+            .find_map(FimFamily::detect);
+
+        if token_ids.is_none() && family.is_none() {
+            return None;
+        }
+        Some(Self { family, token_ids })
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        FimFamily::detect(name).map(|family| Self { family: Some(family), token_ids: None })
+    }
+}
+
+/// Optional knobs of a `complete_fim` request
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FimOptions {
+    /// LM Studio / Ollama / llama.cpp server to use instead of the model pool
+    pub base_url: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub generation_id: Option<String>,
+    /// Defaults to interactive priority
+    pub schedule: Option<ScheduleOptions>,
+}
+
+/// Result of a `complete_fim` request
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FimCompletion {
+    /// Text that goes between the prefix and the suffix
+    pub content: String,
+    pub cancelled: bool,
+    /// Timings of a pooled model's run, None when an HTTP provider answered
+    pub metrics: Option<GenerationMetrics>,
+}
+
+/// How far a completion may reach
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FimScope {
+    /// Code follows the cursor on the same line, complete only that line
+    Line,
+    /// Complete up to the next blank line
+    Block,
+}
+
+impl FimScope {
+    /// Text after the cursor decides: closing brackets and quotes alone do not limit the completion
+    pub fn detect(suffix: &str) -> Self {
+        let rest_of_line = suffix.split('\n').next().unwrap_or_default();
+        if rest_of_line.chars().all(|c| c.is_whitespace() || ")]}>\"'`;,".contains(c)) {
+            FimScope::Block
+        } else {
+            FimScope::Line
+        }
+    }
+}
+
+/// Stop strings for a FIM request
+pub fn fim_stop_strings(family: Option<FimFamily>, scope: FimScope) -> Vec<String> {
+    let mut stop: Vec<String> = family
+        .map(|f| f.stop_strings().iter().map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    stop.push(match scope {
+        FimScope::Line => "\n".to_string(),
+        FimScope::Block => "\n\n".to_string(),
+    });
+    stop
+}
+
+/// Keep the end of `prefix` and the start of `suffix` within `budget` tokens as measured
+/// by `count`, cut at line boundaries. The suffix gets at most a quarter of the budget.
+pub fn trim_context<'a>(prefix: &'a str, suffix: &'a str, budget: usize, count: &dyn Fn(&str) -> usize) -> (&'a str, &'a str) {
+    let suffix_tokens = count(suffix);
+    if count(prefix) + suffix_tokens <= budget {
+        return (prefix, suffix);
+    }
+    let suffix = head(suffix, suffix_tokens.min(budget / 4), count);
+    let prefix = tail(prefix, budget.saturating_sub(count(suffix)), count);
+    (prefix, suffix)
+}
+
+/// Longest end of `text` within `budget` tokens: whole lines, or the end of the
+/// last line when not even that fits
+fn tail<'a>(text: &'a str, budget: usize, count: &dyn Fn(&str) -> usize) -> &'a str {
+    let fits = |start: &usize| count(&text[*start..]) <= budget;
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let first = line_starts.partition_point(|start| !fits(start));
+    if let Some(start) = line_starts.get(first) {
+        return &text[*start..];
+    }
+    let last_line = line_starts[line_starts.len() - 1];
+    let char_starts: Vec<usize> = text[last_line..]
+        .char_indices()
+        .map(|(i, _)| last_line + i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let first = char_starts.partition_point(|start| !fits(start)).min(char_starts.len() - 1);
+    &text[char_starts[first]..]
+}
+
+/// Longest start of `text` within `budget` tokens: whole lines, or the start of the
+/// first line when not even that fits
+fn head<'a>(text: &'a str, budget: usize, count: &dyn Fn(&str) -> usize) -> &'a str {
+    let fits = |end: &usize| count(&text[..*end]) <= budget;
+    let line_ends: Vec<usize> = text
+        .match_indices('\n')
+        .map(|(i, _)| i + 1)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let fitting = line_ends.partition_point(fits);
+    if fitting > 0 {
+        return &text[..line_ends[fitting - 1]];
+    }
+    let char_ends: Vec<usize> = text[..line_ends[0]].char_indices().map(|(i, _)| i).collect();
+    let fitting = char_ends.partition_point(fits);
+    &text[..char_ends[fitting.saturating_sub(1)]]
+}
+
+/// Overlaps with the suffix at least this many characters long are cut without checking brackets
+const MIN_BLIND_OVERLAP: usize = 3;
+
+/// Only the new text: models tend to run on into the code after the cursor,
+/// so a repeated suffix line and an overlap with the suffix are cut off
+pub fn clean_middle(prefix: &str, middle: &str, suffix: &str) -> String {
+    let mut text = middle;
+
+    // A whole line of the suffix came back
+    if let Some(anchor) = suffix.lines().map(str::trim).find(|line| !line.is_empty()) {
+        if anchor.chars().count() >= 4 {
+            let mut offset = 0;
+            for line in text.split_inclusive('\n') {
+                if offset > 0 && line.trim() == anchor {
+                    text = &text[..offset];
+                    break;
+                }
+                offset += line.len();
+            }
+        }
+    }
+
+    // The middle ends with the beginning of the suffix, e.g. a closing bracket. A short
+    // overlap can also be correct code (`bar()` before `)`), so it is only cut when it
+    // closes a bracket or quote the suffix closes again.
+    let first_line = suffix.split('\n').next().unwrap_or_default();
+    if let Some(k) = (1..=first_line.len())
+        .rev()
+        .filter(|k| first_line.is_char_boundary(*k))
+        .find(|k| text.ends_with(&first_line[..*k]))
+    {
+        let stripped = &text[..text.len() - k];
+        if first_line[..k].chars().count() >= MIN_BLIND_OVERLAP
+            || pairing_errors(prefix, stripped, first_line) < pairing_errors(prefix, text, first_line)
+        {
+            text = stripped;
+        }
+    }
+
+    // Trailing blank lines, but not spaces the suffix may rely on
+    let trimmed = text.trim_end();
+    if text[trimmed.len()..].contains('\n') {
+        text = trimmed;
+    }
+    text.to_string()
+}
+
+/// Closing brackets without a matching opener in the middle and the rest of the cursor
+/// line, plus quote characters left unpaired on the cursor line. Rough on purpose:
+/// strings and comments are not parsed, only the difference between two middles matters.
+fn pairing_errors(prefix: &str, middle: &str, line_rest: &str) -> usize {
+    let opener = |close: char| match close {
+        ')' => '(',
+        ']' => '[',
+        _ => '{',
+    };
+    let mut open = Vec::new();
+    let mut errors = 0;
+    let scanned = prefix.chars().map(|c| (c, false)).chain(middle.chars().chain(line_rest.chars()).map(|c| (c, true)));
+    for (c, counted) in scanned {
+        match c {
+            '(' | '[' | '{' => open.push(c),
+            ')' | ']' | '}' if open.last() == Some(&opener(c)) => {
+                open.pop();
+            }
+            ')' | ']' | '}' if counted => errors += 1,
+            _ => {}
+        }
+    }
+
+    let line_start = prefix.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let cursor_line = format!("{}{}{}", &prefix[line_start..], middle, line_rest);
+    errors + ['"', '\'', '`'].iter().filter(|q| cursor_line.matches(**q).count() % 2 == 1).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_header::tests::GgufBuilder;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_detect_family() {
+        assert_eq!(FimFamily::detect("Qwen2.5-Coder-7B-Instruct"), Some(FimFamily::QwenCoder));
+        assert_eq!(FimFamily::detect("qwen2.5-coder:1.5b"), Some(FimFamily::QwenCoder));
+        assert_eq!(FimFamily::detect("deepseek-coder-6.7b-base.Q4_K_M.gguf"), Some(FimFamily::DeepSeekCoder));
+        assert_eq!(FimFamily::detect("starcoder2"), Some(FimFamily::StarCoder));
+        assert_eq!(FimFamily::detect("CodeQwen1.5-7B"), Some(FimFamily::StarCoder));
+        assert_eq!(FimFamily::detect("code-llama-13b"), Some(FimFamily::CodeLlama));
+        assert_eq!(FimFamily::detect("Llama-3.1-8B-Instruct"), None);
+
+        assert_eq!(
+            FimFamily::QwenCoder.render("fn a() {", "}"),
+            "<|fim_prefix|>fn a() {<|fim_suffix|>}<|fim_middle|>"
+        );
+        assert_eq!(FimFamily::CodeLlama.render("a", "b"), "<PRE> a <SUF>b <MID>");
+    }
+
+    #[test]
+    fn test_template_from_metadata() {
+        let read = |bytes: Vec<u8>| GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap();
+
+        let qwen = read(
+            GgufBuilder::new()
+                .kv_str("general.architecture", "qwen2")
+                .kv_str("general.name", "Qwen2.5 Coder 7B Instruct")
+                .kv_u32("tokenizer.ggml.fim_pre_token_id", 151659)
+                .kv_u32("tokenizer.ggml.fim_suf_token_id", 151661)
+                .kv_u32("tokenizer.ggml.fim_mid_token_id", 151660)
+                .build(),
+        );
+        let template = FimTemplate::from_header(&qwen, "model.gguf").unwrap();
+        assert_eq!(template.family, Some(FimFamily::QwenCoder));
+        assert_eq!(template.token_ids, Some(FimTokenIds { prefix: 151659, suffix: 151661, middle: 151660 }));
+
+        // Older conversions only name the model
+        let old = read(GgufBuilder::new().kv_str("general.architecture", "starcoder").build());
+        let template = FimTemplate::from_header(&old, "model.gguf").unwrap();
+        assert_eq!(template, FimTemplate { family: Some(FimFamily::StarCoder), token_ids: None });
+
+        let chat = read(GgufBuilder::new().kv_str("general.architecture", "llama").build());
+        assert!(FimTemplate::from_header(&chat, "llama-3-8b.gguf").is_none());
+    }
+
+    #[test]
+    fn test_scope_and_stops() {
+        assert_eq!(FimScope::detect("\n}\n"), FimScope::Block);
+        assert_eq!(FimScope::detect(");\n"), FimScope::Block);
+        assert_eq!(FimScope::detect(" + offset;\n"), FimScope::Line);
+
+        let stop = fim_stop_strings(Some(FimFamily::CodeLlama), FimScope::Line);
+        assert_eq!(stop, vec!["<EOT>", "<PRE>", "\n"]);
+        assert_eq!(fim_stop_strings(None, FimScope::Block), vec!["\n\n"]);
+    }
+
+    #[test]
+    fn test_trim_context_keeps_text_near_cursor() {
+        let prefix = "line 1\nline 2\nline 3\n    let x = ";
+        let suffix = ";\nline 5\nline 6\n";
+        let chars = |text: &str| text.chars().count();
+        assert_eq!(trim_context(prefix, suffix, 1000, &chars), (prefix, suffix));
+
+        let (p, s) = trim_context(prefix, suffix, 27, &chars);
+        assert_eq!(s, ";\n");
+        assert_eq!(p, "line 3\n    let x = ");
+
+        // Counted in tokens, not characters
+        let words = |text: &str| text.split_whitespace().count();
+        let (p, s) = trim_context(prefix, suffix, 7, &words);
+        assert_eq!(s, ";\n");
+        assert_eq!(p, "line 3\n    let x = ");
+
+        // A cursor line longer than the budget keeps its end
+        let (p, s) = trim_context("a b c d e f", "", 3, &chars);
+        assert_eq!((p, s), ("e f", ""));
+    }
+
+    #[test]
+    fn test_clean_middle() {
+        // Overlap with the closing bracket after the cursor
+        assert_eq!(clean_middle("f(", "a, b)", ")"), "a, b");
+        assert_eq!(clean_middle("print(\"", "hi\"", "\")"), "hi");
+        // A balanced middle keeps its own closing bracket
+        assert_eq!(clean_middle("foo(", "bar()", ")"), "bar()");
+        assert_eq!(clean_middle("foo(", "bar())", ")"), "bar()");
+        // Long overlaps are cut without looking at brackets
+        assert_eq!(clean_middle("if (", "ready) {", ") {"), "ready");
+        // The model went on to repeat the code after the cursor
+        let suffix = "\n    return total;\n}";
+        assert_eq!(clean_middle("", "let total = a + b;\n    return total;\n}", suffix), "let total = a + b;");
+        // Trailing spaces before the suffix are kept, trailing blank lines are not
+        assert_eq!(clean_middle("", "let x = ", "y;"), "let x = ");
+        assert_eq!(clean_middle("", "foo();\n\n", ""), "foo();");
+    }
+}
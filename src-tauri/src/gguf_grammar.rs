@@ -0,0 +1,604 @@
+// src-tauri/src/gguf_grammar.rs
+// Output constraints for GGUF decoding: raw GBNF grammars and JSON Schema -> GBNF conversion
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Constraint passed to the GGUF chat commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputConstraint {
+    /// A GBNF grammar in llama.cpp syntax
+    Grammar {
+        grammar: String,
+        #[serde(default = "default_root")]
+        root: String,
+    },
+    /// Output must validate against this JSON Schema
+    JsonSchema { schema: Value },
+    /// Any valid JSON value
+    Json,
+}
+
+fn default_root() -> String {
+    "root".to_string()
+}
+
+/// Grammar ready to hand to llama.cpp's grammar sampler
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledGrammar {
+    pub gbnf: String,
+    pub root: String,
+}
+
+impl OutputConstraint {
+    pub fn compile(&self) -> Result<CompiledGrammar, String> {
+        match self {
+            OutputConstraint::Grammar { grammar, root } => {
+                if grammar.trim().is_empty() {
+                    return Err("Grammar is empty".to_string());
+                }
+                Ok(CompiledGrammar { gbnf: grammar.clone(), root: root.clone() })
+            }
+            OutputConstraint::JsonSchema { schema } => Ok(CompiledGrammar {
+                gbnf: json_schema_to_gbnf(schema)?,
+                root: default_root(),
+            }),
+            OutputConstraint::Json => Ok(CompiledGrammar {
+                gbnf: json_schema_to_gbnf(&Value::Object(Default::default()))?,
+                root: default_root(),
+            }),
+        }
+    }
+}
+
+/// Largest minItems/maxItems a schema may ask for, every item is spelled out in the grammar
+pub const MAX_ARRAY_ITEMS: u64 = 256;
+
+// Primitive rules, only emitted when referenced
+const PRIMITIVES: &[(&str, &str)] = &[
+    // Bounded like llama.cpp, so a constrained model cannot indent forever
+    ("space", r#"| " " | "\n" [ \t]{0,20}"#),
+    ("boolean", r#"("true" | "false") space"#),
+    ("null", r#""null" space"#),
+    ("integer", r#"("-"? ([0-9] | [1-9] [0-9]*)) space"#),
+    ("number", r#"("-"? ([0-9] | [1-9] [0-9]*)) ("." [0-9]+)? ([eE] [-+]? [0-9]+)? space"#),
+    ("string", r#""\"" char* "\"" space"#),
+    ("char", r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F])"#),
+    ("value", r#"object | array | string | number | boolean | null"#),
+    ("object", r#""{" space (string ":" space value ("," space string ":" space value)*)? "}" space"#),
+    ("array", r#""[" space (value ("," space value)*)? "]" space"#),
+];
+
+/// Primitive rule dependencies, so referencing `value` also pulls in everything it uses
+fn primitive_deps(name: &str) -> &'static [&'static str] {
+    match name {
+        "boolean" | "null" | "integer" | "number" => &["space"],
+        "string" => &["char", "space"],
+        "value" => &["object", "array", "string", "number", "boolean", "null"],
+        "object" => &["string", "value", "space"],
+        "array" => &["value", "space"],
+        _ => &[],
+    }
+}
+
+/// Convert a JSON Schema into a GBNF grammar whose root rule is `root`.
+///
+/// Supports `type` (including type arrays), `properties`/`required`, `items` with
+/// `minItems`/`maxItems`, `enum`, `const`, `anyOf`/`oneOf`, and local `$ref`s into
+/// `#/definitions` or `#/$defs`. Unknown keywords are ignored; objects do not allow
+/// extra properties beyond the declared ones.
+pub fn json_schema_to_gbnf(schema: &Value) -> Result<String, String> {
+    let mut converter = SchemaConverter {
+        root_schema: schema,
+        rules: BTreeMap::new(),
+        primitives: HashSet::new(),
+        names: HashSet::from(["root".to_string()]),
+        refs: HashMap::new(),
+    };
+
+    let root_body = converter.visit(schema, "root")?;
+    converter.rules.insert("root".to_string(), root_body);
+
+    let mut out = String::new();
+    out.push_str(&format!("root ::= {}\n", converter.rules["root"]));
+    for (name, body) in &converter.rules {
+        if name != "root" {
+            out.push_str(&format!("{} ::= {}\n", name, body));
+        }
+    }
+
+    let mut emitted = HashSet::new();
+    let mut pending: Vec<&str> = converter.primitives.iter().copied().collect();
+    pending.sort();
+    while let Some(name) = pending.pop() {
+        if !emitted.insert(name) {
+            continue;
+        }
+        pending.extend(primitive_deps(name));
+    }
+    for (name, body) in PRIMITIVES {
+        if emitted.contains(name) {
+            out.push_str(&format!("{} ::= {}\n", name, body));
+        }
+    }
+
+    Ok(out)
+}
+
+struct SchemaConverter<'a> {
+    root_schema: &'a Value,
+    rules: BTreeMap<String, String>,
+    primitives: HashSet<&'static str>,
+    /// Rule names handed out so far, including ones whose body is still being built
+    names: HashSet<String>,
+    /// `$ref` path -> rule name, set before the target is visited so recursion terminates
+    refs: HashMap<String, String>,
+}
+
+impl<'a> SchemaConverter<'a> {
+    /// Return a GBNF expression matching `schema`; `name` seeds the names of helper rules
+    fn visit(&mut self, schema: &'a Value, name: &str) -> Result<String, String> {
+        let obj = match schema {
+            Value::Bool(true) => return Ok(self.primitive("value")),
+            Value::Bool(false) => return Err(format!("Schema at '{}' allows nothing", name)),
+            Value::Object(obj) => obj,
+            _ => return Err(format!("Invalid schema at '{}'", name)),
+        };
+
+        if let Some(reference) = obj.get("$ref").and_then(|r| r.as_str()) {
+            return self.visit_ref(reference);
+        }
+
+        if let Some(value) = obj.get("const") {
+            let literal = json_literal(value)?;
+            return Ok(self.with_space(format!("{} space", literal)));
+        }
+
+        if let Some(values) = obj.get("enum").and_then(|e| e.as_array()) {
+            if values.is_empty() {
+                return Err(format!("Empty enum at '{}'", name));
+            }
+            let alternatives = values
+                .iter()
+                .map(json_literal)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(self.with_space(format!("({}) space", alternatives.join(" | "))));
+        }
+
+        for key in ["anyOf", "oneOf"] {
+            if let Some(options) = obj.get(key).and_then(|o| o.as_array()) {
+                let mut alternatives = Vec::new();
+                for (i, option) in options.iter().enumerate() {
+                    let rule = self.add_rule(&format!("{}-{}", name, i), option)?;
+                    alternatives.push(rule);
+                }
+                return Ok(alternatives.join(" | "));
+            }
+        }
+
+        match obj.get("type") {
+            Some(Value::String(t)) => self.visit_type(t, obj, name),
+            Some(Value::Array(types)) => {
+                let mut alternatives = Vec::new();
+                for t in types {
+                    let t = t.as_str().ok_or_else(|| format!("Invalid type list at '{}'", name))?;
+                    let rule = self.unique_name(&format!("{}-{}", name, t));
+                    let body = self.visit_type(t, obj, &rule)?;
+                    self.rules.insert(rule.clone(), body);
+                    alternatives.push(rule);
+                }
+                Ok(alternatives.join(" | "))
+            }
+            _ if obj.contains_key("properties") => self.visit_type("object", obj, name),
+            _ if obj.contains_key("items") => self.visit_type("array", obj, name),
+            _ => Ok(self.primitive("value")),
+        }
+    }
+
+    fn visit_type(
+        &mut self,
+        t: &str,
+        obj: &'a serde_json::Map<String, Value>,
+        name: &str,
+    ) -> Result<String, String> {
+        match t {
+            "string" | "number" | "integer" | "boolean" | "null" => Ok(self.primitive(match t {
+                "string" => "string",
+                "number" => "number",
+                "integer" => "integer",
+                "boolean" => "boolean",
+                _ => "null",
+            })),
+            "object" => self.visit_object(obj, name),
+            "array" => self.visit_array(obj, name),
+            other => Err(format!("Unsupported schema type '{}' at '{}'", other, name)),
+        }
+    }
+
+    fn visit_object(&mut self, obj: &'a serde_json::Map<String, Value>, name: &str) -> Result<String, String> {
+        let properties = match obj.get("properties").and_then(|p| p.as_object()) {
+            Some(p) if !p.is_empty() => p,
+            _ => return Ok(self.primitive("object")),
+        };
+        let required: HashSet<&str> = obj
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+
+        let mut required_kvs = Vec::new();
+        let mut optional_kvs = Vec::new();
+        for (key, prop_schema) in properties {
+            let value_rule = self.add_rule(&format!("{}-{}", name, key), prop_schema)?;
+            let kv = format!("{} space \":\" space {}", json_literal(&Value::String(key.clone()))?, value_rule);
+            if required.contains(key.as_str()) {
+                required_kvs.push(kv);
+            } else {
+                optional_kvs.push(kv);
+            }
+        }
+        self.primitives.insert("space");
+
+        let mut body = String::from("\"{\" space ");
+        if !required_kvs.is_empty() {
+            body.push_str(&required_kvs.join(" \",\" space "));
+            for kv in &optional_kvs {
+                body.push_str(&format!(" (\",\" space {})?", kv));
+            }
+        } else {
+            // No required keys: any optional key may come first, later ones stay optional
+            let alternatives: Vec<String> = (0..optional_kvs.len())
+                .map(|i| {
+                    let mut alt = optional_kvs[i].clone();
+                    for kv in &optional_kvs[i + 1..] {
+                        alt.push_str(&format!(" (\",\" space {})?", kv));
+                    }
+                    alt
+                })
+                .collect();
+            body.push_str(&format!("({})?", alternatives.join(" | ")));
+        }
+        body.push_str(" \"}\" space");
+        Ok(body)
+    }
+
+    fn visit_array(&mut self, obj: &'a serde_json::Map<String, Value>, name: &str) -> Result<String, String> {
+        let item = match obj.get("items") {
+            Some(items) => self.add_rule(&format!("{}-item", name), items)?,
+            None => self.primitive("value"),
+        };
+        self.primitives.insert("space");
+
+        let min_items = obj.get("minItems").and_then(|v| v.as_u64()).unwrap_or(0);
+        let max_items = obj.get("maxItems").and_then(|v| v.as_u64());
+        if let Some(max) = max_items {
+            if max < min_items {
+                return Err(format!("maxItems < minItems at '{}'", name));
+            }
+        }
+        if min_items.max(max_items.unwrap_or(0)) > MAX_ARRAY_ITEMS {
+            return Err(format!("minItems/maxItems above {} at '{}'", MAX_ARRAY_ITEMS, name));
+        }
+
+        if max_items == Some(0) {
+            return Ok("\"[\" space \"]\" space".to_string());
+        }
+
+        let next = format!("\",\" space {}", item);
+        let items = {
+            // First item, then the remaining mandatory ones, then the optional tail
+            let mut seq = item.clone();
+            for _ in 1..min_items.max(1) {
+                seq.push_str(&format!(" {}", next));
+            }
+            match max_items {
+                Some(max) => {
+                    // Nested optionals: (next (next (next)?)?)?
+                    let optional = (max - min_items.max(1)) as usize;
+                    for _ in 0..optional {
+                        seq.push_str(" (");
+                        seq.push_str(&next);
+                    }
+                    seq.push_str(&")?".repeat(optional));
+                }
+                None => seq.push_str(&format!(" ({})*", next)),
+            }
+            if min_items == 0 {
+                format!("({})?", seq)
+            } else {
+                seq
+            }
+        };
+
+        Ok(format!("\"[\" space {} \"]\" space", items))
+    }
+
+    fn visit_ref(&mut self, reference: &str) -> Result<String, String> {
+        let path = reference
+            .strip_prefix("#/definitions/")
+            .or_else(|| reference.strip_prefix("#/$defs/"))
+            .ok_or_else(|| format!("Only local $refs are supported: {}", reference))?;
+        if let Some(rule_name) = self.refs.get(path) {
+            return Ok(rule_name.clone());
+        }
+
+        let target = self
+            .root_schema
+            .get("definitions")
+            .and_then(|d| d.get(path))
+            .or_else(|| self.root_schema.get("$defs").and_then(|d| d.get(path)))
+            .ok_or_else(|| format!("Unresolved $ref: {}", reference))?;
+
+        // Mark first so recursive schemas terminate
+        let rule_name = self.unique_name(&format!("ref-{}", path));
+        self.refs.insert(path.to_string(), rule_name.clone());
+        let body = self.visit(target, &rule_name)?;
+        self.rules.insert(rule_name.clone(), body);
+        Ok(rule_name)
+    }
+
+    /// Visit `schema` into its own named rule and return the rule name
+    fn add_rule(&mut self, name: &str, schema: &'a Value) -> Result<String, String> {
+        let rule_name = self.unique_name(name);
+        let body = self.visit(schema, &rule_name)?;
+        // Single rule references do not need a wrapper rule
+        if !body.contains(' ') {
+            return Ok(body);
+        }
+        self.rules.insert(rule_name.clone(), body);
+        Ok(rule_name)
+    }
+
+    /// Sanitized rule name, with a numeric suffix when another definition already has it
+    /// (e.g. the properties "a-b" and "a_b")
+    fn unique_name(&mut self, name: &str) -> String {
+        let base = sanitize(name);
+        let mut candidate = base.clone();
+        let mut suffix = 1;
+        while !self.names.insert(candidate.clone()) {
+            candidate = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        candidate
+    }
+
+    fn primitive(&mut self, name: &'static str) -> String {
+        self.primitives.insert(name);
+        name.to_string()
+    }
+
+    fn with_space(&mut self, expr: String) -> String {
+        self.primitives.insert("space");
+        expr
+    }
+}
+
+/// GBNF rule names may only contain letters, digits and dashes
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .collect()
+}
+
+/// GBNF literal matching the JSON serialization of `value`
+fn json_literal(value: &Value) -> Result<String, String> {
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    Ok(gbnf_literal(&json))
+}
+
+fn gbnf_literal(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule<'a>(grammar: &'a str, name: &str) -> &'a str {
+        let prefix = format!("{} ::= ", name);
+        grammar
+            .lines()
+            .find_map(|l| l.strip_prefix(prefix.as_str()))
+            .unwrap_or_else(|| panic!("rule {} missing in:\n{}", name, grammar))
+    }
+
+    /// Every referenced rule name must be defined
+    fn assert_closed(grammar: &str) {
+        let defined: HashSet<&str> = grammar.lines().filter_map(|l| l.split(" ::= ").next()).collect();
+        for line in grammar.lines() {
+            let body = line.split(" ::= ").nth(1).unwrap();
+            let mut in_literal = false;
+            let mut in_class = false;
+            let mut in_count = false;
+            let mut escaped = false;
+            let mut word = String::new();
+            for c in body.chars().chain(std::iter::once(' ')) {
+                if in_literal || in_class {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if (in_literal && c == '"') || (in_class && c == ']') {
+                        in_literal = false;
+                        in_class = false;
+                    }
+                    continue;
+                }
+                // Repetition counts like {0,20}
+                if in_count || c == '{' {
+                    in_count = c != '}';
+                    continue;
+                }
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    word.push(c);
+                    continue;
+                }
+                if !word.is_empty() {
+                    assert!(defined.contains(word.as_str()), "undefined rule '{}' in:\n{}", word, grammar);
+                    word.clear();
+                }
+                in_literal = c == '"';
+                in_class = c == '[';
+            }
+        }
+    }
+
+    #[test]
+    fn test_object_with_required_and_optional() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer" },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["name"]
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+
+        let root = rule(&grammar, "root");
+        assert!(root.starts_with(r#""{" space "\"name\"" space ":" space string"#));
+        assert!(root.contains(r#"("," space "\"age\"" space ":" space integer)?"#));
+        assert!(root.contains("root-tags"));
+        assert_eq!(rule(&grammar, "root-tags"), r#""[" space (string ("," space string)*)? "]" space"#);
+    }
+
+    #[test]
+    fn test_object_without_required_keys() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "a": { "type": "boolean" }, "b": { "type": "null" } }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(
+            rule(&grammar, "root"),
+            r#""{" space ("\"a\"" space ":" space boolean ("," space "\"b\"" space ":" space null)? | "\"b\"" space ":" space null)? "}" space"#
+        );
+    }
+
+    #[test]
+    fn test_enum_const_and_any_of() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "op": { "enum": ["add", "remove"] },
+                "version": { "const": 2 },
+                "target": { "anyOf": [{ "type": "string" }, { "type": "number" }] }
+            },
+            "required": ["op", "version", "target"]
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(rule(&grammar, "root-op"), r#"("\"add\"" | "\"remove\"") space"#);
+        assert_eq!(rule(&grammar, "root-version"), r#""2" space"#);
+        assert_eq!(rule(&grammar, "root-target"), "string | number");
+    }
+
+    #[test]
+    fn test_array_bounds() {
+        let schema = json!({ "type": "array", "items": { "type": "integer" }, "minItems": 1, "maxItems": 3 });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(
+            rule(&grammar, "root"),
+            r#""[" space integer ("," space integer ("," space integer)?)? "]" space"#
+        );
+
+        let at_cap = json!({ "type": "array", "maxItems": MAX_ARRAY_ITEMS });
+        assert_closed(&json_schema_to_gbnf(&at_cap).unwrap());
+        assert!(json_schema_to_gbnf(&json!({ "type": "array", "maxItems": 100_000_000 })).is_err());
+        assert!(json_schema_to_gbnf(&json!({ "type": "array", "minItems": MAX_ARRAY_ITEMS + 1 })).is_err());
+    }
+
+    #[test]
+    fn test_colliding_rule_names_stay_distinct() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "a-b": { "type": "array", "items": { "type": "string" } },
+                "a_b": { "type": "array", "items": { "type": "integer" } }
+            },
+            "required": ["a-b", "a_b"],
+            "$defs": { "x-y": { "enum": [1] }, "x_y": { "enum": [2] } }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+        assert!(rule(&grammar, "root").contains(r#""\"a-b\"" space ":" space root-a-b "," space "\"a_b\"" space ":" space root-a-b-1"#));
+        assert_eq!(rule(&grammar, "root-a-b"), r#""[" space (string ("," space string)*)? "]" space"#);
+        assert_eq!(rule(&grammar, "root-a-b-1"), r#""[" space (integer ("," space integer)*)? "]" space"#);
+
+        let refs = json!({
+            "anyOf": [{ "$ref": "#/$defs/x-y" }, { "$ref": "#/$defs/x_y" }, { "$ref": "#/$defs/x-y" }],
+            "$defs": { "x-y": { "enum": [1] }, "x_y": { "enum": [2] } }
+        });
+        let grammar = json_schema_to_gbnf(&refs).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(rule(&grammar, "root"), "ref-x-y | ref-x-y-1 | ref-x-y");
+        assert_eq!(rule(&grammar, "ref-x-y"), "(\"1\") space");
+        assert_eq!(rule(&grammar, "ref-x-y-1"), "(\"2\") space");
+    }
+
+    #[test]
+    fn test_recursive_ref() {
+        let schema = json!({
+            "$ref": "#/$defs/node",
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "value": { "type": "number" },
+                        "children": { "type": "array", "items": { "$ref": "#/$defs/node" } }
+                    },
+                    "required": ["value"]
+                }
+            }
+        });
+        let grammar = json_schema_to_gbnf(&schema).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(rule(&grammar, "root"), "ref-node");
+        assert!(rule(&grammar, "ref-node-children").contains("ref-node"));
+    }
+
+    #[test]
+    fn test_nullable_type_list_and_any_json() {
+        let grammar = json_schema_to_gbnf(&json!({ "type": ["string", "null"] })).unwrap();
+        assert_closed(&grammar);
+        assert_eq!(rule(&grammar, "root"), "root-string | root-null");
+
+        let any = OutputConstraint::Json.compile().unwrap();
+        assert_closed(&any.gbnf);
+        assert_eq!(rule(&any.gbnf, "root"), "value");
+        assert_eq!(rule(&any.gbnf, "space"), r#"| " " | "\n" [ \t]{0,20}"#);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(json_schema_to_gbnf(&json!({ "$ref": "https://example.com/schema" })).is_err());
+        assert!(json_schema_to_gbnf(&json!({ "$ref": "#/$defs/missing" })).is_err());
+        assert!(json_schema_to_gbnf(&json!({ "type": "tuple" })).is_err());
+        assert!(OutputConstraint::Grammar { grammar: " ".into(), root: "root".into() }.compile().is_err());
+    }
+
+    #[test]
+    fn test_literal_escaping() {
+        assert_eq!(gbnf_literal("a\"b\\c\n"), r#""a\"b\\c\n""#);
+        assert_eq!(sanitize("root-my_key.x"), "root-my-key-x");
+    }
+}
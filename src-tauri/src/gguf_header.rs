@@ -0,0 +1,766 @@
+// src-tauri/src/gguf_header.rs
+// GGUF v2/v3 header reader - key/value metadata and tensor infos without loading the model
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: u64 = 32;
+
+/// Arrays longer than this (e.g. tokenizer.ggml.tokens) are skipped, only their length is kept
+const MAX_ARRAY_VALUES: u64 = 1024;
+
+// Sanity limits so a corrupt file cannot make us allocate gigabytes
+const MAX_STRING_LEN: u64 = 64 * 1024 * 1024;
+const MAX_TENSOR_DIMS: u32 = 8;
+
+/// A single GGUF metadata value
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    String(String),
+    /// `values` is empty when the array was longer than `MAX_ARRAY_VALUES`
+    Array { item_type: u32, len: u64, values: Vec<GgufValue> },
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl GgufValue {
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) if v >= 0 => Some(v as u64),
+            GgufValue::I16(v) if v >= 0 => Some(v as u64),
+            GgufValue::I32(v) if v >= 0 => Some(v as u64),
+            GgufValue::I64(v) if v >= 0 => Some(v as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            GgufValue::F32(v) => Some(v as f64),
+            GgufValue::F64(v) => Some(v),
+            _ => self.as_u64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            GgufValue::U8(v) => json!(v),
+            GgufValue::I8(v) => json!(v),
+            GgufValue::U16(v) => json!(v),
+            GgufValue::I16(v) => json!(v),
+            GgufValue::U32(v) => json!(v),
+            GgufValue::I32(v) => json!(v),
+            GgufValue::F32(v) => json!(v),
+            GgufValue::Bool(v) => json!(v),
+            GgufValue::String(v) => json!(v),
+            GgufValue::U64(v) => json!(v),
+            GgufValue::I64(v) => json!(v),
+            GgufValue::F64(v) => json!(v),
+            GgufValue::Array { item_type, len, values } => {
+                if values.len() as u64 == *len {
+                    serde_json::Value::Array(values.iter().map(|v| v.to_json()).collect())
+                } else {
+                    json!({
+                        "type": "array",
+                        "item_type": value_type_name(*item_type),
+                        "len": len,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Tensor descriptor from the header (data is not read)
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufTensorInfo {
+    pub name: String,
+    pub dims: Vec<u64>,
+    pub ggml_type: u32,
+    /// Offset relative to the start of the tensor data section
+    pub offset: u64,
+}
+
+impl GgufTensorInfo {
+    pub fn n_elements(&self) -> u64 {
+        checked_product(&self.dims).unwrap_or(u64::MAX)
+    }
+
+    /// Size of the tensor data in bytes, `None` for unknown ggml types
+    pub fn size_bytes(&self) -> Option<u64> {
+        let (block_size, type_size) = ggml_type_block(self.ggml_type)?;
+        (self.n_elements() / block_size).checked_mul(type_size)
+    }
+}
+
+/// Parsed header of a single GGUF file
+#[derive(Debug, Clone)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub metadata: BTreeMap<String, GgufValue>,
+    pub tensors: Vec<GgufTensorInfo>,
+    /// Absolute file offset where tensor data begins
+    pub data_offset: u64,
+}
+
+impl GgufHeader {
+    pub fn read_file(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        Self::read_from(&mut reader)
+    }
+
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Self, String> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).map_err(|e| format!("Failed to read GGUF magic: {}", e))?;
+        if &magic != GGUF_MAGIC {
+            return Err("Not a GGUF file (bad magic)".to_string());
+        }
+
+        let version = read_u32(reader)?;
+        if version != 2 && version != 3 {
+            return Err(format!("Unsupported GGUF version: {} (only v2/v3 are supported)", version));
+        }
+
+        let tensor_count = read_u64(reader)?;
+        let kv_count = read_u64(reader)?;
+
+        let mut metadata = BTreeMap::new();
+        for _ in 0..kv_count {
+            let key = read_string(reader)?;
+            let value_type = read_u32(reader)?;
+            let value = read_value(reader, value_type)?;
+            metadata.insert(key, value);
+        }
+
+        let mut tensors = Vec::new();
+        for _ in 0..tensor_count {
+            let name = read_string(reader)?;
+            let n_dims = read_u32(reader)?;
+            if n_dims > MAX_TENSOR_DIMS {
+                return Err(format!("Tensor {} has invalid dimension count: {}", name, n_dims));
+            }
+            let mut dims = Vec::with_capacity(n_dims as usize);
+            for _ in 0..n_dims {
+                dims.push(read_u64(reader)?);
+            }
+            if checked_product(&dims).is_none() {
+                return Err(format!("GGUF tensor too large: {} {:?}", name, dims));
+            }
+            let ggml_type = read_u32(reader)?;
+            let offset = read_u64(reader)?;
+            tensors.push(GgufTensorInfo { name, dims, ggml_type, offset });
+        }
+
+        let alignment = metadata
+            .get("general.alignment")
+            .and_then(|v| v.as_u64())
+            .filter(|a| *a > 0)
+            .unwrap_or(DEFAULT_ALIGNMENT);
+        let header_end = reader.stream_position().map_err(|e| e.to_string())?;
+        let data_offset = header_end.div_ceil(alignment) * alignment;
+
+        Ok(Self { version, metadata, tensors, data_offset })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&GgufValue> {
+        self.metadata.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| v.as_str())
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(|v| v.as_u64())
+    }
+
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    /// Architecture-scoped key, e.g. `arch_u64("block_count")` -> `qwen2.block_count`
+    pub fn arch_u64(&self, suffix: &str) -> Option<u64> {
+        let arch = self.architecture()?;
+        self.get_u64(&format!("{}.{}", arch, suffix))
+    }
+
+    pub fn file_type(&self) -> Option<u64> {
+        self.get_u64("general.file_type")
+    }
+
+    /// Token count from the tokenizer array length or the `vocab_size` key
+    pub fn vocab_size(&self) -> Option<u64> {
+        match self.get("tokenizer.ggml.tokens") {
+            Some(GgufValue::Array { len, .. }) => Some(*len),
+            _ => self.arch_u64("vocab_size"),
+        }
+    }
+}
+
+/// Header of a (possibly split) GGUF model. Metadata comes from the first shard,
+/// tensors are collected from every shard.
+#[derive(Debug, Clone)]
+pub struct GgufModelHeader {
+    pub header: GgufHeader,
+    pub tensors: Vec<GgufTensorInfo>,
+    pub shard_paths: Vec<String>,
+    pub total_file_size: u64,
+}
+
+impl GgufModelHeader {
+    /// Read a model given any of its shard paths (or a single-file model)
+    pub fn read(path: &str) -> Result<Self, String> {
+        let first_path = crate::gguf::resolve_split_gguf_path(path);
+        let header = GgufHeader::read_file(&first_path)?;
+
+        let split_count = header.get_u64("split.count").unwrap_or(1).max(1);
+        let shard_paths = if split_count > 1 {
+            split_shard_paths(&first_path, split_count)?
+        } else {
+            vec![first_path.clone()]
+        };
+
+        let mut tensors = header.tensors.clone();
+        let mut total_file_size = std::fs::metadata(&first_path).map(|m| m.len()).unwrap_or(0);
+
+        for shard in shard_paths.iter().skip(1) {
+            if !Path::new(shard).exists() {
+                return Err(format!("Missing split part: {}", shard));
+            }
+            let shard_header = GgufHeader::read_file(shard)?;
+            tensors.extend(shard_header.tensors);
+            total_file_size += std::fs::metadata(shard).map(|m| m.len()).unwrap_or(0);
+        }
+
+        Ok(Self { header, tensors, shard_paths, total_file_size })
+    }
+
+    pub fn parameter_count(&self) -> u64 {
+        self.tensors.iter().fold(0, |sum, t| sum.saturating_add(t.n_elements()))
+    }
+
+    pub fn tensor_bytes(&self) -> u64 {
+        self.tensors.iter().filter_map(|t| t.size_bytes()).fold(0, u64::saturating_add)
+    }
+
+    /// Number of tensors per ggml type name, e.g. {"Q4_K": 193, "F32": 113}
+    pub fn tensor_type_counts(&self) -> BTreeMap<String, u64> {
+        let mut counts = BTreeMap::new();
+        for tensor in &self.tensors {
+            *counts.entry(ggml_type_name(tensor.ggml_type).to_string()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// All shard paths of a split model, given the first shard. Its name must follow the
+/// `-00001-of-0000N.gguf` pattern and agree with the header's `split.count`.
+pub fn split_shard_paths(first_path: &str, count: u64) -> Result<Vec<String>, String> {
+    let shards = crate::gguf_download::split_shards(first_path)
+        .ok_or_else(|| format!("split.count is {} but the file is not named as a split part: {}", count, first_path))?;
+    if shards.len() as u64 != count {
+        return Err(format!(
+            "split.count is {} but the file name says {} parts: {}",
+            count,
+            shards.len(),
+            first_path
+        ));
+    }
+    Ok(shards)
+}
+
+/// Human readable parameter count, e.g. 7_615_616_512 -> "7.6B"
+pub fn format_parameter_count(n: u64) -> String {
+    if n >= 1_000_000_000 {
+        format!("{:.1}B", n as f64 / 1e9)
+    } else if n >= 1_000_000 {
+        format!("{:.0}M", n as f64 / 1e6)
+    } else {
+        n.to_string()
+    }
+}
+
+/// (block size in elements, bytes per block) for a ggml tensor type
+pub fn ggml_type_block(ggml_type: u32) -> Option<(u64, u64)> {
+    Some(match ggml_type {
+        0 => (1, 4),      // F32
+        1 => (1, 2),      // F16
+        2 => (32, 18),    // Q4_0
+        3 => (32, 20),    // Q4_1
+        6 => (32, 22),    // Q5_0
+        7 => (32, 24),    // Q5_1
+        8 => (32, 34),    // Q8_0
+        9 => (32, 36),    // Q8_1
+        10 => (256, 84),  // Q2_K
+        11 => (256, 110), // Q3_K
+        12 => (256, 144), // Q4_K
+        13 => (256, 176), // Q5_K
+        14 => (256, 210), // Q6_K
+        15 => (256, 292), // Q8_K
+        16 => (256, 66),  // IQ2_XXS
+        17 => (256, 74),  // IQ2_XS
+        18 => (256, 98),  // IQ3_XXS
+        19 => (256, 50),  // IQ1_S
+        20 => (32, 18),   // IQ4_NL
+        21 => (256, 110), // IQ3_S
+        22 => (256, 82),  // IQ2_S
+        23 => (256, 136), // IQ4_XS
+        24 => (1, 1),     // I8
+        25 => (1, 2),     // I16
+        26 => (1, 4),     // I32
+        27 => (1, 8),     // I64
+        28 => (1, 8),     // F64
+        29 => (256, 56),  // IQ1_M
+        30 => (1, 2),     // BF16
+        34 => (256, 54),  // TQ1_0
+        35 => (256, 66),  // TQ2_0
+        39 => (32, 17),   // MXFP4
+        _ => return None,
+    })
+}
+
+pub fn ggml_type_name(ggml_type: u32) -> &'static str {
+    match ggml_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        6 => "Q5_0",
+        7 => "Q5_1",
+        8 => "Q8_0",
+        9 => "Q8_1",
+        10 => "Q2_K",
+        11 => "Q3_K",
+        12 => "Q4_K",
+        13 => "Q5_K",
+        14 => "Q6_K",
+        15 => "Q8_K",
+        16 => "IQ2_XXS",
+        17 => "IQ2_XS",
+        18 => "IQ3_XXS",
+        19 => "IQ1_S",
+        20 => "IQ4_NL",
+        21 => "IQ3_S",
+        22 => "IQ2_S",
+        23 => "IQ4_XS",
+        24 => "I8",
+        25 => "I16",
+        26 => "I32",
+        27 => "I64",
+        28 => "F64",
+        29 => "IQ1_M",
+        30 => "BF16",
+        34 => "TQ1_0",
+        35 => "TQ2_0",
+        39 => "MXFP4",
+        _ => "Unknown",
+    }
+}
+
+/// `general.file_type` (llama_ftype) -> quantization label
+pub fn file_type_name(file_type: u64) -> &'static str {
+    match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        36 => "TQ1_0",
+        37 => "TQ2_0",
+        38 => "MXFP4_MOE",
+        _ => "Unknown",
+    }
+}
+
+fn value_type_name(value_type: u32) -> &'static str {
+    match value_type {
+        0 => "u8",
+        1 => "i8",
+        2 => "u16",
+        3 => "i16",
+        4 => "u32",
+        5 => "i32",
+        6 => "f32",
+        7 => "bool",
+        8 => "string",
+        9 => "array",
+        10 => "u64",
+        11 => "i64",
+        12 => "f64",
+        _ => "unknown",
+    }
+}
+
+fn read_value<R: Read + Seek>(reader: &mut R, value_type: u32) -> Result<GgufValue, String> {
+    Ok(match value_type {
+        0 => GgufValue::U8(read_bytes::<R, 1>(reader)?[0]),
+        1 => GgufValue::I8(read_bytes::<R, 1>(reader)?[0] as i8),
+        2 => GgufValue::U16(u16::from_le_bytes(read_bytes(reader)?)),
+        3 => GgufValue::I16(i16::from_le_bytes(read_bytes(reader)?)),
+        4 => GgufValue::U32(read_u32(reader)?),
+        5 => GgufValue::I32(i32::from_le_bytes(read_bytes(reader)?)),
+        6 => GgufValue::F32(f32::from_le_bytes(read_bytes(reader)?)),
+        7 => GgufValue::Bool(read_bytes::<R, 1>(reader)?[0] != 0),
+        8 => GgufValue::String(read_string(reader)?),
+        9 => {
+            let item_type = read_u32(reader)?;
+            if item_type == 9 {
+                return Err("Nested GGUF arrays are not supported".to_string());
+            }
+            let len = read_u64(reader)?;
+            let mut values = Vec::new();
+            if len <= MAX_ARRAY_VALUES {
+                for _ in 0..len {
+                    values.push(read_value(reader, item_type)?);
+                }
+            } else {
+                skip_array(reader, item_type, len)?;
+            }
+            GgufValue::Array { item_type, len, values }
+        }
+        10 => GgufValue::U64(read_u64(reader)?),
+        11 => GgufValue::I64(i64::from_le_bytes(read_bytes(reader)?)),
+        12 => GgufValue::F64(f64::from_le_bytes(read_bytes(reader)?)),
+        other => return Err(format!("Unknown GGUF value type: {}", other)),
+    })
+}
+
+fn skip_array<R: Read + Seek>(reader: &mut R, item_type: u32, len: u64) -> Result<(), String> {
+    let fixed_size = match item_type {
+        0 | 1 | 7 => Some(1),
+        2 | 3 => Some(2),
+        4..=6 => Some(4),
+        10..=12 => Some(8),
+        _ => None,
+    };
+
+    match fixed_size {
+        Some(size) => {
+            let bytes = len
+                .checked_mul(size)
+                .and_then(|bytes| i64::try_from(bytes).ok())
+                .ok_or_else(|| "GGUF array too large".to_string())?;
+            reader.seek(SeekFrom::Current(bytes)).map_err(|e| e.to_string())?;
+        }
+        None if item_type == 8 => {
+            for _ in 0..len {
+                let str_len = read_u64(reader)?;
+                let bytes = i64::try_from(str_len).map_err(|_| "GGUF string too large".to_string())?;
+                reader.seek(SeekFrom::Current(bytes)).map_err(|e| e.to_string())?;
+            }
+        }
+        None => return Err(format!("Cannot skip GGUF array of type {}", item_type)),
+    }
+    Ok(())
+}
+
+/// Element count of a tensor, `None` when the dimensions overflow u64
+fn checked_product(dims: &[u64]) -> Option<u64> {
+    dims.iter().try_fold(1u64, |product, &d| product.checked_mul(d))
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], String> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf).map_err(|e| format!("Unexpected end of GGUF header: {}", e))?;
+    Ok(buf)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, String> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String, String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_LEN {
+        return Err(format!("GGUF string too long: {} bytes", len));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf).map_err(|e| format!("Unexpected end of GGUF header: {}", e))?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Minimal GGUF writer for synthetic test files
+    pub(crate) struct GgufBuilder {
+        kvs: Vec<u8>,
+        kv_count: u64,
+        tensors: Vec<u8>,
+        tensor_count: u64,
+    }
+
+    impl GgufBuilder {
+        pub(crate) fn new() -> Self {
+            Self { kvs: Vec::new(), kv_count: 0, tensors: Vec::new(), tensor_count: 0 }
+        }
+
+        fn push_string(buf: &mut Vec<u8>, s: &str) {
+            buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+
+        pub(crate) fn kv_str(mut self, key: &str, value: &str) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&8u32.to_le_bytes());
+            Self::push_string(&mut self.kvs, value);
+            self.kv_count += 1;
+            self
+        }
+
+        pub(crate) fn kv_u32(mut self, key: &str, value: u32) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&4u32.to_le_bytes());
+            self.kvs.extend_from_slice(&value.to_le_bytes());
+            self.kv_count += 1;
+            self
+        }
+
+        pub(crate) fn kv_u16(mut self, key: &str, value: u16) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&2u32.to_le_bytes());
+            self.kvs.extend_from_slice(&value.to_le_bytes());
+            self.kv_count += 1;
+            self
+        }
+
+        pub(crate) fn kv_f32(mut self, key: &str, value: f32) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&6u32.to_le_bytes());
+            self.kvs.extend_from_slice(&value.to_le_bytes());
+            self.kv_count += 1;
+            self
+        }
+
+        pub(crate) fn kv_str_array(mut self, key: &str, values: &[&str]) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&9u32.to_le_bytes());
+            self.kvs.extend_from_slice(&8u32.to_le_bytes());
+            self.kvs.extend_from_slice(&(values.len() as u64).to_le_bytes());
+            for v in values {
+                Self::push_string(&mut self.kvs, v);
+            }
+            self.kv_count += 1;
+            self
+        }
+
+        /// Array header claiming `len` items of `item_type`, without the items
+        fn kv_array_header(mut self, key: &str, item_type: u32, len: u64) -> Self {
+            Self::push_string(&mut self.kvs, key);
+            self.kvs.extend_from_slice(&9u32.to_le_bytes());
+            self.kvs.extend_from_slice(&item_type.to_le_bytes());
+            self.kvs.extend_from_slice(&len.to_le_bytes());
+            self.kv_count += 1;
+            self
+        }
+
+        pub(crate) fn tensor(mut self, name: &str, dims: &[u64], ggml_type: u32) -> Self {
+            Self::push_string(&mut self.tensors, name);
+            self.tensors.extend_from_slice(&(dims.len() as u32).to_le_bytes());
+            for d in dims {
+                self.tensors.extend_from_slice(&d.to_le_bytes());
+            }
+            self.tensors.extend_from_slice(&ggml_type.to_le_bytes());
+            self.tensors.extend_from_slice(&0u64.to_le_bytes());
+            self.tensor_count += 1;
+            self
+        }
+
+        pub(crate) fn build(self) -> Vec<u8> {
+            let mut out = Vec::new();
+            out.extend_from_slice(GGUF_MAGIC);
+            out.extend_from_slice(&3u32.to_le_bytes());
+            out.extend_from_slice(&self.tensor_count.to_le_bytes());
+            out.extend_from_slice(&self.kv_count.to_le_bytes());
+            out.extend_from_slice(&self.kvs);
+            out.extend_from_slice(&self.tensors);
+            out
+        }
+
+        pub(crate) fn write_to(self, path: &Path) {
+            std::fs::write(path, self.build()).unwrap();
+        }
+    }
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("corex_gguf_header_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_metadata_and_tensors() {
+        let bytes = GgufBuilder::new()
+            .kv_str("general.architecture", "qwen2")
+            .kv_u32("general.file_type", 15)
+            .kv_u32("qwen2.block_count", 28)
+            .kv_u32("qwen2.context_length", 32768)
+            .kv_f32("qwen2.rope.freq_base", 1_000_000.0)
+            .kv_str("tokenizer.chat_template", "{% for m in messages %}{{ m.content }}{% endfor %}")
+            .kv_str_array("tokenizer.ggml.tokens", &["a", "b", "c"])
+            .tensor("token_embd.weight", &[256, 4], 12)
+            .tensor("output_norm.weight", &[256], 0)
+            .build();
+
+        let header = GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap();
+
+        assert_eq!(header.version, 3);
+        assert_eq!(header.architecture(), Some("qwen2"));
+        assert_eq!(header.arch_u64("block_count"), Some(28));
+        assert_eq!(header.arch_u64("context_length"), Some(32768));
+        assert_eq!(header.file_type().map(file_type_name), Some("Q4_K_M"));
+        assert_eq!(header.vocab_size(), Some(3));
+        assert!(header.get_str("tokenizer.chat_template").unwrap().contains("messages"));
+
+        assert_eq!(header.tensors.len(), 2);
+        assert_eq!(header.tensors[0].n_elements(), 1024);
+        assert_eq!(header.tensors[0].size_bytes(), Some(4 * 144));
+        assert_eq!(header.tensors[1].size_bytes(), Some(256 * 4));
+        assert_eq!(header.data_offset % DEFAULT_ALIGNMENT, 0);
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        assert!(GgufHeader::read_from(&mut Cursor::new(b"GGML\x03\x00\x00\x00".to_vec())).is_err());
+
+        let mut v1 = b"GGUF".to_vec();
+        v1.extend_from_slice(&1u32.to_le_bytes());
+        assert!(GgufHeader::read_from(&mut Cursor::new(v1)).is_err());
+
+        // Truncated key/value section
+        let mut bytes = GgufBuilder::new().kv_str("general.architecture", "llama").build();
+        bytes.truncate(bytes.len() - 3);
+        assert!(GgufHeader::read_from(&mut Cursor::new(bytes)).is_err());
+
+        // Crafted sizes must fail cleanly instead of overflowing
+        let huge_array = GgufBuilder::new().kv_array_header("tokenizer.ggml.scores", 10, u64::MAX / 4).build();
+        let err = GgufHeader::read_from(&mut Cursor::new(huge_array)).unwrap_err();
+        assert!(err.contains("too large"), "{}", err);
+        let huge_tensor = GgufBuilder::new().tensor("blk.0.weight", &[u64::MAX, 2], 0).build();
+        let err = GgufHeader::read_from(&mut Cursor::new(huge_tensor)).unwrap_err();
+        assert!(err.contains("too large"), "{}", err);
+    }
+
+    #[test]
+    fn test_long_arrays_are_skipped() {
+        let tokens: Vec<String> = (0..(MAX_ARRAY_VALUES + 5)).map(|i| format!("t{}", i)).collect();
+        let token_refs: Vec<&str> = tokens.iter().map(|s| s.as_str()).collect();
+        let bytes = GgufBuilder::new()
+            .kv_str_array("tokenizer.ggml.tokens", &token_refs)
+            .kv_str("general.architecture", "llama")
+            .build();
+
+        let header = GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(header.vocab_size(), Some(MAX_ARRAY_VALUES + 5));
+        // The key after the skipped array must still parse
+        assert_eq!(header.architecture(), Some("llama"));
+    }
+
+    #[test]
+    fn test_split_model_collects_all_shards() {
+        let dir = test_dir("split");
+        for i in 1..=2u16 {
+            let path = dir.join(format!("model-{:05}-of-00002.gguf", i));
+            GgufBuilder::new()
+                .kv_str("general.architecture", "llama")
+                .kv_u16("split.no", i - 1)
+                .kv_u16("split.count", 2)
+                .tensor(&format!("blk.{}.weight", i), &[32, 32], 8)
+                .write_to(&path);
+        }
+
+        let second = dir.join("model-00002-of-00002.gguf");
+        let model = GgufModelHeader::read(second.to_str().unwrap()).unwrap();
+
+        assert_eq!(model.shard_paths.len(), 2);
+        assert!(model.shard_paths[0].ends_with("model-00001-of-00002.gguf"));
+        assert_eq!(model.tensors.len(), 2);
+        assert_eq!(model.parameter_count(), 2048);
+        assert_eq!(model.tensor_type_counts().get("Q8_0"), Some(&2));
+    }
+
+    #[test]
+    fn test_split_count_must_match_file_name() {
+        let dir = test_dir("split_mismatch");
+        let write = |name: &str| {
+            let path = dir.join(name);
+            GgufBuilder::new()
+                .kv_str("general.architecture", "llama")
+                .kv_u16("split.no", 0)
+                .kv_u16("split.count", 3)
+                .write_to(&path);
+            path
+        };
+
+        let renamed = write("model.gguf");
+        let err = GgufModelHeader::read(renamed.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("not named as a split part"), "{}", err);
+
+        let disagreeing = write("model-00001-of-00002.gguf");
+        let err = GgufModelHeader::read(disagreeing.to_str().unwrap()).unwrap_err();
+        assert!(err.contains("says 2 parts"), "{}", err);
+    }
+
+    #[test]
+    fn test_format_parameter_count() {
+        assert_eq!(format_parameter_count(7_615_616_512), "7.6B");
+        assert_eq!(format_parameter_count(135_000_000), "135M");
+    }
+}
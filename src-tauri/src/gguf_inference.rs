@@ -0,0 +1,462 @@
+// src-tauri/src/gguf_inference.rs
+// Shared llama.cpp decode loop used by the GGUF chat and streaming commands
+
+use llama_cpp_2::context::LlamaContext;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::LlamaModel;
+use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_2::token::data_array::LlamaTokenDataArray;
+use llama_cpp_2::token::LlamaToken;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cancellation::CancelToken;
+use crate::gguf_context_shift::{shift_plan, ContextShift, ContextShiftReport};
+use crate::gguf_grammar::{CompiledGrammar, OutputConstraint};
+use crate::gguf_lora::LoraRequest;
+use crate::gguf_metrics::{elapsed_ms, GenerationMetrics, StopReason};
+use crate::gguf_sampling::{log_probs, Candidate, Sampler, SamplingParams};
+use crate::gguf_scheduler::ScheduleOptions;
+use crate::gguf_speculative::{SpeculativeConfig, SpeculativeStats};
+use crate::stop_sequences::StopMatcher;
+
+/// Maximum number of prompt tokens evaluated in a single batch
+pub const MAX_BATCH_SIZE: usize = 8192;
+
+/// Most alternatives that can be reported per generated token
+pub const MAX_TOP_LOGPROBS: u32 = 20;
+
+/// Knobs for a single generation run
+#[derive(Debug, Clone)]
+pub struct GenerationParams {
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub sampling: SamplingParams,
+    /// Restricts sampling to tokens the grammar accepts next
+    pub grammar: Option<CompiledGrammar>,
+    /// Generation ends as soon as any of these strings appears; it is not part of the output
+    pub stop: Vec<String>,
+    /// Verify tokens proposed by a draft model instead of sampling one by one
+    pub speculative: Option<SpeculativeConfig>,
+    /// Priority class and supersede key on the model's request queue
+    pub schedule: ScheduleOptions,
+    /// LoRA adapters to run with; None applies every adapter attached to the model
+    pub loras: Option<Vec<LoraRequest>>,
+    /// Checked before every token, generation stops early once it is set
+    pub cancel: Option<Arc<CancelToken>>,
+    /// Record each generated token's log-probability along with this many most likely alternatives
+    pub logprobs: Option<u32>,
+    /// Slide the KV cache instead of failing once prompt and reply outgrow the context
+    pub context_shift: Option<ContextShift>,
+    /// Evaluate the whole prompt from an empty KV cache instead of reusing a cached prefix
+    pub cold_start: bool,
+}
+
+/// Optional per-request knobs of the GGUF chat commands, any field may be left out
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GgufRequestOptions {
+    /// Id to stop the request with cancel_generation; a new one is made when missing
+    pub generation_id: Option<String>,
+    pub sampling: SamplingParams,
+    /// GBNF grammar or JSON schema the reply has to follow
+    pub constraint: Option<OutputConstraint>,
+    pub stop: Vec<String>,
+    pub speculative: Option<SpeculativeConfig>,
+    pub schedule: ScheduleOptions,
+    pub loras: Option<Vec<LoraRequest>>,
+    pub logprobs: Option<u32>,
+    pub context_shift: Option<ContextShift>,
+}
+
+impl GgufRequestOptions {
+    /// Generation parameters without a cancel token. The constraint is compiled here, so
+    /// an invalid grammar or schema is reported before anything is queued.
+    pub fn into_params(self, max_tokens: u32, temperature: f32) -> Result<GenerationParams, String> {
+        Ok(GenerationParams {
+            max_tokens,
+            temperature,
+            sampling: self.sampling,
+            grammar: self.constraint.as_ref().map(OutputConstraint::compile).transpose()?,
+            stop: self.stop,
+            speculative: self.speculative,
+            schedule: self.schedule,
+            loras: self.loras,
+            cancel: None,
+            logprobs: self.logprobs,
+            context_shift: self.context_shift,
+            cold_start: false,
+        })
+    }
+}
+
+/// An alternative for a generated position
+#[derive(Debug, Clone, Serialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub id: i32,
+    pub logprob: f32,
+    pub probability: f32,
+}
+
+/// A generated token with its log-probability under the model's distribution, taken
+/// before penalties, temperature and truncation (over the allowed tokens with a grammar)
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenLogprob {
+    /// Text this token completed; empty for a token that ends in the middle of a character
+    pub token: String,
+    pub id: i32,
+    pub logprob: f32,
+    pub probability: f32,
+    /// Most likely tokens at this position, the generated one included if it ranks
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// Result of a generation run
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOutput {
+    /// Exact concatenation of every decoded piece, untouched
+    pub text: String,
+    pub tokens: Vec<LlamaToken>,
+    pub prompt_tokens: usize,
+    /// Prompt tokens served from the KV cache instead of being evaluated
+    pub reused_tokens: usize,
+    /// True when the run was aborted through its cancel token
+    pub cancelled: bool,
+    /// Stop string that ended the run, if any
+    pub stop_sequence: Option<String>,
+    /// Draft acceptance statistics when speculative decoding was used
+    pub speculative: Option<SpeculativeStats>,
+    /// One entry per generated token when `GenerationParams::logprobs` was set
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    /// (first position, count) of every context shift, in the order they happened
+    pub shifts: Vec<(usize, usize)>,
+    /// Prompt tokens cut from the middle so the prompt fit the context
+    pub truncated_prompt_tokens: usize,
+    /// Token counts, timings and stop reason; the request-level parts are filled in by the caller
+    pub metrics: GenerationMetrics,
+    /// When the first reply token was sampled
+    pub first_token_at: Option<Instant>,
+}
+
+impl GenerationOutput {
+    /// What the sliding window did, None when it never moved
+    pub fn context_shift(&self) -> Option<ContextShiftReport> {
+        ContextShiftReport::new(&self.shifts, self.truncated_prompt_tokens)
+    }
+}
+
+/// Evaluate `tokens` as the prompt, then sample up to `max_tokens` new tokens.
+///
+/// The first `reused` tokens must already sit in the context's KV cache at
+/// positions `0..reused`; only the remaining suffix is evaluated. After the run
+/// the KV cache holds `tokens` followed by `output.tokens`, less the ranges in
+/// `output.shifts` when a context shift made room.
+///
+/// Every decoded piece is handed to `on_piece` as soon as it is produced. The
+/// UTF-8 decoder is stateful, so multi-byte characters split across tokens are
+/// emitted once complete and the concatenated pieces equal `output.text` byte for byte.
+/// Text that might be the start of a stop string is held back until it is decided.
+pub fn generate(
+    model: &LlamaModel,
+    context: &mut LlamaContext,
+    tokens: &[LlamaToken],
+    reused: usize,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let started = Instant::now();
+    let batch = evaluate_prompt(context, tokens, reused)?;
+    let prompt_eval_ms = elapsed_ms(started);
+    let mut output = sample_tokens(model, context, batch, tokens, tokens.len() as i32, params, on_piece)?;
+    output.reused_tokens = reused;
+    output.metrics.prompt_eval_tokens = tokens.len() - reused;
+    output.metrics.prompt_eval_ms = prompt_eval_ms;
+    Ok(output)
+}
+
+/// Sample after a prompt that was evaluated some other way, e.g. together with image
+/// embeddings. The KV cache holds `n_past` positions whose logits are current.
+pub fn generate_after(
+    model: &LlamaModel,
+    context: &mut LlamaContext,
+    n_past: i32,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let mut output = sample_tokens(model, context, LlamaBatch::new(1, 1), &[], n_past, params, on_piece)?;
+    output.prompt_tokens = n_past.max(0) as usize;
+    output.metrics.prompt_tokens = output.prompt_tokens;
+    Ok(output)
+}
+
+/// The decode loop: `n_past` positions are evaluated, `prompt` are the known prompt tokens
+/// for the repetition penalties
+fn sample_tokens(
+    model: &LlamaModel,
+    context: &mut LlamaContext,
+    mut batch: LlamaBatch,
+    prompt: &[LlamaToken],
+    n_past: i32,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<GenerationOutput, String> {
+    let top_logprobs = params.logprobs.map(|n| n.min(MAX_TOP_LOGPROBS) as usize);
+    let mut output = GenerationOutput {
+        prompt_tokens: prompt.len(),
+        logprobs: top_logprobs.map(|_| Vec::new()),
+        ..Default::default()
+    };
+    let mut n_cur = n_past;
+    let mut decoder = encoding_rs::UTF_8.new_decoder();
+    let mut decode_errors = 0;
+    let mut stop_matcher = StopMatcher::new(&params.stop);
+
+    let mut sampler = Sampler::new(params.sampling.clone(), params.temperature);
+    let mut grammar = match &params.grammar {
+        Some(g) => {
+            info!("📐 Grammar constrained decoding (root: {})", g.root);
+            Some(
+                LlamaSampler::grammar(model, &g.gbnf, &g.root)
+                    .map_err(|e| format!("Invalid grammar: {:?}", e))?,
+            )
+        }
+        None => None,
+    };
+    // Prompt + generated token ids, used by the repetition penalties
+    let mut history: Vec<i32> = prompt.iter().map(|t| t.0).collect();
+    // The BOS token is always kept on top of the requested ones
+    let shift_keep = params.context_shift.map(|shift| {
+        shift.keep as usize + usize::from(prompt.first() == Some(&model.token_bos()))
+    });
+
+    info!("🎲 Starting token generation...");
+    let started = Instant::now();
+
+    for i in 0..params.max_tokens {
+        if params.cancel.as_ref().map(|c| c.is_cancelled()).unwrap_or(false) {
+            info!("🛑 Generation cancelled after {} tokens", i);
+            output.cancelled = true;
+            output.metrics.stop_reason = StopReason::Cancelled;
+            break;
+        }
+
+        let candidates = match grammar.as_mut() {
+            Some(grammar) => grammar_candidates(grammar, context),
+            None => context
+                .candidates()
+                .map(|c| Candidate::new(c.id().0, c.logit()))
+                .collect(),
+        };
+        // The sampler consumes the candidates, keep the raw distribution for the log-probabilities
+        let distribution = top_logprobs.map(|_| candidates.clone());
+        let new_token_id = match sampler.sample(candidates, &history) {
+            Some(id) => LlamaToken::new(id),
+            None => {
+                info!("⚠️ No token allowed at position {}, stopping", i);
+                output.metrics.stop_reason = StopReason::Eos;
+                break;
+            }
+        };
+        history.push(new_token_id.0);
+        if let Some(grammar) = grammar.as_mut() {
+            grammar.accept(new_token_id);
+        }
+
+        // Check for EOS (End of Sequence)
+        if model.is_eog_token(new_token_id) {
+            info!("✅ EOS token found at position {}, stopping", i);
+            output.metrics.stop_reason = StopReason::Eos;
+            break;
+        }
+
+        if output.tokens.is_empty() {
+            output.first_token_at = Some(Instant::now());
+        }
+        output.tokens.push(new_token_id);
+
+        let piece = model.token_to_piece(new_token_id, &mut decoder, false, None);
+        if let (Some(logprobs), Some(distribution), Some(top_n)) = (output.logprobs.as_mut(), &distribution, top_logprobs) {
+            let text = piece.as_deref().unwrap_or_default();
+            logprobs.push(token_logprob(model, distribution, new_token_id, text, top_n));
+        }
+
+        match piece {
+            Ok(piece) => {
+                let result = stop_matcher.push(&piece);
+                if !result.emit.is_empty() {
+                    on_piece(&result.emit);
+                    output.text.push_str(&result.emit);
+                }
+                if let Some(stop) = result.stopped {
+                    info!("🛑 Stop sequence {:?} found at position {}", stop, i);
+                    output.stop_sequence = Some(stop);
+                    output.metrics.stop_reason = StopReason::StopSequence;
+                }
+            }
+            Err(e) => {
+                decode_errors += 1;
+                if decode_errors <= 10 {
+                    info!("⏭️ Token {}: decode failed: {:?}", i, e);
+                }
+            }
+        }
+
+        // Log every 50 tokens
+        if i % 50 == 0 && i > 0 {
+            info!("📊 Generated {}/{} tokens", i, params.max_tokens);
+        }
+
+        if let Some(keep) = shift_keep {
+            if n_cur as u32 >= context.n_ctx() {
+                let (start, count) = shift_plan(n_cur as usize, keep)
+                    .ok_or_else(|| format!("Context dolu ve kaydırılacak token yok ({} token)", n_cur))?;
+                shift_kv_cache(context, start, count, n_cur as usize)?;
+                n_cur -= count as i32;
+                output.shifts.push((start, count));
+            }
+        }
+
+        batch.clear();
+        batch.add(new_token_id, n_cur, &[0], true)
+            .map_err(|e| format!("Batch add failed: {:?}", e))?;
+
+        context.decode(&mut batch)
+            .map_err(|e| format!("Decode failed at token {}: {:?}", i, e))?;
+
+        n_cur += 1;
+
+        // Stop only after decoding, so the KV cache still matches `output.tokens`
+        if output.stop_sequence.is_some() {
+            break;
+        }
+    }
+
+    let held = stop_matcher.finish();
+    if !held.is_empty() {
+        on_piece(&held);
+        output.text.push_str(&held);
+    }
+    output.metrics.prompt_tokens = prompt.len();
+    output.metrics.generated_tokens = output.tokens.len();
+    output.metrics.generation_ms = elapsed_ms(started);
+
+    info!(
+        "✅ Token generation completed: {} tokens, {} chars ({} decode errors)",
+        output.tokens.len(),
+        output.text.len(),
+        decode_errors
+    );
+
+    Ok(output)
+}
+
+/// Drop KV positions `start..start + count` and slide the ones after them back to close the gap
+fn shift_kv_cache(context: &mut LlamaContext, start: usize, count: usize, n_past: usize) -> Result<(), String> {
+    let (p0, p1) = (start as u32, (start + count) as u32);
+    match context.clear_kv_cache_seq(Some(0), Some(p0), Some(p1)) {
+        Ok(true) => {}
+        // Recurrent models cannot drop a partial sequence
+        other => return Err(format!("Model context kaydırmayı desteklemiyor: {:?}", other)),
+    }
+    context.kv_cache_seq_add(0, Some(p1), Some(n_past as u32), -(count as i32))
+        .map_err(|e| format!("KV cache kaydırılamadı: {:?}", e))?;
+    info!("↪️ Context shift: {} tokens discarded after position {}, {} remain", count, start, n_past - count);
+    Ok(())
+}
+
+fn token_logprob(model: &LlamaModel, distribution: &[Candidate], token: LlamaToken, text: &str, top_n: usize) -> TokenLogprob {
+    let (logprob, top) = log_probs(distribution, token.0, top_n).unwrap_or((f32::NEG_INFINITY, Vec::new()));
+    let top_logprobs = top
+        .into_iter()
+        .map(|(id, logprob)| TopLogprob {
+            // Own decoder per alternative; a partial UTF-8 sequence may come back empty
+            token: model
+                .token_to_piece(LlamaToken::new(id), &mut encoding_rs::UTF_8.new_decoder(), false, None)
+                .unwrap_or_default(),
+            id,
+            logprob,
+            probability: logprob.exp(),
+        })
+        .collect();
+    TokenLogprob { token: text.to_string(), id: token.0, logprob, probability: logprob.exp(), top_logprobs }
+}
+
+/// Candidates for the next token with everything the grammar rejects removed
+fn grammar_candidates(grammar: &mut LlamaSampler, context: &LlamaContext) -> Vec<Candidate> {
+    let mut array = LlamaTokenDataArray::from_iter(context.candidates(), false);
+    grammar.apply(&mut array);
+
+    // Rejected tokens come back with a -inf logit
+    array
+        .data
+        .iter()
+        .filter(|c| c.logit().is_finite())
+        .map(|c| Candidate::new(c.id().0, c.logit()))
+        .collect()
+}
+
+/// Decode `tokens[start..]`, chunking it when it is larger than `MAX_BATCH_SIZE`.
+/// Returns the batch so the generation loop can reuse its allocation.
+pub(crate) fn evaluate_prompt(context: &mut LlamaContext, tokens: &[LlamaToken], start: usize) -> Result<LlamaBatch, String> {
+    if tokens.is_empty() {
+        return Err("Prompt is empty after tokenization".to_string());
+    }
+    if start >= tokens.len() {
+        return Err("At least one prompt token must be evaluated".to_string());
+    }
+
+    let pending = tokens.len() - start;
+    let batch_size = pending.min(MAX_BATCH_SIZE);
+    info!(
+        "📦 Creating batch: prompt_tokens={}, cached={}, batch_size={}",
+        tokens.len(),
+        start,
+        batch_size
+    );
+
+    let mut batch = LlamaBatch::new(batch_size, 1);
+    let mut processed = start;
+
+    while processed < tokens.len() {
+        batch.clear();
+        let chunk_size = (tokens.len() - processed).min(MAX_BATCH_SIZE);
+
+        for i in 0..chunk_size {
+            let token_idx = processed + i;
+            batch.add(
+                tokens[token_idx],
+                token_idx as i32,
+                &[0],
+                token_idx == tokens.len() - 1,
+            ).map_err(|e| format!("Batch add failed: {:?}", e))?;
+        }
+
+        context.decode(&mut batch)
+            .map_err(|e| {
+                error!("❌ Decode failed at chunk {}: {:?}", (processed - start) / MAX_BATCH_SIZE, e);
+                format!("Decode failed: {:?}", e)
+            })?;
+
+        processed += chunk_size;
+        if pending > MAX_BATCH_SIZE {
+            info!("📊 Processed {}/{} tokens", processed - start, pending);
+        }
+    }
+
+    info!("✅ Prompt processed!");
+    Ok(batch)
+}
+
+/// Number of leading tokens `cached` and `prompt` share. At least one prompt
+/// token is always left over so the last position produces fresh logits.
+pub fn reusable_prefix(cached: &[LlamaToken], prompt: &[LlamaToken]) -> usize {
+    let common = cached
+        .iter()
+        .zip(prompt)
+        .take_while(|(a, b)| a == b)
+        .count();
+    common.min(prompt.len().saturating_sub(1))
+}
@@ -0,0 +1,770 @@
+// src-tauri/src/gguf_library.rs
+// Local GGUF model library: scans model folders, reads header metadata, finds duplicate files, persists the catalog
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::gguf::{lock_state, GgufState};
+use crate::gguf_download::{sha256_file, split_shards};
+use crate::gguf_header::{file_type_name, format_parameter_count, GgufModelHeader};
+
+/// Bumped whenever the catalog layout changes
+pub const LIBRARY_FORMAT_VERSION: u32 = 1;
+/// Folder depth a scan descends to below each configured directory
+const MAX_SCAN_DEPTH: usize = 4;
+/// Hex digits of the content hash that make up a model ID
+const ID_LENGTH: usize = 16;
+
+lazy_static::lazy_static! {
+    // Catalog as last saved, read from disk on first use
+    static ref LIBRARY: Mutex<Option<ModelLibrary>> = Mutex::new(None);
+}
+
+/// Set while a scan runs, a second one is refused
+static SCANNING: AtomicBool = AtomicBool::new(false);
+
+/// One model of the library. Split models are a single entry covering all their shards.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryModel {
+    /// Start of the content hash, so it survives moving or renaming the file
+    pub id: String,
+    pub name: String,
+    /// Path to load, the first shard for split models
+    pub path: String,
+    pub shard_paths: Vec<String>,
+    /// Other copies of the same content (their first shard for split models)
+    #[serde(default)]
+    pub duplicates: Vec<String>,
+    pub architecture: Option<String>,
+    pub parameters: Option<String>,
+    pub parameter_count: u64,
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    pub size_bytes: u64,
+    pub sha256: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub added_at: u64,
+    /// Newest modification time among the shards
+    pub modified_at: u64,
+}
+
+impl LibraryModel {
+    /// The catalog path, or a duplicate when that file is gone
+    pub fn existing_path(&self) -> Option<&str> {
+        std::iter::once(&self.path)
+            .chain(&self.duplicates)
+            .map(String::as_str)
+            .find(|path| Path::new(path).exists())
+    }
+
+    /// Every file of the model on disk, shards of all copies included
+    pub fn all_files(&self) -> Vec<String> {
+        let mut files = self.shard_paths.clone();
+        for duplicate in &self.duplicates {
+            files.extend(split_shards(duplicate).unwrap_or_else(|| vec![duplicate.clone()]));
+        }
+        files
+    }
+
+    fn matches(&self, terms: &[String]) -> bool {
+        let file_name = Path::new(&self.path).file_name().and_then(|n| n.to_str());
+        let haystack = [
+            Some(self.id.as_str()),
+            Some(self.name.as_str()),
+            file_name,
+            self.architecture.as_deref(),
+            self.parameters.as_deref(),
+            self.quantization.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.tags.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+        terms.iter().all(|term| haystack.contains(term.as_str()))
+    }
+}
+
+/// Hash of one file, reused while its size and modification time stay the same
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HashedFile {
+    pub size: u64,
+    pub modified: u64,
+    pub sha256: String,
+}
+
+/// Persisted catalog, `~/.corex/model_library.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelLibrary {
+    pub format_version: u32,
+    /// Folders a scan looks in
+    pub directories: Vec<String>,
+    pub models: Vec<LibraryModel>,
+    /// Content hashes of models removed from the catalog while their files stayed; scans skip them
+    #[serde(default)]
+    pub ignored: Vec<String>,
+    /// File path -> hash, so a rescan only hashes new or changed files
+    #[serde(default)]
+    pub file_hashes: BTreeMap<String, HashedFile>,
+}
+
+impl Default for ModelLibrary {
+    fn default() -> Self {
+        Self {
+            format_version: LIBRARY_FORMAT_VERSION,
+            directories: Vec::new(),
+            models: Vec::new(),
+            ignored: Vec::new(),
+            file_hashes: BTreeMap::new(),
+        }
+    }
+}
+
+/// What a scan found, before it is merged into the catalog
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    pub models: Vec<LibraryModel>,
+    pub file_hashes: BTreeMap<String, HashedFile>,
+    pub errors: Vec<String>,
+}
+
+/// Catalog changes of a scan, returned to the frontend
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScanChanges {
+    pub total: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Files that are copies of another model
+    pub duplicate_files: usize,
+    pub errors: Vec<String>,
+}
+
+impl ModelLibrary {
+    /// An empty library when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Model kütüphanesi okunamadı ({}): {}", path.display(), e))?;
+        let library: Self = serde_json::from_str(&json).map_err(|e| format!("Invalid model library: {}", e))?;
+        if library.format_version != LIBRARY_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported model library version {} (expected {})",
+                library.format_version, LIBRARY_FORMAT_VERSION
+            ));
+        }
+        Ok(library)
+    }
+
+    /// Written to a temporary file and renamed, so a crash never leaves half a catalog
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Klasör oluşturulamadı: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json).map_err(|e| format!("Model kütüphanesi yazılamadı: {}", e))?;
+        fs::rename(&tmp_path, path).map_err(|e| format!("Model kütüphanesi yazılamadı: {}", e))
+    }
+
+    pub fn get(&self, id: &str) -> Result<&LibraryModel, String> {
+        self.models.iter().find(|m| m.id == id).ok_or_else(|| not_found(id))
+    }
+
+    /// Models matching every word of `query` (ID, name, file name, architecture, size,
+    /// quantization or tag) and carrying all of `tags`. An empty query matches everything.
+    pub fn search(&self, query: &str, tags: &[String]) -> Vec<LibraryModel> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let tags = normalize_tags(tags);
+        self.models
+            .iter()
+            .filter(|m| m.matches(&terms) && tags.iter().all(|tag| m.tags.contains(tag)))
+            .cloned()
+            .collect()
+    }
+
+    /// Replace the tags of a model
+    pub fn set_tags(&mut self, id: &str, tags: &[String]) -> Result<LibraryModel, String> {
+        let model = self.models.iter_mut().find(|m| m.id == id).ok_or_else(|| not_found(id))?;
+        model.tags = normalize_tags(tags);
+        Ok(model.clone())
+    }
+
+    /// Take a model out of the catalog. With `ignore` later scans leave it out even though
+    /// its files are still in a scanned folder.
+    pub fn remove(&mut self, id: &str, ignore: bool) -> Result<LibraryModel, String> {
+        let index = self.models.iter().position(|m| m.id == id).ok_or_else(|| not_found(id))?;
+        let model = self.models.remove(index);
+        if ignore && !self.ignored.contains(&model.sha256) {
+            self.ignored.push(model.sha256.clone());
+        }
+        Ok(model)
+    }
+
+    /// Replace the catalog with a scan result. Tags and the date a model was first added
+    /// carry over by content hash; ignored models stay out.
+    pub fn apply_scan(&mut self, scan: ScanResult) -> ScanChanges {
+        let ScanResult { mut models, file_hashes, errors } = scan;
+        models.retain(|m| !self.ignored.contains(&m.sha256));
+
+        let previous: HashMap<&str, &LibraryModel> = self.models.iter().map(|m| (m.sha256.as_str(), m)).collect();
+        let mut added = Vec::new();
+        for model in &mut models {
+            match previous.get(model.sha256.as_str()) {
+                Some(old) => {
+                    model.tags = old.tags.clone();
+                    model.added_at = old.added_at;
+                }
+                None => added.push(model.id.clone()),
+            }
+        }
+        let found: HashSet<&str> = models.iter().map(|m| m.sha256.as_str()).collect();
+        let removed = self.models.iter()
+            .filter(|m| !found.contains(m.sha256.as_str()))
+            .map(|m| m.id.clone())
+            .collect();
+
+        let changes = ScanChanges {
+            total: models.len(),
+            added,
+            removed,
+            duplicate_files: models.iter().map(|m| m.duplicates.len()).sum(),
+            errors,
+        };
+        self.models = models;
+        self.file_hashes = file_hashes;
+        changes
+    }
+}
+
+fn not_found(id: &str) -> String {
+    format!("Kütüphanede model bulunamadı: {}", id)
+}
+
+/// Trimmed, lowercase, sorted and without repeats
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags.iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Index every GGUF model below `directories`. Split models become one entry, copies of
+/// the same content one entry listing the others as `duplicates`. Files whose size and
+/// modification time match `cache` are not hashed again. `on_model(done, total, path)`
+/// is called after each model.
+pub fn scan_directories(
+    directories: &[String],
+    cache: &BTreeMap<String, HashedFile>,
+    mut on_model: impl FnMut(usize, usize, &str),
+) -> ScanResult {
+    let mut errors = Vec::new();
+    let mut files = Vec::new();
+    for dir in directories {
+        if Path::new(dir).is_dir() {
+            collect_gguf_files(Path::new(dir), MAX_SCAN_DEPTH, &mut files);
+        } else {
+            errors.push(format!("Klasör bulunamadı: {}", dir));
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    // Split models are indexed through their first shard
+    let present: HashSet<&String> = files.iter().collect();
+    let mut model_files = Vec::new();
+    let mut orphan_shards = Vec::new();
+    for file in &files {
+        match split_shards(file) {
+            Some(shards) if shards[0] == *file => model_files.push(file.clone()),
+            Some(shards) if !present.contains(&shards[0]) && !orphan_shards.contains(&shards[0]) => {
+                orphan_shards.push(shards[0].clone())
+            }
+            Some(_) => {}
+            None => model_files.push(file.clone()),
+        }
+    }
+    for first in orphan_shards {
+        errors.push(format!("{}: ilk parça eksik, split model atlandı", first));
+    }
+
+    let mut file_hashes = BTreeMap::new();
+    let mut indexed = Vec::new();
+    for (i, path) in model_files.iter().enumerate() {
+        match index_model(path, cache, &mut file_hashes) {
+            Ok(Some(model)) => indexed.push(model),
+            Ok(None) => {}
+            Err(e) => {
+                warn!("⚠️ Model indekslenemedi {}: {}", path, e);
+                errors.push(format!("{}: {}", path, e));
+            }
+        }
+        on_model(i + 1, model_files.len(), path);
+    }
+
+    // Copies of the same content become one entry, the first path in order is the main one
+    let mut by_hash: BTreeMap<String, LibraryModel> = BTreeMap::new();
+    for model in indexed {
+        match by_hash.get_mut(&model.sha256) {
+            Some(main) => main.duplicates.push(model.path),
+            None => {
+                by_hash.insert(model.sha256.clone(), model);
+            }
+        }
+    }
+    let mut models: Vec<LibraryModel> = by_hash.into_values().collect();
+    models.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| a.path.cmp(&b.path)));
+
+    ScanResult { models, file_hashes, errors }
+}
+
+/// `.gguf` files below `dir`, without hidden folders and mmproj projectors
+/// (those are found next to their model when an image comes in)
+fn collect_gguf_files(dir: &Path, depth: usize, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("⚠️ Klasör okunamadı {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if name.starts_with('.') {
+            continue;
+        }
+        // Followed through symlinks, model caches often link to blobs
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                collect_gguf_files(&path, depth - 1, files);
+            }
+        } else if name.ends_with(".gguf") && !name.contains("mmproj") {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+/// Catalog entry read from the header of `path`. None for vision projectors.
+fn index_model(
+    path: &str,
+    cache: &BTreeMap<String, HashedFile>,
+    file_hashes: &mut BTreeMap<String, HashedFile>,
+) -> Result<Option<LibraryModel>, String> {
+    let model_header = GgufModelHeader::read(path)?;
+    let header = &model_header.header;
+    if header.architecture() == Some("clip") {
+        return Ok(None);
+    }
+
+    let mut shard_hashes = Vec::new();
+    let mut modified_at = 0;
+    for shard in &model_header.shard_paths {
+        let hashed = hash_file(shard, cache)?;
+        modified_at = modified_at.max(hashed.modified);
+        shard_hashes.push(hashed.sha256.clone());
+        file_hashes.insert(shard.clone(), hashed);
+    }
+    let sha256 = content_hash(&shard_hashes);
+    let parameter_count = model_header.parameter_count();
+
+    Ok(Some(LibraryModel {
+        id: sha256[..ID_LENGTH].to_string(),
+        name: header.get_str("general.name").map(str::to_string).unwrap_or_else(|| model_stem(path)),
+        path: model_header.shard_paths[0].clone(),
+        shard_paths: model_header.shard_paths.clone(),
+        duplicates: Vec::new(),
+        architecture: header.architecture().map(str::to_string),
+        parameters: header.get_str("general.size_label")
+            .map(str::to_string)
+            .or_else(|| (parameter_count > 0).then(|| format_parameter_count(parameter_count))),
+        parameter_count,
+        quantization: header.file_type().map(|t| file_type_name(t).to_string()),
+        context_length: header.arch_u64("context_length"),
+        size_bytes: model_header.total_file_size,
+        sha256,
+        tags: Vec::new(),
+        added_at: now_secs(),
+        modified_at,
+    }))
+}
+
+fn hash_file(path: &str, cache: &BTreeMap<String, HashedFile>) -> Result<HashedFile, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Dosya okunamadı: {}", e))?;
+    let size = metadata.len();
+    let modified = metadata.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if let Some(cached) = cache.get(path).filter(|c| c.size == size && c.modified == modified) {
+        return Ok(cached.clone());
+    }
+
+    info!("🔐 Hashing {} ({} MB)", path, size / (1024 * 1024));
+    let sha256 = sha256_file(path).map_err(|e| format!("Hash hesaplanamadı: {}", e))?;
+    Ok(HashedFile { size, modified, sha256 })
+}
+
+/// The file hash for a single-file model, a hash over the shard hashes for a split one
+pub fn content_hash(shard_hashes: &[String]) -> String {
+    if let [single] = shard_hashes {
+        return single.clone();
+    }
+    let mut hasher = Sha256::new();
+    for hash in shard_hashes {
+        hasher.update(hash.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// File name without extension and split suffix, "qwen-7b-00001-of-00003.gguf" -> "qwen-7b"
+fn model_stem(path: &str) -> String {
+    let stem = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    regex::Regex::new(r"-\d{5}-of-\d{5}$")
+        .map(|re| re.replace(&stem, "").to_string())
+        .unwrap_or(stem)
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn library_path() -> Result<PathBuf, String> {
+    let home = crate::commands::get_home_dir()?;
+    Ok(PathBuf::from(home).join(".corex").join("model_library.json"))
+}
+
+/// Run `f` on the catalog, loading it on first use. With `save` the changed catalog is
+/// written to disk and only kept in memory once that worked.
+fn with_library<T>(save: bool, f: impl FnOnce(&mut ModelLibrary) -> Result<T, String>) -> Result<T, String> {
+    let path = library_path()?;
+    let mut guard = LIBRARY.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if guard.is_none() {
+        *guard = Some(ModelLibrary::load(&path)?);
+    }
+    let library = guard.as_mut().unwrap();
+    if !save {
+        return f(library);
+    }
+
+    let mut updated = library.clone();
+    let result = f(&mut updated)?;
+    updated.save(&path)?;
+    *library = updated;
+    Ok(result)
+}
+
+/// Path to load for `model`: an existing file path as is, otherwise it is looked up as a
+/// library ID. Anything else is returned unchanged for the caller to report.
+pub fn resolve_model_path(model: &str) -> String {
+    if Path::new(model).exists() {
+        return model.to_string();
+    }
+    let resolved = with_library(false, |library| {
+        Ok(library.get(model).ok().map(|m| m.existing_path().unwrap_or(&m.path).to_string()))
+    });
+    match resolved {
+        Ok(Some(path)) => {
+            info!("📚 Library model {} -> {}", model, path);
+            path
+        }
+        Ok(None) => model.to_string(),
+        Err(e) => {
+            warn!("⚠️ Model kütüphanesi okunamadı: {}", e);
+            model.to_string()
+        }
+    }
+}
+
+/// Clears the scan flag however the scan ends
+struct ScanGuard;
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        SCANNING.store(false, Ordering::SeqCst);
+    }
+}
+
+// --------------------
+// COMMANDS
+// --------------------
+
+#[tauri::command]
+pub async fn list_library_models() -> Result<Vec<LibraryModel>, String> {
+    with_library(false, |library| Ok(library.models.clone()))
+}
+
+#[tauri::command]
+pub async fn get_model_directories() -> Result<Vec<String>, String> {
+    with_library(false, |library| Ok(library.directories.clone()))
+}
+
+/// 📁 Set the folders the library scans; takes effect with the next `scan_model_library`
+#[tauri::command]
+pub async fn set_model_directories(directories: Vec<String>) -> Result<Vec<String>, String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for dir in directories {
+        let dir = dir.trim().to_string();
+        if dir.is_empty() || cleaned.contains(&dir) {
+            continue;
+        }
+        if !Path::new(&dir).is_dir() {
+            return Err(format!("Klasör bulunamadı: {}", dir));
+        }
+        cleaned.push(dir);
+    }
+    info!("📁 Model klasörleri: {:?}", cleaned);
+    with_library(true, |library| {
+        library.directories = cleaned.clone();
+        Ok(cleaned)
+    })
+}
+
+/// 🔍 Rescan the model folders and rebuild the catalog. Only new or changed files are
+/// hashed; progress goes out as `model-library-progress` events. Models removed without
+/// deleting their files come back with `include_removed`.
+#[tauri::command]
+pub async fn scan_model_library(app: AppHandle, include_removed: Option<bool>) -> Result<ScanChanges, String> {
+    if SCANNING.swap(true, Ordering::SeqCst) {
+        return Err("Model kütüphanesi taraması zaten sürüyor".to_string());
+    }
+    let _guard = ScanGuard;
+    let include_removed = include_removed.unwrap_or(false);
+
+    let (directories, cache) = with_library(false, |library| {
+        Ok((library.directories.clone(), library.file_hashes.clone()))
+    })?;
+    if directories.is_empty() {
+        return Err("Model klasörü ayarlanmamış, önce set_model_directories çağırın".to_string());
+    }
+    info!("🔍 Scanning model library: {:?}", directories);
+
+    let scan = tokio::task::spawn_blocking(move || {
+        scan_directories(&directories, &cache, |done, total, path| {
+            let payload = json!({ "done": done, "total": total, "path": path });
+            if let Err(e) = app.emit("model-library-progress", payload) {
+                error!("❌ Event emit hatası: {}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| format!("Scan task failed: {}", e))?;
+
+    let changes = with_library(true, |library| {
+        if include_removed {
+            library.ignored.clear();
+        }
+        Ok(library.apply_scan(scan))
+    })?;
+    info!(
+        "✅ Model library: {} models, {} new, {} gone, {} duplicate files, {} errors",
+        changes.total, changes.added.len(), changes.removed.len(), changes.duplicate_files, changes.errors.len()
+    );
+    Ok(changes)
+}
+
+#[tauri::command]
+pub async fn search_library_models(query: String, tags: Option<Vec<String>>) -> Result<Vec<LibraryModel>, String> {
+    with_library(false, |library| Ok(library.search(&query, &tags.unwrap_or_default())))
+}
+
+/// 🏷️ Replace the tags of a library model
+#[tauri::command]
+pub async fn tag_library_model(model_id: String, tags: Vec<String>) -> Result<LibraryModel, String> {
+    with_library(true, |library| library.set_tags(&model_id, &tags))
+}
+
+/// 🗑️ Remove a model from the library. With `delete_files` every file of it (all shards
+/// and copies) is deleted from disk; otherwise the files stay and later scans skip it.
+#[tauri::command]
+pub async fn remove_library_model(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    model_id: String,
+    delete_files: Option<bool>,
+) -> Result<LibraryModel, String> {
+    let delete_files = delete_files.unwrap_or(false);
+    let model = with_library(false, |library| library.get(&model_id).cloned())?;
+
+    if delete_files {
+        let files = model.all_files();
+        {
+            let state_guard = lock_state(&state);
+            if let Some(loaded) = files.iter().find(|f| state_guard.models.contains_key(f.as_str())) {
+                return Err(format!("Model şu an yüklü, önce kaldırın: {}", loaded));
+            }
+        }
+        for file in &files {
+            match fs::remove_file(file) {
+                Ok(()) => info!("🗑️ Deleted {}", file),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Dosya silinemedi {}: {}", file, e)),
+            }
+        }
+    }
+
+    with_library(true, |library| {
+        let removed = library.remove(&model_id, !delete_files)?;
+        if delete_files {
+            for file in removed.all_files() {
+                library.file_hashes.remove(&file);
+            }
+        }
+        Ok(removed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_header::tests::GgufBuilder;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("corex_gguf_library_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn qwen() -> GgufBuilder {
+        GgufBuilder::new()
+            .kv_str("general.architecture", "qwen2")
+            .kv_str("general.name", "Qwen2.5 Coder 7B Instruct")
+            .kv_u32("general.file_type", 15)
+            .kv_u32("qwen2.context_length", 32768)
+            .tensor("token_embd.weight", &[256, 4], 0)
+    }
+
+    fn split_part(n: u16) -> GgufBuilder {
+        GgufBuilder::new()
+            .kv_str("general.architecture", "llama")
+            .kv_u16("split.no", n)
+            .kv_u16("split.count", 2)
+            .tensor(&format!("blk.{}.weight", n), &[64], 0)
+    }
+
+    fn scan(dir: &Path, cache: &BTreeMap<String, HashedFile>) -> ScanResult {
+        scan_directories(&[dir.to_string_lossy().to_string()], cache, |_, _, _| {})
+    }
+
+    #[test]
+    fn test_scan_groups_shards_and_duplicates() {
+        let dir = test_dir("scan");
+        fs::create_dir_all(dir.join("work")).unwrap();
+        qwen().write_to(&dir.join("qwen-coder-q4_k_m.gguf"));
+        qwen().write_to(&dir.join("work").join("qwen-copy.gguf"));
+        split_part(0).write_to(&dir.join("big-00001-of-00002.gguf"));
+        split_part(1).write_to(&dir.join("big-00002-of-00002.gguf"));
+        split_part(1).write_to(&dir.join("orphan-00002-of-00002.gguf"));
+        GgufBuilder::new().kv_str("general.architecture", "clip").write_to(&dir.join("mmproj-qwen-f16.gguf"));
+        fs::write(dir.join("broken.gguf"), b"not a gguf").unwrap();
+
+        let result = scan(&dir, &BTreeMap::new());
+        assert_eq!(result.models.len(), 2);
+        assert_eq!(result.errors.len(), 2, "{:?}", result.errors);
+
+        let big = &result.models[0];
+        assert_eq!(big.name, "big");
+        assert_eq!(big.shard_paths.len(), 2);
+        assert_eq!(big.architecture.as_deref(), Some("llama"));
+        assert_eq!(big.parameter_count, 128);
+        assert_eq!(big.sha256.len(), 64);
+
+        let qwen = &result.models[1];
+        assert_eq!(qwen.name, "Qwen2.5 Coder 7B Instruct");
+        assert_eq!(qwen.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(qwen.context_length, Some(32768));
+        assert!(qwen.path.ends_with("qwen-coder-q4_k_m.gguf"));
+        assert_eq!(qwen.duplicates.len(), 1);
+        assert!(qwen.duplicates[0].ends_with("qwen-copy.gguf"));
+        assert_eq!(qwen.id, qwen.sha256[..ID_LENGTH]);
+        assert_eq!(result.file_hashes.len(), 4);
+
+        // Unchanged files take their hash from the cache
+        let mut cache = result.file_hashes.clone();
+        for hashed in cache.values_mut() {
+            hashed.sha256 = "ab".repeat(32);
+        }
+        let rescan = scan(&dir, &cache);
+        assert_eq!(rescan.models[1].sha256, "ab".repeat(32));
+        assert_eq!(rescan.models[1].duplicates.len(), 1);
+    }
+
+    fn model(id: &str, name: &str, tags: &[&str]) -> LibraryModel {
+        LibraryModel {
+            id: id.to_string(),
+            name: name.to_string(),
+            path: format!("/models/{}.gguf", id),
+            shard_paths: vec![format!("/models/{}.gguf", id)],
+            duplicates: Vec::new(),
+            architecture: Some("qwen2".to_string()),
+            parameters: Some("7B".to_string()),
+            parameter_count: 7_000_000_000,
+            quantization: Some("Q4_K_M".to_string()),
+            context_length: Some(32768),
+            size_bytes: 4_683_073_536,
+            sha256: format!("{}-hash", id),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            added_at: 1,
+            modified_at: 1,
+        }
+    }
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tags_search_and_remove() {
+        let mut library = ModelLibrary {
+            models: vec![model("coder", "Qwen2.5 Coder", &[]), model("chat", "Llama Chat", &["chat"])],
+            ..Default::default()
+        };
+
+        let tagged = library.set_tags("coder", &tags(&[" Code ", "fast", "code", ""])).unwrap();
+        assert_eq!(tagged.tags, tags(&["code", "fast"]));
+        assert!(library.set_tags("missing", &[]).is_err());
+
+        assert_eq!(library.search("", &[]).len(), 2);
+        assert_eq!(library.search("qwen q4_k", &[])[0].id, "coder");
+        assert_eq!(library.search("llama", &tags(&["Chat"]))[0].id, "chat");
+        assert!(library.search("llama", &tags(&["code"])).is_empty());
+
+        // A scan keeps tags and leaves removed models out
+        let removed = library.remove("chat", true).unwrap();
+        assert_eq!(removed.name, "Llama Chat");
+        let mut rescanned = model("coder", "Qwen2.5 Coder", &[]);
+        rescanned.added_at = 99;
+        let changes = library.apply_scan(ScanResult {
+            models: vec![rescanned, model("chat", "Llama Chat", &[]), model("new", "New", &[])],
+            ..Default::default()
+        });
+        assert_eq!(changes.added, tags(&["new"]));
+        assert!(changes.removed.is_empty());
+        assert_eq!(library.models.len(), 2);
+        assert_eq!(library.get("coder").unwrap().tags, tags(&["code", "fast"]));
+        assert_eq!(library.get("coder").unwrap().added_at, 1);
+
+        let path = test_dir("persist").join("model_library.json");
+        library.save(&path).unwrap();
+        assert_eq!(ModelLibrary::load(&path).unwrap(), library);
+    }
+}
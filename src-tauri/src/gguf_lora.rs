@@ -0,0 +1,169 @@
+// src-tauri/src/gguf_lora.rs
+// LoRA adapters attached to pooled GGUF models and per-request adapter selection
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::gguf_header::GgufHeader;
+
+/// An adapter attached to a pooled model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LoraInfo {
+    pub name: String,
+    pub path: String,
+    /// Scale used when a request does not choose adapters itself
+    pub scale: f32,
+    pub size_bytes: u64,
+}
+
+/// Adapter chosen by a single chat request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoraRequest {
+    pub name: String,
+    /// Overrides the adapter's configured scale for this request
+    #[serde(default)]
+    pub scale: Option<f32>,
+}
+
+/// Adapter as applied to a context
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveLora {
+    pub name: String,
+    pub scale: f32,
+}
+
+/// File stem of the adapter path, e.g. `/loras/fastapi-r16.gguf` -> `fastapi-r16`
+pub fn default_lora_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("lora")
+        .to_string()
+}
+
+pub fn check_scale(scale: f32) -> Result<f32, String> {
+    if scale.is_finite() {
+        Ok(scale)
+    } else {
+        Err(format!("Geçersiz LoRA scale: {}", scale))
+    }
+}
+
+/// Reject GGUF files that are not LoRA adapters or were trained for another architecture.
+/// Adapters converted before `general.type` existed only fail on an architecture mismatch.
+pub fn check_adapter_header(header: &GgufHeader, base_architecture: Option<&str>) -> Result<(), String> {
+    if let Some(kind) = header.get_str("general.type") {
+        if kind != "adapter" {
+            return Err(format!("Dosya bir LoRA adapter değil (general.type = {})", kind));
+        }
+    }
+    if let Some(kind) = header.get_str("adapter.type") {
+        if kind != "lora" {
+            return Err(format!("Desteklenmeyen adapter türü: {}", kind));
+        }
+    }
+    if let (Some(adapter), Some(base)) = (header.architecture(), base_architecture) {
+        if adapter != base {
+            return Err(format!("LoRA {} için eğitilmiş, model mimarisi {}", adapter, base));
+        }
+    }
+    Ok(())
+}
+
+/// Adapters a request runs with: every attached adapter when `requested` is None,
+/// otherwise exactly the requested ones (an empty list means the plain base model).
+/// Adapters with scale 0 are left out.
+pub fn resolve_loras(attached: &[LoraInfo], requested: Option<&[LoraRequest]>) -> Result<Vec<ActiveLora>, String> {
+    let mut active: Vec<ActiveLora> = match requested {
+        None => attached
+            .iter()
+            .map(|lora| ActiveLora { name: lora.name.clone(), scale: lora.scale })
+            .collect(),
+        Some(requested) => requested
+            .iter()
+            .map(|request| {
+                let lora = attached
+                    .iter()
+                    .find(|lora| lora.name == request.name)
+                    .ok_or_else(|| format!("LoRA adapter yüklü değil: {}", request.name))?;
+                let scale = check_scale(request.scale.unwrap_or(lora.scale))?;
+                Ok(ActiveLora { name: lora.name.clone(), scale })
+            })
+            .collect::<Result<_, String>>()?,
+    };
+
+    active.retain(|lora| lora.scale != 0.0);
+    let mut seen = std::collections::HashSet::new();
+    active.retain(|lora| seen.insert(lora.name.clone()));
+    Ok(active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_header::tests::GgufBuilder;
+    use std::io::Cursor;
+
+    fn attached() -> Vec<LoraInfo> {
+        ["django", "fastapi"]
+            .iter()
+            .map(|name| LoraInfo {
+                name: name.to_string(),
+                path: format!("/loras/{}.gguf", name),
+                scale: 1.0,
+                size_bytes: 0,
+            })
+            .collect()
+    }
+
+    fn active(name: &str, scale: f32) -> ActiveLora {
+        ActiveLora { name: name.to_string(), scale }
+    }
+
+    #[test]
+    fn test_resolve_defaults_and_selection() {
+        let mut loras = attached();
+        loras[1].scale = 0.0;
+        assert_eq!(resolve_loras(&loras, None).unwrap(), vec![active("django", 1.0)]);
+        assert_eq!(resolve_loras(&loras, Some(&[])).unwrap(), vec![]);
+
+        let request = vec![
+            LoraRequest { name: "fastapi".to_string(), scale: Some(0.5) },
+            LoraRequest { name: "fastapi".to_string(), scale: None },
+        ];
+        assert_eq!(resolve_loras(&loras, Some(&request)).unwrap(), vec![active("fastapi", 0.5)]);
+
+        let unknown = vec![LoraRequest { name: "rails".to_string(), scale: None }];
+        assert!(resolve_loras(&loras, Some(&unknown)).unwrap_err().contains("rails"));
+    }
+
+    #[test]
+    fn test_adapter_header_checks() {
+        let read = |bytes: Vec<u8>| GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap();
+
+        let lora = read(
+            GgufBuilder::new()
+                .kv_str("general.architecture", "qwen2")
+                .kv_str("general.type", "adapter")
+                .kv_str("adapter.type", "lora")
+                .build(),
+        );
+        assert!(check_adapter_header(&lora, Some("qwen2")).is_ok());
+        assert!(check_adapter_header(&lora, Some("llama")).is_err());
+
+        let model = read(
+            GgufBuilder::new()
+                .kv_str("general.architecture", "qwen2")
+                .kv_str("general.type", "model")
+                .build(),
+        );
+        assert!(check_adapter_header(&model, Some("qwen2")).is_err());
+    }
+
+    #[test]
+    fn test_default_name_and_scale() {
+        assert_eq!(default_lora_name("/loras/fastapi-r16.gguf"), "fastapi-r16");
+        assert!(check_scale(f32::NAN).is_err());
+        assert_eq!(check_scale(-0.5), Ok(-0.5));
+    }
+}
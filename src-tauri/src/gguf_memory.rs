@@ -0,0 +1,161 @@
+// src-tauri/src/gguf_memory.rs
+// Memory accounting for loaded GGUF models: weights and KV cache, split between RAM and VRAM
+
+use serde::Serialize;
+
+use crate::gguf_header::GgufHeader;
+use crate::gguf_pool::ModelFootprint;
+
+/// Attention hyperparameters that determine the KV cache size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ModelHyperparams {
+    pub n_layer: u64,
+    pub n_embd: u64,
+    pub n_head: u64,
+    /// Fewer than `n_head` with grouped-query attention
+    pub n_head_kv: u64,
+    pub head_dim_k: u64,
+    pub head_dim_v: u64,
+}
+
+impl ModelHyperparams {
+    /// Read `<arch>.block_count`, `<arch>.attention.*` etc. Returns None for
+    /// headers without the basic transformer keys.
+    pub fn from_header(header: &GgufHeader) -> Option<Self> {
+        let n_layer = header.arch_u64("block_count")?;
+        let n_embd = header.arch_u64("embedding_length")?;
+        let n_head = header.arch_u64("attention.head_count").filter(|&n| n > 0)?;
+        // Per-layer head counts come as arrays; those fall back to the plain head count
+        let n_head_kv = header.arch_u64("attention.head_count_kv").unwrap_or(n_head);
+        let head_dim_k = header.arch_u64("attention.key_length").unwrap_or(n_embd / n_head);
+        let head_dim_v = header.arch_u64("attention.value_length").unwrap_or(n_embd / n_head);
+
+        Some(Self { n_layer, n_embd, n_head, n_head_kv, head_dim_k, head_dim_v })
+    }
+
+    /// K + V elements stored per token per layer
+    fn kv_elements_per_token(&self) -> u64 {
+        self.n_head_kv * (self.head_dim_k + self.head_dim_v)
+    }
+}
+
+/// Element type of the K/V cache tensors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KvCacheType {
+    F32,
+    /// llama.cpp's default
+    #[default]
+    F16,
+    Q8_0,
+    Q4_0,
+}
+
+impl KvCacheType {
+    /// Average bytes per element, including the per-block scale of quantized types
+    pub fn bytes_per_element(&self) -> f64 {
+        match self {
+            KvCacheType::F32 => 4.0,
+            KvCacheType::F16 => 2.0,
+            KvCacheType::Q8_0 => 34.0 / 32.0,
+            KvCacheType::Q4_0 => 18.0 / 32.0,
+        }
+    }
+}
+
+/// KV cache bytes for `kv_size` cells. With KV offload (llama.cpp's default) the
+/// cache of each GPU layer lives in VRAM, the rest in RAM.
+pub fn kv_cache_footprint(
+    hparams: &ModelHyperparams,
+    kv_size: u32,
+    cache_type: KvCacheType,
+    n_gpu_layers: u32,
+) -> ModelFootprint {
+    let per_layer = (hparams.kv_elements_per_token() as f64
+        * kv_size as f64
+        * cache_type.bytes_per_element()) as u64;
+    let gpu_layers = (n_gpu_layers as u64).min(hparams.n_layer);
+
+    ModelFootprint {
+        ram_bytes: per_layer * (hparams.n_layer - gpu_layers),
+        vram_bytes: per_layer * gpu_layers,
+    }
+}
+
+/// Memory use of one loaded model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryBreakdown {
+    pub weights: ModelFootprint,
+    pub kv_cache: ModelFootprint,
+}
+
+impl MemoryBreakdown {
+    pub fn total(&self) -> ModelFootprint {
+        ModelFootprint {
+            ram_bytes: self.weights.ram_bytes + self.kv_cache.ram_bytes,
+            vram_bytes: self.weights.vram_bytes + self.kv_cache.vram_bytes,
+        }
+    }
+}
+
+pub fn bytes_to_gb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_header::tests::GgufBuilder;
+    use std::io::Cursor;
+
+    fn qwen_like_header() -> GgufHeader {
+        let bytes = GgufBuilder::new()
+            .kv_str("general.architecture", "qwen2")
+            .kv_u32("qwen2.block_count", 28)
+            .kv_u32("qwen2.embedding_length", 3584)
+            .kv_u32("qwen2.attention.head_count", 28)
+            .kv_u32("qwen2.attention.head_count_kv", 4)
+            .build();
+        GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_hyperparams_from_header() {
+        let hp = ModelHyperparams::from_header(&qwen_like_header()).unwrap();
+        assert_eq!(hp.n_layer, 28);
+        assert_eq!(hp.n_head_kv, 4);
+        assert_eq!(hp.head_dim_k, 128);
+        assert_eq!(hp.head_dim_v, 128);
+
+        let bytes = GgufBuilder::new().kv_str("general.architecture", "clip").build();
+        let header = GgufHeader::read_from(&mut Cursor::new(bytes)).unwrap();
+        assert!(ModelHyperparams::from_header(&header).is_none());
+    }
+
+    #[test]
+    fn test_kv_cache_size_gqa() {
+        let hp = ModelHyperparams::from_header(&qwen_like_header()).unwrap();
+
+        // 28 layers * 4096 cells * 4 kv heads * (128 + 128) * 2 bytes = 224 MiB
+        let cpu = kv_cache_footprint(&hp, 4096, KvCacheType::F16, 0);
+        assert_eq!(cpu, ModelFootprint { ram_bytes: 224 * 1024 * 1024, vram_bytes: 0 });
+
+        let split = kv_cache_footprint(&hp, 4096, KvCacheType::F16, 7);
+        assert_eq!(split.vram_bytes, 56 * 1024 * 1024);
+        assert_eq!(split.ram_bytes, 168 * 1024 * 1024);
+
+        let all_gpu = kv_cache_footprint(&hp, 4096, KvCacheType::Q8_0, 99);
+        assert_eq!(all_gpu.ram_bytes, 0);
+        assert_eq!(all_gpu.vram_bytes, 224 * 1024 * 1024 / 32 * 17);
+    }
+
+    #[test]
+    fn test_breakdown_total() {
+        let breakdown = MemoryBreakdown {
+            weights: ModelFootprint { ram_bytes: 10, vram_bytes: 20 },
+            kv_cache: ModelFootprint { ram_bytes: 1, vram_bytes: 2 },
+        };
+        assert_eq!(breakdown.total(), ModelFootprint { ram_bytes: 11, vram_bytes: 22 });
+        assert_eq!(bytes_to_gb(1024 * 1024 * 1024), 1.0);
+    }
+}
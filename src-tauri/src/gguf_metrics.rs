@@ -0,0 +1,173 @@
+// src-tauri/src/gguf_metrics.rs
+// Timings of local GGUF generations and the standard prompt set of `benchmark_gguf_model`
+
+use serde::Serialize;
+use std::time::Instant;
+
+/// Why a generation ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// End-of-generation token, or a grammar that allows nothing more
+    Eos,
+    #[default]
+    MaxTokens,
+    StopSequence,
+    Cancelled,
+}
+
+/// Timings of a generation run, all durations in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct GenerationMetrics {
+    /// How long `load_gguf_model` took for the model that served the request
+    pub load_ms: f64,
+    /// Time spent waiting in the model's request queue
+    pub queue_ms: f64,
+    pub prompt_tokens: usize,
+    /// Prompt tokens that were evaluated, the rest came from the KV cache
+    pub prompt_eval_tokens: usize,
+    pub prompt_eval_ms: f64,
+    pub prompt_tokens_per_second: f64,
+    pub generated_tokens: usize,
+    /// Sampling and decoding of the reply, after the prompt was evaluated
+    pub generation_ms: f64,
+    pub generation_tokens_per_second: f64,
+    /// From the request reaching the backend to the first generated token, None when nothing was generated
+    pub time_to_first_token_ms: Option<f64>,
+    pub total_ms: f64,
+    pub stop_reason: StopReason,
+}
+
+impl GenerationMetrics {
+    /// Derive the throughput figures once the counts and durations are in
+    pub fn finish(&mut self) {
+        self.prompt_tokens_per_second = tokens_per_second(self.prompt_eval_tokens, self.prompt_eval_ms);
+        self.generation_tokens_per_second = tokens_per_second(self.generated_tokens, self.generation_ms);
+    }
+}
+
+pub fn elapsed_ms(since: Instant) -> f64 {
+    since.elapsed().as_secs_f64() * 1000.0
+}
+
+pub fn tokens_per_second(tokens: usize, ms: f64) -> f64 {
+    if ms > 0.0 {
+        tokens as f64 * 1000.0 / ms
+    } else {
+        0.0
+    }
+}
+
+/// Stop reason of a run whose decode loop did not record one
+pub fn stop_reason(cancelled: bool, stop_sequence: bool, generated: usize, max_tokens: u32) -> StopReason {
+    if cancelled {
+        StopReason::Cancelled
+    } else if stop_sequence {
+        StopReason::StopSequence
+    } else if generated >= max_tokens as usize {
+        StopReason::MaxTokens
+    } else {
+        StopReason::Eos
+    }
+}
+
+/// Standard benchmark prompts: (name, user message). A short question, code generation,
+/// and a long input that mostly measures prompt evaluation.
+pub fn benchmark_prompts() -> Vec<(&'static str, String)> {
+    let article = "Local language models run entirely on the user's machine. The weights are \
+        stored in a quantized GGUF file, the prompt is evaluated in large batches and the \
+        reply is generated one token at a time, each step reading every weight once. "
+        .repeat(12);
+    vec![
+        ("short_answer", "What is the capital of France? Answer in one sentence.".to_string()),
+        (
+            "code",
+            "Write a Rust function that returns the n-th Fibonacci number iteratively, with a doc comment.".to_string(),
+        ),
+        ("long_prompt", format!("Summarize the following text in three bullet points.\n\n{}", article)),
+    ]
+}
+
+/// Averages over the runs of a benchmark
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct BenchmarkSummary {
+    pub runs: usize,
+    pub prompt_tokens_per_second: f64,
+    pub generation_tokens_per_second: f64,
+    pub time_to_first_token_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Token-weighted throughput (total tokens over total time) and mean latencies
+pub fn summarize(runs: &[GenerationMetrics]) -> BenchmarkSummary {
+    if runs.is_empty() {
+        return BenchmarkSummary::default();
+    }
+    let sum = |f: fn(&GenerationMetrics) -> f64| runs.iter().map(f).sum::<f64>();
+    let first_tokens: Vec<f64> = runs.iter().filter_map(|m| m.time_to_first_token_ms).collect();
+    BenchmarkSummary {
+        runs: runs.len(),
+        prompt_tokens_per_second: tokens_per_second(
+            runs.iter().map(|m| m.prompt_eval_tokens).sum(),
+            sum(|m| m.prompt_eval_ms),
+        ),
+        generation_tokens_per_second: tokens_per_second(
+            runs.iter().map(|m| m.generated_tokens).sum(),
+            sum(|m| m.generation_ms),
+        ),
+        time_to_first_token_ms: if first_tokens.is_empty() {
+            0.0
+        } else {
+            first_tokens.iter().sum::<f64>() / first_tokens.len() as f64
+        },
+        total_ms: sum(|m| m.total_ms) / runs.len() as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_and_stop_reason() {
+        let mut metrics = GenerationMetrics {
+            prompt_eval_tokens: 512,
+            prompt_eval_ms: 250.0,
+            generated_tokens: 100,
+            generation_ms: 4000.0,
+            ..Default::default()
+        };
+        metrics.finish();
+        assert_eq!(metrics.prompt_tokens_per_second, 2048.0);
+        assert_eq!(metrics.generation_tokens_per_second, 25.0);
+        assert_eq!(tokens_per_second(10, 0.0), 0.0);
+
+        assert_eq!(stop_reason(true, true, 5, 5), StopReason::Cancelled);
+        assert_eq!(stop_reason(false, true, 5, 5), StopReason::StopSequence);
+        assert_eq!(stop_reason(false, false, 5, 5), StopReason::MaxTokens);
+        assert_eq!(stop_reason(false, false, 3, 5), StopReason::Eos);
+        assert_eq!(serde_json::to_value(StopReason::StopSequence).unwrap(), "stop_sequence");
+    }
+
+    #[test]
+    fn test_summarize() {
+        let run = |prompt_ms: f64, gen_tokens: usize, ttft: Option<f64>| GenerationMetrics {
+            prompt_eval_tokens: 100,
+            prompt_eval_ms: prompt_ms,
+            generated_tokens: gen_tokens,
+            generation_ms: 1000.0,
+            time_to_first_token_ms: ttft,
+            total_ms: 2000.0,
+            ..Default::default()
+        };
+        let summary = summarize(&[run(100.0, 10, Some(150.0)), run(300.0, 30, Some(350.0)), run(0.0, 0, None)]);
+        assert_eq!(summary.runs, 3);
+        // 300 tokens in 400 ms, 40 tokens in 3 s
+        assert_eq!(summary.prompt_tokens_per_second, 750.0);
+        assert!((summary.generation_tokens_per_second - 40.0 / 3.0).abs() < 1e-9);
+        assert_eq!(summary.time_to_first_token_ms, 250.0);
+        assert_eq!(summary.total_ms, 2000.0);
+        assert_eq!(summarize(&[]), BenchmarkSummary::default());
+        assert_eq!(benchmark_prompts().len(), 3);
+    }
+}
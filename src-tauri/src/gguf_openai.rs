@@ -0,0 +1,599 @@
+// src-tauri/src/gguf_openai.rs
+// OpenAI-compatible request/response shapes for the local GGUF server (/v1/chat/completions, ...)
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::commands::ChatMessage;
+use crate::gguf_grammar::OutputConstraint;
+use crate::gguf_inference::TokenLogprob;
+use crate::gguf_sampling::SamplingParams;
+
+/// Used when a request does not set `max_tokens`
+pub const DEFAULT_MAX_TOKENS: u32 = 2048;
+/// Used when a request does not set `temperature`, same as the in-app chat
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+/// Terminates an SSE stream
+pub const SSE_DONE: &str = "data: [DONE]\n\n";
+
+/// A single string or a list of strings, both are accepted for `stop`, `prompt` and `input`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+/// Generation fields shared by chat and text completions
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CompletionOptions {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Newer name of `max_tokens` in the chat API
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub stop: Option<OneOrMany>,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+impl CompletionOptions {
+    pub fn max_tokens(&self) -> u32 {
+        self.max_completion_tokens.or(self.max_tokens).unwrap_or(DEFAULT_MAX_TOKENS)
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature.unwrap_or(DEFAULT_TEMPERATURE)
+    }
+
+    pub fn sampling(&self) -> SamplingParams {
+        let mut sampling = SamplingParams::default();
+        if let Some(top_p) = self.top_p {
+            sampling.top_p = top_p;
+        }
+        if let Some(penalty) = self.frequency_penalty {
+            sampling.frequency_penalty = penalty;
+        }
+        if let Some(penalty) = self.presence_penalty {
+            sampling.presence_penalty = penalty;
+        }
+        sampling.seed = self.seed;
+        sampling
+    }
+
+    pub fn stop(&self) -> Vec<String> {
+        let stop = self.stop.clone().map(OneOrMany::into_vec).unwrap_or_default();
+        stop.into_iter().filter(|s| !s.is_empty()).collect()
+    }
+}
+
+/// Message content: plain text or a list of typed parts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContentPart {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenAiMessage {
+    pub role: String,
+    /// Missing or null on assistant messages that only carried tool calls
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub json_schema: Option<Value>,
+}
+
+/// Body of `POST /v1/chat/completions`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub messages: Vec<OpenAiMessage>,
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(default)]
+    pub logprobs: bool,
+    #[serde(default)]
+    pub top_logprobs: Option<u32>,
+    #[serde(flatten)]
+    pub options: CompletionOptions,
+}
+
+impl ChatCompletionRequest {
+    /// Messages as the chat template expects them; only text parts are supported
+    pub fn chat_messages(&self) -> Result<Vec<ChatMessage>, String> {
+        self.messages
+            .iter()
+            .map(|message| {
+                let content = match &message.content {
+                    None => String::new(),
+                    Some(MessageContent::Text(text)) => text.clone(),
+                    Some(MessageContent::Parts(parts)) => {
+                        let mut text = String::new();
+                        for part in parts {
+                            match (part.kind.as_str(), &part.text) {
+                                ("text", Some(part_text)) => text.push_str(part_text),
+                                (kind, _) => return Err(format!("Desteklenmeyen mesaj içeriği: {}", kind)),
+                            }
+                        }
+                        text
+                    }
+                };
+                Ok(ChatMessage { role: message.role.clone(), content })
+            })
+            .collect()
+    }
+
+    /// Alternatives to report per token, None when `logprobs` is off
+    pub fn logprobs(&self) -> Option<u32> {
+        self.logprobs.then(|| self.top_logprobs.unwrap_or(0))
+    }
+
+    /// `response_format` as a grammar constraint
+    pub fn constraint(&self) -> Result<Option<OutputConstraint>, String> {
+        let Some(format) = &self.response_format else { return Ok(None) };
+        match format.kind.as_str() {
+            "text" => Ok(None),
+            "json_object" => Ok(Some(OutputConstraint::Json)),
+            "json_schema" => {
+                let schema = format
+                    .json_schema
+                    .as_ref()
+                    .and_then(|s| s.get("schema"))
+                    .cloned()
+                    .ok_or("response_format.json_schema.schema eksik")?;
+                Ok(Some(OutputConstraint::JsonSchema { schema }))
+            }
+            other => Err(format!("Desteklenmeyen response_format: {}", other)),
+        }
+    }
+}
+
+/// Body of `POST /v1/completions`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionRequest {
+    pub prompt: OneOrMany,
+    /// Legacy API: the number of alternatives per token, which also turns logprobs on
+    #[serde(default)]
+    pub logprobs: Option<u32>,
+    #[serde(flatten)]
+    pub options: CompletionOptions,
+}
+
+impl CompletionRequest {
+    /// Batched prompts are not supported, a list must hold exactly one prompt
+    pub fn prompt(&self) -> Result<String, String> {
+        match &self.prompt {
+            OneOrMany::One(prompt) => Ok(prompt.clone()),
+            OneOrMany::Many(prompts) if prompts.len() == 1 => Ok(prompts[0].clone()),
+            OneOrMany::Many(prompts) => Err(format!("Tek istekte yalnızca bir prompt desteklenir ({} verildi)", prompts.len())),
+        }
+    }
+}
+
+/// Body of `POST /v1/embeddings`
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingRequest {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub input: OneOrMany,
+}
+
+/// Model ID shown to clients: the file name without `.gguf`
+pub fn model_id(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// Pick the pooled model a request asked for. `models` are `(path, last_used)` pairs.
+/// No model, `""` and `"default"` select the most recently used one; otherwise the
+/// full path, the file name and the model ID are accepted.
+pub fn resolve_model<'a>(models: &[(&'a str, u64)], requested: Option<&str>) -> Result<&'a str, String> {
+    match requested.map(str::trim).filter(|m| !m.is_empty() && *m != "default") {
+        None => models
+            .iter()
+            .max_by_key(|(_, last_used)| *last_used)
+            .map(|(path, _)| *path)
+            .ok_or_else(|| "Havuzda yüklü GGUF model yok".to_string()),
+        Some(requested) => models
+            .iter()
+            .map(|(path, _)| *path)
+            .find(|path| {
+                *path == requested
+                    || Path::new(path).file_name().and_then(|n| n.to_str()) == Some(requested)
+                    || model_id(path) == requested
+            })
+            .ok_or_else(|| format!("Model bulunamadı: {}", requested)),
+    }
+}
+
+/// Accept any request when no key is configured, otherwise require `Bearer <key>`
+pub fn check_bearer(authorization: Option<&str>, api_key: Option<&str>) -> bool {
+    match api_key {
+        None => true,
+        Some(key) => authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim() == key)
+            .unwrap_or(false),
+    }
+}
+
+/// Only `127.0.0.1`, `localhost` and `[::1]` are served. A page the user visits can point
+/// its own host name at 127.0.0.1 (DNS rebinding); its requests then carry that name.
+pub fn is_local_host(host: Option<&str>) -> bool {
+    let Some(host) = host.map(str::trim) else {
+        return false;
+    };
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name == "127.0.0.1" || name == "::1" || name.eq_ignore_ascii_case("localhost")
+}
+
+/// Browsers set `Origin` on cross-site requests; those are only accepted from local pages.
+/// Requests without it come from non-browser clients.
+pub fn is_local_origin(origin: Option<&str>) -> bool {
+    match origin.map(str::trim) {
+        None => true,
+        Some(origin) => origin
+            .split_once("://")
+            .map(|(_, rest)| is_local_host(Some(rest.split('/').next().unwrap_or_default())))
+            .unwrap_or(false),
+    }
+}
+
+/// A form or `text/plain` POST needs no CORS preflight, so only JSON bodies are accepted
+pub fn is_json_content_type(content_type: Option<&str>) -> bool {
+    content_type
+        .and_then(|value| value.split(';').next())
+        .map(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"))
+        .unwrap_or(false)
+}
+
+/// A `data:` line of a `/v1/completions` SSE stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum SseData {
+    Text(String),
+    Done,
+}
+
+/// Parse one line of a completion stream, None for anything that carries no text
+pub fn parse_completion_sse_line(line: &str) -> Option<SseData> {
+    let data = line.strip_prefix("data: ")?;
+    if data.trim() == "[DONE]" {
+        return Some(SseData::Done);
+    }
+    let json: Value = serde_json::from_str(data).ok()?;
+    json["choices"][0]["text"].as_str().map(|text| SseData::Text(text.to_string()))
+}
+
+/// `length` when the token limit ended the run, `stop` for EOS or a stop string
+pub fn finish_reason(completion_tokens: usize, max_tokens: u32, hit_stop_sequence: bool) -> &'static str {
+    if !hit_stop_sequence && completion_tokens >= max_tokens as usize {
+        "length"
+    } else {
+        "stop"
+    }
+}
+
+pub fn usage(prompt_tokens: usize, completion_tokens: usize) -> Value {
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+pub fn error_body(message: &str, kind: &str) -> Value {
+    json!({ "error": { "message": message, "type": kind, "code": null } })
+}
+
+/// One server-sent event
+pub fn sse_event(data: &Value) -> String {
+    format!("data: {}\n\n", data)
+}
+
+pub fn model_list(models: &[(String, u32)]) -> Value {
+    let data: Vec<Value> = models
+        .iter()
+        .map(|(path, n_ctx)| {
+            json!({
+                "id": model_id(path),
+                "object": "model",
+                "created": 0,
+                "owned_by": "corex",
+                "path": path,
+                "n_ctx": n_ctx,
+            })
+        })
+        .collect();
+    json!({ "object": "list", "data": data })
+}
+
+/// `logprobs` of a chat choice, `null` when they were not requested
+pub fn chat_logprobs(logprobs: Option<&[TokenLogprob]>) -> Value {
+    let Some(logprobs) = logprobs else { return Value::Null };
+    let entry = |token: &str, logprob: f32| json!({ "token": token, "logprob": logprob, "bytes": token.as_bytes() });
+    let content: Vec<Value> = logprobs
+        .iter()
+        .map(|t| {
+            let mut item = entry(&t.token, t.logprob);
+            item["top_logprobs"] = t.top_logprobs.iter().map(|alt| entry(&alt.token, alt.logprob)).collect();
+            item
+        })
+        .collect();
+    json!({ "content": content })
+}
+
+/// Legacy `logprobs` of a text completion choice, `null` when they were not requested
+pub fn text_logprobs(logprobs: Option<&[TokenLogprob]>) -> Value {
+    let Some(logprobs) = logprobs else { return Value::Null };
+    let mut offset = 0;
+    let mut text_offset = Vec::with_capacity(logprobs.len());
+    for t in logprobs {
+        text_offset.push(offset);
+        offset += t.token.len();
+    }
+    let top: Vec<Value> = logprobs
+        .iter()
+        .map(|t| t.top_logprobs.iter().map(|alt| (alt.token.clone(), json!(alt.logprob))).collect())
+        .collect();
+    json!({
+        "tokens": logprobs.iter().map(|t| t.token.as_str()).collect::<Vec<_>>(),
+        "token_logprobs": logprobs.iter().map(|t| t.logprob).collect::<Vec<_>>(),
+        "top_logprobs": top,
+        "text_offset": text_offset,
+    })
+}
+
+pub fn chat_completion(id: &str, model: &str, created: u64, content: &str, finish_reason: &str, usage: Value, logprobs: Value) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "logprobs": logprobs,
+            "finish_reason": finish_reason,
+        }],
+        "usage": usage,
+    })
+}
+
+/// Streaming chat chunk; `delta` is `{"role": ...}`, `{"content": ...}` or `{}` on the last chunk
+pub fn chat_chunk(id: &str, model: &str, created: u64, delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    })
+}
+
+pub fn text_completion(id: &str, model: &str, created: u64, text: &str, finish_reason: &str, usage: Value, logprobs: Value) -> Value {
+    json!({
+        "id": id,
+        "object": "text_completion",
+        "created": created,
+        "model": model,
+        "choices": [{ "index": 0, "text": text, "logprobs": logprobs, "finish_reason": finish_reason }],
+        "usage": usage,
+    })
+}
+
+/// Streaming text chunk, the shape `chat_with_http_streaming` reads (`choices[0].text`)
+pub fn text_chunk(id: &str, model: &str, created: u64, text: &str, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": id,
+        "object": "text_completion",
+        "created": created,
+        "model": model,
+        "choices": [{ "index": 0, "text": text, "logprobs": null, "finish_reason": finish_reason }],
+    })
+}
+
+/// Token counts are not tracked for embeddings, usage is reported as zero
+pub fn embedding_list(model: &str, embeddings: Vec<Vec<f32>>) -> Value {
+    let data: Vec<Value> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| json!({ "object": "embedding", "index": index, "embedding": embedding }))
+        .collect();
+    json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        "usage": { "prompt_tokens": 0, "total_tokens": 0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_inference::TopLogprob;
+
+    #[test]
+    fn test_parse_chat_request() {
+        let body = r#"{
+            "model": "qwen2.5-coder-7b",
+            "messages": [
+                {"role": "system", "content": "Be brief"},
+                {"role": "user", "content": [{"type": "text", "text": "Hello "}, {"type": "text", "text": "there"}]}
+            ],
+            "stop": "\n\n",
+            "top_p": 0.9,
+            "max_completion_tokens": 64,
+            "response_format": {"type": "json_object"},
+            "stream": true
+        }"#;
+        let request: ChatCompletionRequest = serde_json::from_str(body).unwrap();
+        let messages = request.chat_messages().unwrap();
+        assert_eq!(messages[1].content, "Hello there");
+        assert_eq!(request.options.max_tokens(), 64);
+        assert_eq!(request.options.stop(), vec!["\n\n"]);
+        assert_eq!(request.options.sampling().top_p, 0.9);
+        assert!(request.options.stream);
+        assert!(matches!(request.constraint().unwrap(), Some(OutputConstraint::Json)));
+
+        let image = r#"{"messages": [{"role": "user", "content": [{"type": "image_url"}]}]}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(image).unwrap();
+        assert!(request.chat_messages().is_err());
+    }
+
+    #[test]
+    fn test_parse_completion_request_from_http_client() {
+        // Body sent by `chat_with_http_streaming`
+        let body = json!({
+            "model": "default",
+            "prompt": "fn main() {",
+            "max_tokens": 2000,
+            "temperature": 0.7,
+            "stream": true,
+            "stop": ["}"],
+        });
+        let request: CompletionRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(request.prompt().unwrap(), "fn main() {");
+        assert_eq!(request.options.model.as_deref(), Some("default"));
+        assert_eq!(request.options.stop(), vec!["}"]);
+
+        assert_eq!(request.logprobs, None);
+
+        let batched: CompletionRequest = serde_json::from_value(json!({ "prompt": ["a", "b"] })).unwrap();
+        assert!(batched.prompt().is_err());
+        assert_eq!(batched.options.max_tokens(), DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_logprobs_shapes() {
+        let request: ChatCompletionRequest =
+            serde_json::from_value(json!({ "messages": [], "logprobs": true, "top_logprobs": 2 })).unwrap();
+        assert_eq!(request.logprobs(), Some(2));
+        let request: ChatCompletionRequest = serde_json::from_value(json!({ "messages": [], "top_logprobs": 2 })).unwrap();
+        assert_eq!(request.logprobs(), None);
+
+        let alt = |token: &str, logprob: f32| TopLogprob { token: token.to_string(), id: 0, logprob, probability: logprob.exp() };
+        let tokens = vec![
+            TokenLogprob { token: "fn".to_string(), id: 1, logprob: -0.1, probability: 0.9, top_logprobs: vec![alt("fn", -0.1), alt("pub", -2.5)] },
+            TokenLogprob { token: " main".to_string(), id: 2, logprob: -0.5, probability: 0.6, top_logprobs: vec![alt(" main", -0.5)] },
+        ];
+        let chat = chat_logprobs(Some(&tokens));
+        assert_eq!(chat["content"][0]["top_logprobs"][1]["token"], "pub");
+        assert_eq!(chat["content"][1]["bytes"], json!(" main".as_bytes()));
+
+        let text = text_logprobs(Some(&tokens));
+        assert_eq!(text["text_offset"], json!([0, 2]));
+        assert_eq!(text["top_logprobs"][0]["pub"], json!(-2.5f32));
+        assert!(text_logprobs(None).is_null());
+    }
+
+    #[test]
+    fn test_resolve_model() {
+        let models = [("/models/qwen2.5-coder-7b.gguf", 3), ("/models/bge-small.gguf", 7)];
+        assert_eq!(resolve_model(&models, None).unwrap(), "/models/bge-small.gguf");
+        assert_eq!(resolve_model(&models, Some("default")).unwrap(), "/models/bge-small.gguf");
+        assert_eq!(resolve_model(&models, Some("qwen2.5-coder-7b")).unwrap(), "/models/qwen2.5-coder-7b.gguf");
+        assert_eq!(resolve_model(&models, Some("qwen2.5-coder-7b.gguf")).unwrap(), "/models/qwen2.5-coder-7b.gguf");
+        assert!(resolve_model(&models, Some("llama")).is_err());
+        assert!(resolve_model(&[], None).is_err());
+    }
+
+    #[test]
+    fn test_stream_chunks_readable_by_http_client() {
+        let events = [
+            sse_event(&text_chunk("cmpl-1", "qwen", 0, "Hel", None)),
+            sse_event(&text_chunk("cmpl-1", "qwen", 0, "lo", None)),
+            sse_event(&text_chunk("cmpl-1", "qwen", 0, "", Some("stop"))),
+            SSE_DONE.to_string(),
+        ]
+        .concat();
+
+        // The parser `chat_with_http_streaming` uses
+        let mut text = String::new();
+        for line in events.lines() {
+            match parse_completion_sse_line(line) {
+                Some(SseData::Text(piece)) => text.push_str(&piece),
+                Some(SseData::Done) => break,
+                None => {}
+            }
+        }
+        assert_eq!(text, "Hello");
+        assert_eq!(parse_completion_sse_line(": keep-alive"), None);
+    }
+
+    #[test]
+    fn test_finish_reason_and_auth() {
+        assert_eq!(finish_reason(16, 16, false), "length");
+        assert_eq!(finish_reason(16, 16, true), "stop");
+        assert_eq!(finish_reason(3, 16, false), "stop");
+
+        assert!(check_bearer(None, None));
+        assert!(check_bearer(Some("Bearer secret"), Some("secret")));
+        assert!(!check_bearer(Some("Bearer wrong"), Some("secret")));
+        assert!(!check_bearer(None, Some("secret")));
+    }
+
+    #[test]
+    fn test_local_request_checks() {
+        assert!(is_local_host(Some("127.0.0.1:8765")));
+        assert!(is_local_host(Some("LOCALHOST")));
+        assert!(is_local_host(Some("[::1]:8765")));
+        assert!(!is_local_host(Some("attacker.example:8765")));
+        assert!(!is_local_host(Some("127.0.0.1.attacker.example")));
+        assert!(!is_local_host(None));
+
+        assert!(is_local_origin(None));
+        assert!(is_local_origin(Some("http://localhost:1420")));
+        assert!(!is_local_origin(Some("https://attacker.example")));
+        assert!(!is_local_origin(Some("null")));
+
+        assert!(is_json_content_type(Some("application/json")));
+        assert!(is_json_content_type(Some("Application/JSON; charset=utf-8")));
+        assert!(!is_json_content_type(Some("text/plain")));
+        assert!(!is_json_content_type(None));
+    }
+}
@@ -0,0 +1,210 @@
+// src-tauri/src/gguf_pool.rs
+// Memory budget and LRU eviction planning for the GGUF model pool
+
+use serde::{Deserialize, Serialize};
+
+use crate::gguf_header::GgufTensorInfo;
+
+/// Bytes a loaded model occupies in system RAM and in GPU memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ModelFootprint {
+    pub ram_bytes: u64,
+    pub vram_bytes: u64,
+}
+
+impl ModelFootprint {
+    pub fn total(&self) -> u64 {
+        self.ram_bytes + self.vram_bytes
+    }
+
+    fn add(self, other: ModelFootprint) -> ModelFootprint {
+        ModelFootprint {
+            ram_bytes: self.ram_bytes + other.ram_bytes,
+            vram_bytes: self.vram_bytes + other.vram_bytes,
+        }
+    }
+}
+
+/// Upper limits for the whole pool; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolBudget {
+    pub ram_bytes: Option<u64>,
+    pub vram_bytes: Option<u64>,
+}
+
+impl PoolBudget {
+    pub fn fits(&self, usage: ModelFootprint) -> bool {
+        self.ram_bytes.map(|limit| usage.ram_bytes <= limit).unwrap_or(true)
+            && self.vram_bytes.map(|limit| usage.vram_bytes <= limit).unwrap_or(true)
+    }
+}
+
+/// What the planner needs to know about a pooled model
+#[derive(Debug, Clone)]
+pub struct PoolEntry<'a> {
+    pub path: &'a str,
+    pub footprint: ModelFootprint,
+    /// Pool-wide use counter value of the model's last request
+    pub last_used: u64,
+    pub pinned: bool,
+}
+
+/// Split weight bytes between RAM and VRAM the way llama.cpp offloads layers:
+/// the last `n_gpu_layers` blocks go to the GPU, the output head follows once every
+/// block is offloaded, and the token embeddings always stay in RAM.
+pub fn weights_footprint(tensors: &[GgufTensorInfo], block_count: u64, n_gpu_layers: u32) -> ModelFootprint {
+    let n_gpu_layers = n_gpu_layers as u64;
+    let first_gpu_block = block_count.saturating_sub(n_gpu_layers);
+    let output_on_gpu = n_gpu_layers > block_count;
+
+    let mut footprint = ModelFootprint::default();
+    for tensor in tensors {
+        let bytes = tensor.size_bytes().unwrap_or(0);
+        let on_gpu = match block_index(&tensor.name) {
+            Some(block) => n_gpu_layers > 0 && block >= first_gpu_block,
+            None => output_on_gpu && tensor.name.starts_with("output"),
+        };
+        if on_gpu {
+            footprint.vram_bytes += bytes;
+        } else {
+            footprint.ram_bytes += bytes;
+        }
+    }
+    footprint
+}
+
+/// `blk.12.attn_q.weight` -> 12
+fn block_index(tensor_name: &str) -> Option<u64> {
+    tensor_name.strip_prefix("blk.")?.split('.').next()?.parse().ok()
+}
+
+/// Pick the least recently used unpinned models to unload so `incoming` fits the budget.
+/// Returns the paths to evict in eviction order, or an error if it cannot fit even then.
+pub fn plan_eviction(
+    entries: &[PoolEntry],
+    budget: &PoolBudget,
+    incoming: ModelFootprint,
+) -> Result<Vec<String>, String> {
+    if !budget.fits(incoming) {
+        return Err(format!(
+            "Model bellek bütçesine sığmıyor: RAM {:.2} GB, VRAM {:.2} GB gerekli",
+            gb(incoming.ram_bytes),
+            gb(incoming.vram_bytes)
+        ));
+    }
+
+    let mut usage = entries
+        .iter()
+        .fold(ModelFootprint::default(), |acc, e| acc.add(e.footprint));
+
+    let mut candidates: Vec<&PoolEntry> = entries.iter().filter(|e| !e.pinned).collect();
+    candidates.sort_by_key(|e| e.last_used);
+
+    let mut evict = Vec::new();
+    for entry in candidates {
+        if budget.fits(usage.add(incoming)) {
+            break;
+        }
+        usage.ram_bytes -= entry.footprint.ram_bytes;
+        usage.vram_bytes -= entry.footprint.vram_bytes;
+        evict.push(entry.path.to_string());
+    }
+
+    if !budget.fits(usage.add(incoming)) {
+        return Err(format!(
+            "Bellek bütçesi yetersiz: sabitlenmiş (pinned) modeller {:.2} GB RAM / {:.2} GB VRAM kullanıyor",
+            gb(usage.ram_bytes),
+            gb(usage.vram_bytes)
+        ));
+    }
+
+    Ok(evict)
+}
+
+fn gb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    fn tensor(name: &str, elements: u64) -> GgufTensorInfo {
+        // F32, 4 bytes per element
+        GgufTensorInfo { name: name.to_string(), dims: vec![elements], ggml_type: 0, offset: 0 }
+    }
+
+    fn entry(path: &str, ram: u64, vram: u64, last_used: u64, pinned: bool) -> PoolEntry<'_> {
+        PoolEntry {
+            path,
+            footprint: ModelFootprint { ram_bytes: ram, vram_bytes: vram },
+            last_used,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn test_weights_footprint_offload_split() {
+        let tensors = vec![
+            tensor("token_embd.weight", 100),
+            tensor("blk.0.attn_q.weight", 10),
+            tensor("blk.1.attn_q.weight", 10),
+            tensor("blk.2.attn_q.weight", 10),
+            tensor("output_norm.weight", 1),
+            tensor("output.weight", 100),
+        ];
+
+        let cpu = weights_footprint(&tensors, 3, 0);
+        assert_eq!(cpu, ModelFootprint { ram_bytes: 231 * 4, vram_bytes: 0 });
+
+        let partial = weights_footprint(&tensors, 3, 2);
+        assert_eq!(partial, ModelFootprint { ram_bytes: 211 * 4, vram_bytes: 20 * 4 });
+
+        let full = weights_footprint(&tensors, 3, 99);
+        assert_eq!(full, ModelFootprint { ram_bytes: 100 * 4, vram_bytes: 131 * 4 });
+    }
+
+    #[test]
+    fn test_no_eviction_when_it_fits() {
+        let budget = PoolBudget { ram_bytes: Some(16 * GB), vram_bytes: None };
+        let entries = vec![entry("a", 4 * GB, 0, 1, false)];
+        let plan = plan_eviction(&entries, &budget, ModelFootprint { ram_bytes: 8 * GB, vram_bytes: 0 });
+        assert_eq!(plan.unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_first() {
+        let budget = PoolBudget { ram_bytes: Some(16 * GB), vram_bytes: Some(8 * GB) };
+        let entries = vec![
+            entry("recent", 4 * GB, 2 * GB, 9, false),
+            entry("oldest", 4 * GB, 2 * GB, 1, false),
+            entry("middle", 4 * GB, 2 * GB, 5, false),
+        ];
+        let incoming = ModelFootprint { ram_bytes: 4 * GB, vram_bytes: 6 * GB };
+        let plan = plan_eviction(&entries, &budget, incoming).unwrap();
+        assert_eq!(plan, vec!["oldest".to_string(), "middle".to_string()]);
+    }
+
+    #[test]
+    fn test_pinned_models_are_kept() {
+        let budget = PoolBudget { ram_bytes: Some(10 * GB), vram_bytes: None };
+        let entries = vec![
+            entry("pinned-old", 6 * GB, 0, 1, true),
+            entry("free", 2 * GB, 0, 5, false),
+        ];
+        let plan = plan_eviction(&entries, &budget, ModelFootprint { ram_bytes: 3 * GB, vram_bytes: 0 });
+        assert_eq!(plan.unwrap(), vec!["free".to_string()]);
+
+        let too_big = plan_eviction(&entries, &budget, ModelFootprint { ram_bytes: 5 * GB, vram_bytes: 0 });
+        assert!(too_big.unwrap_err().contains("pinned"));
+    }
+
+    #[test]
+    fn test_model_larger_than_budget() {
+        let budget = PoolBudget { ram_bytes: None, vram_bytes: Some(4 * GB) };
+        let plan = plan_eviction(&[], &budget, ModelFootprint { ram_bytes: 0, vram_bytes: 5 * GB });
+        assert!(plan.is_err());
+    }
+}
@@ -0,0 +1,483 @@
+// src-tauri/src/gguf_sampling.rs
+// Configurable sampler chain for local GGUF inference (penalties, top-k/p, min-p, typical, mirostat)
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Sampling settings accepted by the GGUF chat commands.
+/// Defaults reproduce the previous fixed behaviour: repeat penalty 1.15 over the
+/// last 64 tokens and plain softmax sampling over the whole vocabulary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    /// Keep only the k most likely tokens (0 = disabled)
+    pub top_k: i32,
+    /// Nucleus sampling threshold (1.0 = disabled)
+    pub top_p: f32,
+    /// Drop tokens below `min_p * p_max` (0.0 = disabled)
+    pub min_p: f32,
+    /// Locally typical sampling threshold (1.0 = disabled)
+    pub typical_p: f32,
+    /// Repetition penalty (1.0 = disabled)
+    pub repeat_penalty: f32,
+    /// How many recent tokens the penalties look at (0 = disabled, -1 = whole history)
+    pub repeat_last_n: i32,
+    /// Subtracted once per previous occurrence of a token
+    pub frequency_penalty: f32,
+    /// Subtracted once if a token occurred at all
+    pub presence_penalty: f32,
+    /// 0 = off, 1 = Mirostat, 2 = Mirostat 2.0 (replaces top-k/p, min-p and typical)
+    pub mirostat: u8,
+    /// Mirostat target surprise
+    pub mirostat_tau: f32,
+    /// Mirostat learning rate
+    pub mirostat_eta: f32,
+    /// Fixed RNG seed for reproducible output (None = random)
+    pub seed: Option<u64>,
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self {
+            top_k: 0,
+            top_p: 1.0,
+            min_p: 0.0,
+            typical_p: 1.0,
+            repeat_penalty: 1.15,
+            repeat_last_n: 64,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            mirostat: 0,
+            mirostat_tau: 5.0,
+            mirostat_eta: 0.1,
+            seed: None,
+        }
+    }
+}
+
+/// A candidate token during sampling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub id: i32,
+    pub logit: f32,
+    pub p: f32,
+}
+
+impl Candidate {
+    pub fn new(id: i32, logit: f32) -> Self {
+        Self { id, logit, p: 0.0 }
+    }
+}
+
+/// Number of top candidates Mirostat v1 uses to estimate the Zipf exponent
+const MIROSTAT_M: usize = 100;
+
+/// Stateful sampler: owns the RNG and the Mirostat `mu` across the tokens of one generation
+pub struct Sampler {
+    params: SamplingParams,
+    temperature: f32,
+    rng: StdRng,
+    mirostat_mu: f32,
+}
+
+impl Sampler {
+    pub fn new(params: SamplingParams, temperature: f32) -> Self {
+        let rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mirostat_mu = 2.0 * params.mirostat_tau;
+        Self { params, temperature, rng, mirostat_mu }
+    }
+
+    pub fn params(&self) -> &SamplingParams {
+        &self.params
+    }
+
+    /// Pick the next token. `history` is every token seen so far (prompt + generated).
+    /// Returns None only when `candidates` is empty.
+    pub fn sample(&mut self, mut candidates: Vec<Candidate>, history: &[i32]) -> Option<i32> {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        self.apply_penalties(&mut candidates, history);
+
+        // Greedy decoding for temperature <= 0
+        if self.temperature <= 0.0 {
+            return argmax(&candidates);
+        }
+
+        match self.params.mirostat {
+            1 => {
+                apply_temperature(&mut candidates, self.temperature);
+                Some(self.sample_mirostat_v1(candidates))
+            }
+            2 => {
+                apply_temperature(&mut candidates, self.temperature);
+                Some(self.sample_mirostat_v2(candidates))
+            }
+            _ => {
+                top_k(&mut candidates, self.params.top_k);
+                typical(&mut candidates, self.params.typical_p);
+                top_p(&mut candidates, self.params.top_p);
+                min_p(&mut candidates, self.params.min_p);
+                apply_temperature(&mut candidates, self.temperature);
+                Some(self.sample_distribution(&mut candidates))
+            }
+        }
+    }
+
+    fn apply_penalties(&self, candidates: &mut [Candidate], history: &[i32]) {
+        let p = &self.params;
+        let penalties_off = p.repeat_penalty == 1.0 && p.frequency_penalty == 0.0 && p.presence_penalty == 0.0;
+        if penalties_off || p.repeat_last_n == 0 || history.is_empty() {
+            return;
+        }
+
+        let window = if p.repeat_last_n < 0 {
+            history
+        } else {
+            &history[history.len().saturating_sub(p.repeat_last_n as usize)..]
+        };
+
+        let mut counts: HashMap<i32, u32> = HashMap::new();
+        for id in window {
+            *counts.entry(*id).or_insert(0) += 1;
+        }
+
+        for c in candidates.iter_mut() {
+            if let Some(&count) = counts.get(&c.id) {
+                if c.logit <= 0.0 {
+                    c.logit *= p.repeat_penalty;
+                } else {
+                    c.logit /= p.repeat_penalty;
+                }
+                c.logit -= count as f32 * p.frequency_penalty + p.presence_penalty;
+            }
+        }
+    }
+
+    fn sample_distribution(&mut self, candidates: &mut [Candidate]) -> i32 {
+        softmax(candidates);
+        let random_val: f32 = self.rng.gen();
+        let mut cumulative = 0.0;
+        for c in candidates.iter() {
+            cumulative += c.p;
+            if random_val < cumulative {
+                return c.id;
+            }
+        }
+        // Rounding left the cumulative sum slightly below 1.0
+        candidates[candidates.len() - 1].id
+    }
+
+    fn sample_mirostat_v1(&mut self, mut candidates: Vec<Candidate>) -> i32 {
+        softmax(&mut candidates);
+        let n_vocab = candidates.len() as f32;
+
+        // Estimate the Zipf exponent s_hat from the top-m probabilities
+        let m = MIROSTAT_M.min(candidates.len().saturating_sub(1));
+        let mut sum_ti_bi = 0.0f32;
+        let mut sum_ti_sq = 0.0f32;
+        for i in 0..m {
+            let t_i = ((i + 2) as f32 / (i + 1) as f32).ln();
+            let b_i = (candidates[i].p / candidates[i + 1].p).ln();
+            if b_i.is_finite() {
+                sum_ti_bi += t_i * b_i;
+                sum_ti_sq += t_i * t_i;
+            }
+        }
+        let s_hat = if sum_ti_sq > 0.0 { sum_ti_bi / sum_ti_sq } else { 1.0 };
+
+        // Compute k from the estimated s_hat and the target surprise mu
+        let epsilon_hat = s_hat - 1.0;
+        let k = if epsilon_hat.abs() < f32::EPSILON {
+            candidates.len() as f32
+        } else {
+            ((epsilon_hat * 2f32.powf(self.mirostat_mu)) / (1.0 - n_vocab.powf(-epsilon_hat))).powf(1.0 / s_hat)
+        };
+        let k = if k.is_finite() { (k as usize).clamp(1, candidates.len()) } else { candidates.len() };
+
+        candidates.truncate(k);
+        let id = self.sample_distribution(&mut candidates);
+        self.update_mirostat_mu(&candidates, id);
+        id
+    }
+
+    fn sample_mirostat_v2(&mut self, mut candidates: Vec<Candidate>) -> i32 {
+        softmax(&mut candidates);
+
+        // Drop tokens whose surprise exceeds mu, always keep the best one
+        let mu = self.mirostat_mu;
+        let keep = candidates.iter().take_while(|c| -c.p.log2() <= mu).count().max(1);
+        candidates.truncate(keep);
+
+        let id = self.sample_distribution(&mut candidates);
+        self.update_mirostat_mu(&candidates, id);
+        id
+    }
+
+    fn update_mirostat_mu(&mut self, candidates: &[Candidate], id: i32) {
+        if let Some(selected) = candidates.iter().find(|c| c.id == id) {
+            let observed_surprise = -selected.p.log2();
+            let error = observed_surprise - self.params.mirostat_tau;
+            self.mirostat_mu -= self.params.mirostat_eta * error;
+        }
+    }
+}
+
+fn argmax(candidates: &[Candidate]) -> Option<i32> {
+    candidates
+        .iter()
+        .max_by(|a, b| a.logit.partial_cmp(&b.logit).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|c| c.id)
+}
+
+fn sort_by_logit(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| b.logit.partial_cmp(&a.logit).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+/// Sort descending by logit and fill in normalized probabilities
+pub fn softmax(candidates: &mut [Candidate]) {
+    if candidates.is_empty() {
+        return;
+    }
+    sort_by_logit(candidates);
+    let max_logit = candidates[0].logit;
+    let mut sum = 0.0;
+    for c in candidates.iter_mut() {
+        c.p = (c.logit - max_logit).exp();
+        sum += c.p;
+    }
+    for c in candidates.iter_mut() {
+        c.p /= sum;
+    }
+}
+
+/// Log-probability of `chosen` and of the `top_n` most likely tokens, most likely first,
+/// under the distribution given by the logits of `candidates`. None when `chosen` is not a candidate.
+pub fn log_probs(candidates: &[Candidate], chosen: i32, top_n: usize) -> Option<(f32, Vec<(i32, f32)>)> {
+    let max_logit = candidates.iter().map(|c| c.logit).fold(f32::NEG_INFINITY, f32::max);
+    if !max_logit.is_finite() {
+        return None;
+    }
+    let sum: f64 = candidates.iter().map(|c| ((c.logit - max_logit) as f64).exp()).sum();
+    let log_sum = max_logit + sum.ln() as f32;
+    let chosen = candidates.iter().find(|c| c.id == chosen)?.logit - log_sum;
+
+    // Insertion into a short sorted list, a full sort of the vocabulary would dominate
+    let mut top: Vec<&Candidate> = Vec::with_capacity(top_n + 1);
+    for c in candidates {
+        if top_n > 0 && (top.len() < top_n || c.logit > top[top.len() - 1].logit) {
+            let at = top.partition_point(|t| t.logit >= c.logit);
+            top.insert(at, c);
+            top.truncate(top_n);
+        }
+    }
+    Some((chosen, top.iter().map(|c| (c.id, c.logit - log_sum)).collect()))
+}
+
+fn apply_temperature(candidates: &mut [Candidate], temperature: f32) {
+    for c in candidates.iter_mut() {
+        c.logit /= temperature;
+    }
+}
+
+fn top_k(candidates: &mut Vec<Candidate>, k: i32) {
+    if k <= 0 || k as usize >= candidates.len() {
+        return;
+    }
+    let k = k as usize;
+    // Partition first so we only sort the k survivors, not the whole vocabulary
+    candidates.select_nth_unstable_by(k - 1, |a, b| {
+        b.logit.partial_cmp(&a.logit).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    candidates.truncate(k);
+    sort_by_logit(candidates);
+}
+
+fn top_p(candidates: &mut Vec<Candidate>, p: f32) {
+    if p >= 1.0 {
+        return;
+    }
+    softmax(candidates);
+    let mut cumulative = 0.0;
+    let mut keep = candidates.len();
+    for (i, c) in candidates.iter().enumerate() {
+        cumulative += c.p;
+        if cumulative >= p {
+            keep = i + 1;
+            break;
+        }
+    }
+    candidates.truncate(keep.max(1));
+}
+
+fn min_p(candidates: &mut Vec<Candidate>, p: f32) {
+    if p <= 0.0 {
+        return;
+    }
+    softmax(candidates);
+    let threshold = candidates[0].p * p;
+    let keep = candidates.iter().take_while(|c| c.p >= threshold).count();
+    candidates.truncate(keep.max(1));
+}
+
+fn typical(candidates: &mut Vec<Candidate>, p: f32) {
+    if p >= 1.0 {
+        return;
+    }
+    softmax(candidates);
+
+    let entropy: f32 = candidates
+        .iter()
+        .filter(|c| c.p > 0.0)
+        .map(|c| -c.p * c.p.ln())
+        .sum();
+
+    // Prefer tokens whose surprise is closest to the expected surprise (entropy)
+    let mut scored: Vec<(f32, Candidate)> = candidates
+        .iter()
+        .map(|c| ((-c.p.ln() - entropy).abs(), *c))
+        .collect();
+    scored.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut cumulative = 0.0;
+    let mut keep = scored.len();
+    for (i, (_, c)) in scored.iter().enumerate() {
+        cumulative += c.p;
+        if cumulative >= p {
+            keep = i + 1;
+            break;
+        }
+    }
+
+    *candidates = scored.into_iter().take(keep.max(1)).map(|(_, c)| c).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logits(values: &[f32]) -> Vec<Candidate> {
+        values.iter().enumerate().map(|(i, l)| Candidate::new(i as i32, *l)).collect()
+    }
+
+    fn no_penalty() -> SamplingParams {
+        SamplingParams { repeat_penalty: 1.0, ..Default::default() }
+    }
+
+    #[test]
+    fn test_greedy_when_temperature_zero() {
+        let mut sampler = Sampler::new(no_penalty(), 0.0);
+        assert_eq!(sampler.sample(logits(&[0.1, 3.0, 1.0]), &[]), Some(1));
+        assert_eq!(sampler.sample(Vec::new(), &[]), None);
+    }
+
+    #[test]
+    fn test_repeat_penalty_changes_greedy_choice() {
+        let params = SamplingParams { repeat_penalty: 2.0, ..Default::default() };
+        let mut sampler = Sampler::new(params, 0.0);
+        // Token 1 wins without penalty, but it was just generated
+        assert_eq!(sampler.sample(logits(&[2.0, 3.0]), &[1]), Some(0));
+    }
+
+    #[test]
+    fn test_penalty_window() {
+        let params = SamplingParams { repeat_penalty: 2.0, repeat_last_n: 1, ..Default::default() };
+        let mut sampler = Sampler::new(params, 0.0);
+        // Token 1 is outside the 1-token window, so it is not penalized
+        assert_eq!(sampler.sample(logits(&[2.0, 3.0]), &[1, 0]), Some(1));
+    }
+
+    #[test]
+    fn test_frequency_and_presence_penalties() {
+        let params = SamplingParams {
+            repeat_penalty: 1.0,
+            frequency_penalty: 0.5,
+            presence_penalty: 0.5,
+            ..Default::default()
+        };
+        let mut sampler = Sampler::new(params, 0.0);
+        // 3.0 - 2 * 0.5 - 0.5 = 1.5 < 2.0
+        assert_eq!(sampler.sample(logits(&[2.0, 3.0]), &[1, 1]), Some(0));
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let params = SamplingParams { seed: Some(42), ..no_penalty() };
+        let run = |params: SamplingParams| {
+            let mut sampler = Sampler::new(params, 1.0);
+            (0..32)
+                .map(|_| sampler.sample(logits(&[1.0, 1.1, 0.9, 1.05, 0.95]), &[]).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(params.clone()), run(params));
+    }
+
+    #[test]
+    fn test_top_k_restricts_choices() {
+        let params = SamplingParams { top_k: 2, seed: Some(7), ..no_penalty() };
+        let mut sampler = Sampler::new(params, 1.0);
+        for _ in 0..100 {
+            let id = sampler.sample(logits(&[5.0, 4.9, 0.0, 0.0, 0.0]), &[]).unwrap();
+            assert!(id == 0 || id == 1);
+        }
+    }
+
+    #[test]
+    fn test_top_p_and_min_p_filters() {
+        let mut c = logits(&[10.0, 9.0, 0.0, -5.0]);
+        top_p(&mut c, 0.9);
+        assert_eq!(c.iter().map(|c| c.id).collect::<Vec<_>>(), vec![0, 1]);
+
+        let mut c = logits(&[10.0, 9.9, 0.0, -5.0]);
+        min_p(&mut c, 0.5);
+        assert_eq!(c.iter().map(|c| c.id).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_log_probs() {
+        let c = logits(&[1.0f32.ln(), 2.0f32.ln(), 1.0f32.ln()]);
+        let (chosen, top) = log_probs(&c, 2, 2).unwrap();
+        assert!((chosen - 0.25f32.ln()).abs() < 1e-5);
+        assert_eq!(top.iter().map(|t| t.0).collect::<Vec<_>>(), vec![1, 0]);
+        assert!((top[0].1 - 0.5f32.ln()).abs() < 1e-5);
+
+        assert!(log_probs(&c, 7, 2).is_none());
+        assert!(log_probs(&c, 1, 0).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_typical_keeps_at_least_one() {
+        let mut c = logits(&[1.0, 1.0, 1.0, 1.0]);
+        typical(&mut c, 0.01);
+        assert_eq!(c.len(), 1);
+    }
+
+    #[test]
+    fn test_mirostat_v2_adapts_mu() {
+        let params = SamplingParams { mirostat: 2, seed: Some(1), ..no_penalty() };
+        let mut sampler = Sampler::new(params, 1.0);
+        let start_mu = sampler.mirostat_mu;
+        for _ in 0..10 {
+            let id = sampler.sample(logits(&[3.0, 2.0, 1.0, 0.0, -1.0]), &[]).unwrap();
+            assert!((0..5).contains(&id));
+        }
+        assert!(sampler.mirostat_mu != start_mu);
+    }
+
+    #[test]
+    fn test_mirostat_v1_samples_valid_token() {
+        let params = SamplingParams { mirostat: 1, seed: Some(3), ..no_penalty() };
+        let mut sampler = Sampler::new(params, 0.8);
+        let values: Vec<f32> = (0..200).map(|i| -(i as f32) * 0.05).collect();
+        for _ in 0..10 {
+            let id = sampler.sample(logits(&values), &[]).unwrap();
+            assert!((0..200).contains(&id));
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// src-tauri/src/gguf_scheduler.rs
+// Priority classes, superseding and wait-time statistics for the per-model GGUF request queues
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cancellation::CancelToken;
+
+/// Priority class of a local inference request; higher classes are always started first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    /// Summarization, test generation and other jobs nobody is actively waiting on
+    Background,
+    #[default]
+    Chat,
+    /// Inline completions - the user is typing and waiting for them
+    Interactive,
+}
+
+/// Scheduling options attached to a GGUF request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleOptions {
+    #[serde(default)]
+    pub priority: RequestPriority,
+    /// A newer request with the same key replaces this one, e.g. `"completion:<editor id>"`
+    #[serde(default)]
+    pub supersede_key: Option<String>,
+}
+
+struct Entry<J> {
+    job: J,
+    priority: RequestPriority,
+    supersede_key: Option<String>,
+    cancel: Option<Arc<CancelToken>>,
+    seq: u64,
+    enqueued_at: Instant,
+}
+
+struct Running {
+    priority: RequestPriority,
+    supersede_key: Option<String>,
+    cancel: Option<Arc<CancelToken>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    started: u64,
+    superseded: u64,
+    cancelled: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+/// Per-class queue figures
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ClassStats {
+    pub queued: usize,
+    pub started: u64,
+    /// Dropped from the queue or stopped because a newer request had the same key
+    pub superseded: u64,
+    /// Cancelled while still waiting, never started
+    pub cancelled: u64,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: u64,
+    /// How long the oldest request still in the queue has been waiting
+    pub oldest_wait_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueueStats {
+    /// Class of the request currently running, if any
+    pub running: Option<RequestPriority>,
+    pub queued: usize,
+    pub interactive: ClassStats,
+    pub chat: ClassStats,
+    pub background: ClassStats,
+}
+
+/// Pending requests of one model. Higher priority classes jump ahead, requests
+/// within a class run in arrival order. A request that is already running is
+/// never interrupted by priority alone, only by superseding or cancellation.
+pub struct RequestQueue<J> {
+    entries: Vec<Entry<J>>,
+    running: Option<Running>,
+    next_seq: u64,
+    counters: [Counters; 3],
+}
+
+impl<J> Default for RequestQueue<J> {
+    fn default() -> Self {
+        Self { entries: Vec::new(), running: None, next_seq: 0, counters: Default::default() }
+    }
+}
+
+impl<J> RequestQueue<J> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Queue `job`. Queued requests with the same supersede key are dropped, a running
+    /// one is stopped through its cancel token. Returns how many were superseded.
+    pub fn push(
+        &mut self,
+        job: J,
+        options: &ScheduleOptions,
+        cancel: Option<Arc<CancelToken>>,
+        now: Instant,
+    ) -> usize {
+        let mut superseded = 0;
+        if let Some(key) = &options.supersede_key {
+            let (stale, keep): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+                .into_iter()
+                .partition(|e| e.supersede_key.as_ref() == Some(key));
+            self.entries = keep;
+            for entry in stale {
+                if let Some(cancel) = &entry.cancel {
+                    cancel.cancel();
+                }
+                self.counters[entry.priority as usize].superseded += 1;
+                superseded += 1;
+            }
+
+            if let Some(running) = self.running.as_mut().filter(|r| r.supersede_key.as_ref() == Some(key)) {
+                if let Some(cancel) = running.cancel.take() {
+                    cancel.cancel();
+                    self.counters[running.priority as usize].superseded += 1;
+                    superseded += 1;
+                }
+            }
+        }
+
+        self.entries.push(Entry {
+            job,
+            priority: options.priority,
+            supersede_key: options.supersede_key.clone(),
+            cancel,
+            seq: self.next_seq,
+            enqueued_at: now,
+        });
+        self.next_seq += 1;
+        superseded
+    }
+
+    /// Take the next job to run and mark it as running. Requests cancelled while
+    /// they were waiting are discarded on the way.
+    pub fn pop(&mut self, now: Instant) -> Option<J> {
+        loop {
+            let idx = self
+                .entries
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(b.seq.cmp(&a.seq)))
+                .map(|(idx, _)| idx)?;
+            let entry = self.entries.remove(idx);
+            let counters = &mut self.counters[entry.priority as usize];
+
+            if entry.cancel.as_ref().map(|c| c.is_cancelled()).unwrap_or(false) {
+                counters.cancelled += 1;
+                continue;
+            }
+
+            let wait = now.saturating_duration_since(entry.enqueued_at);
+            counters.started += 1;
+            counters.total_wait += wait;
+            counters.max_wait = counters.max_wait.max(wait);
+
+            self.running = Some(Running {
+                priority: entry.priority,
+                supersede_key: entry.supersede_key,
+                cancel: entry.cancel,
+            });
+            return Some(entry.job);
+        }
+    }
+
+    /// The running job returned
+    pub fn finish(&mut self) {
+        self.running = None;
+    }
+
+    pub fn stats(&self, now: Instant) -> QueueStats {
+        let class = |priority: RequestPriority| {
+            let counters = &self.counters[priority as usize];
+            let queued: Vec<&Entry<J>> = self.entries.iter().filter(|e| e.priority == priority).collect();
+            let oldest = queued
+                .iter()
+                .map(|e| now.saturating_duration_since(e.enqueued_at))
+                .max()
+                .unwrap_or_default();
+            ClassStats {
+                queued: queued.len(),
+                started: counters.started,
+                superseded: counters.superseded,
+                cancelled: counters.cancelled,
+                avg_wait_ms: if counters.started > 0 {
+                    counters.total_wait.as_secs_f64() * 1000.0 / counters.started as f64
+                } else {
+                    0.0
+                },
+                max_wait_ms: counters.max_wait.as_millis() as u64,
+                oldest_wait_ms: oldest.as_millis() as u64,
+            }
+        };
+
+        QueueStats {
+            running: self.running.as_ref().map(|r| r.priority),
+            queued: self.entries.len(),
+            interactive: class(RequestPriority::Interactive),
+            chat: class(RequestPriority::Chat),
+            background: class(RequestPriority::Background),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(priority: RequestPriority, key: Option<&str>) -> ScheduleOptions {
+        ScheduleOptions { priority, supersede_key: key.map(|k| k.to_string()) }
+    }
+
+    #[test]
+    fn test_priority_then_arrival_order() {
+        let now = Instant::now();
+        let mut queue = RequestQueue::default();
+        queue.push("summary", &opts(RequestPriority::Background, None), None, now);
+        queue.push("chat-1", &opts(RequestPriority::Chat, None), None, now);
+        queue.push("completion", &opts(RequestPriority::Interactive, None), None, now);
+        queue.push("chat-2", &opts(RequestPriority::Chat, None), None, now);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(now)).collect();
+        assert_eq!(order, vec!["completion", "chat-1", "chat-2", "summary"]);
+    }
+
+    #[test]
+    fn test_newer_request_supersedes_queued_and_running() {
+        let now = Instant::now();
+        let mut queue = RequestQueue::default();
+        let running = Arc::new(CancelToken::default());
+        let queued = Arc::new(CancelToken::default());
+        let key = Some("completion:editor-1");
+
+        queue.push("old-running", &opts(RequestPriority::Interactive, key), Some(running.clone()), now);
+        assert_eq!(queue.pop(now), Some("old-running"));
+        queue.push("other-editor", &opts(RequestPriority::Interactive, Some("completion:editor-2")), None, now);
+
+        // Stops the running request
+        let superseded = queue.push("old-queued", &opts(RequestPriority::Interactive, key), Some(queued.clone()), now);
+        assert_eq!(superseded, 1);
+        assert!(running.is_cancelled());
+
+        // Drops the queued one
+        let superseded = queue.push("newest", &opts(RequestPriority::Interactive, key), None, now);
+        assert_eq!(superseded, 1);
+        assert!(queued.is_cancelled());
+        assert_eq!(queue.stats(now).interactive.superseded, 2);
+
+        queue.finish();
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(now)).collect();
+        assert_eq!(order, vec!["other-editor", "newest"]);
+    }
+
+    #[test]
+    fn test_cancelled_while_queued_is_skipped() {
+        let now = Instant::now();
+        let mut queue = RequestQueue::default();
+        let cancel = Arc::new(CancelToken::default());
+        queue.push("cancelled", &opts(RequestPriority::Chat, None), Some(cancel.clone()), now);
+        queue.push("next", &opts(RequestPriority::Chat, None), None, now);
+        cancel.cancel();
+
+        assert_eq!(queue.pop(now), Some("next"));
+        assert_eq!(queue.pop(now), None);
+        assert_eq!(queue.stats(now).chat.cancelled, 1);
+    }
+
+    #[test]
+    fn test_wait_time_stats() {
+        let start = Instant::now();
+        let mut queue = RequestQueue::default();
+        queue.push(1, &opts(RequestPriority::Background, None), None, start);
+        queue.push(2, &opts(RequestPriority::Background, None), None, start);
+
+        let later = start + Duration::from_millis(300);
+        assert_eq!(queue.stats(later).background.oldest_wait_ms, 300);
+
+        queue.pop(start + Duration::from_millis(100));
+        assert_eq!(queue.stats(later).running, Some(RequestPriority::Background));
+        queue.finish();
+        queue.pop(later);
+
+        let stats = queue.stats(later).background;
+        assert_eq!(stats.started, 2);
+        assert_eq!(stats.max_wait_ms, 300);
+        assert_eq!(stats.avg_wait_ms, 200.0);
+        assert_eq!(stats.queued, 0);
+    }
+}
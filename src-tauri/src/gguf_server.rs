@@ -0,0 +1,672 @@
+// src-tauri/src/gguf_server.rs
+// Optional OpenAI-compatible HTTP server on localhost, backed by the pooled GGUF models
+
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tauri::State;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::cancellation;
+use crate::gguf::{embed_with_gguf, lock_state, run_gguf_chat, GgufPrompt, GgufState};
+use crate::gguf_embedding::GgufEmbeddingOptions;
+use crate::gguf_grammar::OutputConstraint;
+use crate::gguf_inference::{GenerationOutput, GenerationParams};
+use crate::gguf_openai::{
+    chat_chunk, chat_completion, chat_logprobs, check_bearer, embedding_list, error_body, finish_reason,
+    is_json_content_type, is_local_host, is_local_origin, model_id, model_list, resolve_model, sse_event, text_chunk,
+    text_completion, text_logprobs, usage, ChatCompletionRequest, CompletionOptions, CompletionRequest,
+    EmbeddingRequest, SSE_DONE,
+};
+
+pub const DEFAULT_SERVER_PORT: u16 = 8765;
+
+/// The running server, if any
+#[derive(Default)]
+pub struct GgufServerState {
+    running: Mutex<Option<RunningServer>>,
+}
+
+struct RunningServer {
+    server: Arc<Server>,
+    thread: JoinHandle<()>,
+    context: Arc<ServerContext>,
+    port: u16,
+}
+
+/// Shared by every connection thread
+struct ServerContext {
+    pool: Box<dyn ModelPool>,
+    api_key: Option<String>,
+    requests: AtomicU64,
+}
+
+/// A pooled model as the server sees it
+struct PooledModel {
+    path: String,
+    n_ctx: u32,
+    last_used: u64,
+}
+
+/// What the server needs from the model pool: the app's `GgufState`, or a stub in tests
+trait ModelPool: Send + Sync {
+    fn models(&self) -> Vec<PooledModel>;
+
+    fn generate(
+        &self,
+        model_path: &str,
+        prompt: GgufPrompt,
+        params: &GenerationParams,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<GenerationOutput, String>;
+
+    fn embed(&self, options: &GgufEmbeddingOptions, text: &str) -> Result<Vec<f32>, String>;
+}
+
+impl ModelPool for Arc<Mutex<GgufState>> {
+    fn models(&self) -> Vec<PooledModel> {
+        let state = lock_state(self);
+        state
+            .models
+            .values()
+            .map(|m| PooledModel { path: m.model_path.clone(), n_ctx: m.n_ctx, last_used: m.last_used })
+            .collect()
+    }
+
+    fn generate(
+        &self,
+        model_path: &str,
+        prompt: GgufPrompt,
+        params: &GenerationParams,
+        on_piece: &mut dyn FnMut(&str),
+    ) -> Result<GenerationOutput, String> {
+        run_gguf_chat(self, model_path, prompt, params, on_piece)
+    }
+
+    fn embed(&self, options: &GgufEmbeddingOptions, text: &str) -> Result<Vec<f32>, String> {
+        embed_with_gguf(self, options, text)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GgufServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    /// e.g. `http://127.0.0.1:8765`, usable as `base_url` of `chat_with_http_streaming`
+    pub base_url: Option<String>,
+    pub requests_served: u64,
+    pub auth_required: bool,
+}
+
+impl GgufServerStatus {
+    fn stopped() -> Self {
+        Self { running: false, port: None, base_url: None, requests_served: 0, auth_required: false }
+    }
+}
+
+impl RunningServer {
+    fn status(&self) -> GgufServerStatus {
+        GgufServerStatus {
+            running: true,
+            port: Some(self.port),
+            base_url: Some(format!("http://127.0.0.1:{}", self.port)),
+            requests_served: self.context.requests.load(Ordering::SeqCst),
+            auth_required: self.context.api_key.is_some(),
+        }
+    }
+}
+
+/// Error turned into an OpenAI-style error response
+struct ApiError {
+    status: u16,
+    message: String,
+    kind: &'static str,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self { status: 400, message: message.into(), kind: "invalid_request_error" }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self { status: 403, message: message.into(), kind: "permission_error" }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self { status: 404, message: message.into(), kind: "not_found_error" }
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self { status: 500, message: message.into(), kind: "server_error" }
+    }
+}
+
+/// 🌐 Serve the pooled GGUF models on `127.0.0.1:<port>` with `/v1/models`,
+/// `/v1/chat/completions`, `/v1/completions` and `/v1/embeddings`.
+/// Port 0 picks a free port. With `api_key` set every request needs `Authorization: Bearer <key>`.
+#[tauri::command]
+pub async fn start_gguf_server(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    server_state: State<'_, GgufServerState>,
+    port: Option<u16>,
+    api_key: Option<String>,
+) -> Result<GgufServerStatus, String> {
+    let mut running = server_state.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(running) = running.as_ref() {
+        return Err(format!("GGUF sunucusu zaten çalışıyor: port {}", running.port));
+    }
+
+    let started = spawn_server(Box::new(state.inner().clone()), port.unwrap_or(DEFAULT_SERVER_PORT), api_key)?;
+    let status = started.status();
+    *running = Some(started);
+    Ok(status)
+}
+
+fn spawn_server(pool: Box<dyn ModelPool>, port: u16, api_key: Option<String>) -> Result<RunningServer, String> {
+    let server = Server::http(("127.0.0.1", port))
+        .map_err(|e| format!("GGUF sunucusu başlatılamadı (port {}): {}", port, e))?;
+    let port = server.server_addr().to_ip().map(|addr| addr.port()).unwrap_or(port);
+    let server = Arc::new(server);
+
+    let context = Arc::new(ServerContext {
+        pool,
+        api_key: api_key.filter(|key| !key.is_empty()),
+        requests: AtomicU64::new(0),
+    });
+
+    let thread_server = server.clone();
+    let thread_context = context.clone();
+    let thread = std::thread::Builder::new()
+        .name("gguf-server".to_string())
+        .spawn(move || serve(&thread_server, &thread_context))
+        .map_err(|e| format!("Sunucu thread'i başlatılamadı: {}", e))?;
+
+    info!("🌐 OpenAI-compatible GGUF server listening on http://127.0.0.1:{}", port);
+    Ok(RunningServer { server, thread, context, port })
+}
+
+/// Stop accepting connections; requests already running are finished
+#[tauri::command]
+pub async fn stop_gguf_server(server_state: State<'_, GgufServerState>) -> Result<GgufServerStatus, String> {
+    let running = server_state.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+    let Some(running) = running else {
+        return Ok(GgufServerStatus::stopped());
+    };
+
+    running.server.unblock();
+    let port = running.port;
+    tokio::task::spawn_blocking(move || {
+        if running.thread.join().is_err() {
+            error!("❌ GGUF server thread panicked");
+        }
+    })
+    .await
+    .map_err(|e| format!("Sunucu durdurulamadı: {}", e))?;
+
+    info!("🛑 GGUF server stopped (port {})", port);
+    Ok(GgufServerStatus::stopped())
+}
+
+#[tauri::command]
+pub async fn get_gguf_server_status(server_state: State<'_, GgufServerState>) -> Result<GgufServerStatus, String> {
+    let running = server_state.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    Ok(running.as_ref().map(RunningServer::status).unwrap_or_else(GgufServerStatus::stopped))
+}
+
+/// Accept loop; every request gets its own thread so different models run in parallel
+fn serve(server: &Server, context: &Arc<ServerContext>) {
+    for request in server.incoming_requests() {
+        let context = context.clone();
+        let spawned = std::thread::Builder::new()
+            .name("gguf-server-request".to_string())
+            .spawn(move || handle(request, &context));
+        if let Err(e) = spawned {
+            error!("❌ Request thread başlatılamadı: {}", e);
+        }
+    }
+}
+
+fn handle(mut request: Request, context: &ServerContext) {
+    context.requests.fetch_add(1, Ordering::SeqCst);
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    info!("📥 GGUF server: {} {}", method, path);
+
+    // Web pages can reach localhost too: no foreign Host (DNS rebinding) or Origin
+    if !is_local_host(header(&request, "Host").as_deref()) || !is_local_origin(header(&request, "Origin").as_deref()) {
+        return respond_error(request, ApiError::forbidden("Yalnızca localhost istekleri kabul edilir"));
+    }
+
+    let authorization = header(&request, "Authorization");
+    if !check_bearer(authorization.as_deref(), context.api_key.as_deref()) {
+        let error = ApiError { status: 401, message: "Geçersiz API anahtarı".to_string(), kind: "authentication_error" };
+        return respond_error(request, error);
+    }
+
+    let result = match (&method, path.as_str()) {
+        (Method::Get, "/v1/models") => Ok(list_models(context)),
+        (Method::Post, "/v1/chat/completions") => match read_json::<ChatCompletionRequest>(&mut request) {
+            Ok(body) => return chat_completions(request, context, body),
+            Err(e) => Err(e),
+        },
+        (Method::Post, "/v1/completions") => match read_json::<CompletionRequest>(&mut request) {
+            Ok(body) => return completions(request, context, body),
+            Err(e) => Err(e),
+        },
+        (Method::Post, "/v1/embeddings") => read_json::<EmbeddingRequest>(&mut request)
+            .and_then(|body| embeddings(context, body)),
+        _ => Err(ApiError::not_found(format!("Bilinmeyen endpoint: {} {}", method, path))),
+    };
+
+    match result {
+        Ok(body) => respond_json(request, 200, &body),
+        Err(e) => respond_error(request, e),
+    }
+}
+
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(request: &mut Request) -> Result<T, ApiError> {
+    if !is_json_content_type(header(request, "Content-Type").as_deref()) {
+        return Err(ApiError {
+            status: 415,
+            message: "Content-Type application/json olmalı".to_string(),
+            kind: "invalid_request_error",
+        });
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| ApiError::bad_request(format!("İstek gövdesi okunamadı: {}", e)))?;
+    serde_json::from_str(&body).map_err(|e| ApiError::bad_request(format!("Geçersiz JSON: {}", e)))
+}
+
+fn list_models(context: &ServerContext) -> Value {
+    let mut models: Vec<(String, u32)> = context.pool.models().into_iter().map(|m| (m.path, m.n_ctx)).collect();
+    models.sort();
+    model_list(&models)
+}
+
+/// Full path of the pooled model a request asked for
+fn pooled_model_path(context: &ServerContext, requested: Option<&str>) -> Result<String, ApiError> {
+    let pooled = context.pool.models();
+    let models: Vec<(&str, u64)> = pooled.iter().map(|m| (m.path.as_str(), m.last_used)).collect();
+    resolve_model(&models, requested).map(str::to_string).map_err(ApiError::not_found)
+}
+
+fn chat_completions(request: Request, context: &ServerContext, body: ChatCompletionRequest) {
+    let prepared = body
+        .chat_messages()
+        .and_then(|messages| Ok((messages, body.constraint()?)))
+        .map_err(ApiError::bad_request);
+    match prepared {
+        Ok((messages, constraint)) => {
+            let logprobs = body.logprobs();
+            generate(request, context, GgufPrompt::Chat(messages), &body.options, constraint, CompletionKind::Chat, logprobs)
+        }
+        Err(e) => respond_error(request, e),
+    }
+}
+
+fn completions(request: Request, context: &ServerContext, body: CompletionRequest) {
+    match body.prompt() {
+        Ok(prompt) => generate(request, context, GgufPrompt::Raw(prompt), &body.options, None, CompletionKind::Text, body.logprobs),
+        Err(e) => respond_error(request, ApiError::bad_request(e)),
+    }
+}
+
+fn embeddings(context: &ServerContext, body: EmbeddingRequest) -> Result<Value, ApiError> {
+    let model_path = pooled_model_path(context, body.model.as_deref())?;
+    let options = GgufEmbeddingOptions {
+        model_path: model_path.clone(),
+        pooling: Default::default(),
+        normalize: true,
+    };
+    let embeddings = body
+        .input
+        .into_vec()
+        .iter()
+        .map(|text| context.pool.embed(&options, text))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(ApiError::internal)?;
+    Ok(embedding_list(&model_id(&model_path), embeddings))
+}
+
+#[derive(Clone, Copy)]
+enum CompletionKind {
+    Chat,
+    Text,
+}
+
+/// Run a chat or text completion and answer with one JSON body, or with SSE chunks when `stream` is set.
+/// Logprobs are only reported in the JSON body, streamed chunks carry none.
+fn generate(
+    request: Request,
+    context: &ServerContext,
+    prompt: GgufPrompt,
+    options: &CompletionOptions,
+    constraint: Option<OutputConstraint>,
+    kind: CompletionKind,
+    logprobs: Option<u32>,
+) {
+    let model_path = match pooled_model_path(context, options.model.as_deref()) {
+        Ok(path) => path,
+        Err(e) => return respond_error(request, e),
+    };
+    let grammar = match constraint.as_ref().map(OutputConstraint::compile).transpose() {
+        Ok(grammar) => grammar,
+        Err(e) => return respond_error(request, ApiError::bad_request(e)),
+    };
+
+    let id = match kind {
+        CompletionKind::Chat => format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        CompletionKind::Text => format!("cmpl-{}", uuid::Uuid::new_v4().simple()),
+    };
+    // Registered under the response ID, so `cancel_generation` can stop it from the app too
+    let generation = cancellation::register_generation(Some(id.clone()));
+    let params = GenerationParams {
+        max_tokens: options.max_tokens(),
+        temperature: options.temperature(),
+        sampling: options.sampling(),
+        grammar,
+        stop: options.stop(),
+        speculative: None,
+        schedule: Default::default(),
+        loras: None,
+        cancel: Some(generation.token.clone()),
+        logprobs: logprobs.filter(|_| !options.stream),
+        context_shift: None,
+        cold_start: false,
+    };
+    let model = model_id(&model_path);
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    if !options.stream {
+        let output = match context.pool.generate(&model_path, prompt, &params, &mut |_| {}) {
+            Ok(output) => output,
+            Err(e) => return respond_error(request, ApiError::internal(e)),
+        };
+        let reason = finish_reason(output.tokens.len(), params.max_tokens, output.stop_sequence.is_some());
+        let usage = usage(output.prompt_tokens, output.tokens.len());
+        let mut body = match kind {
+            CompletionKind::Chat => {
+                chat_completion(&id, &model, created, &output.text, reason, usage, chat_logprobs(output.logprobs.as_deref()))
+            }
+            CompletionKind::Text => {
+                text_completion(&id, &model, created, &output.text, reason, usage, text_logprobs(output.logprobs.as_deref()))
+            }
+        };
+        // Non-standard, like llama.cpp's server: speed figures for clients that want them
+        body["timings"] = json!(output.metrics);
+        return respond_json(request, 200, &body);
+    }
+
+    // 🌊 Stream: the headers go out with the first piece, so errors before it still get a proper status.
+    // Chat streams open with a role chunk, sent along with that first piece.
+    let mut stream = SseStream { writer: request.into_writer(), started: false, closed: false };
+    let role_chunk = chat_chunk(&id, &model, created, json!({ "role": "assistant", "content": "" }), None);
+    let result = context.pool.generate(&model_path, prompt, &params, &mut |piece| {
+        if let (CompletionKind::Chat, false) = (kind, stream.started) {
+            stream.send(&role_chunk);
+        }
+        let chunk = match kind {
+            CompletionKind::Chat => chat_chunk(&id, &model, created, json!({ "content": piece }), None),
+            CompletionKind::Text => text_chunk(&id, &model, created, piece, None),
+        };
+        // The client went away, stop generating for it
+        if !stream.send(&chunk) {
+            generation.token.cancel();
+        }
+    });
+
+    match result {
+        Ok(output) => {
+            if output.cancelled {
+                info!("🛑 GGUF server generation cancelled: {}", id);
+            }
+            if let (CompletionKind::Chat, false) = (kind, stream.started) {
+                stream.send(&role_chunk);
+            }
+            let reason = finish_reason(output.tokens.len(), params.max_tokens, output.stop_sequence.is_some());
+            let last = match kind {
+                CompletionKind::Chat => chat_chunk(&id, &model, created, json!({}), Some(reason)),
+                CompletionKind::Text => text_chunk(&id, &model, created, "", Some(reason)),
+            };
+            stream.send(&last);
+            stream.finish();
+        }
+        Err(e) if !stream.started => stream.fail(ApiError::internal(e)),
+        Err(e) => {
+            error!("❌ GGUF server stream failed: {}", e);
+            stream.send(&error_body(&e, "server_error"));
+            stream.finish();
+        }
+    }
+}
+
+/// SSE response written by hand with chunked transfer encoding, flushed after every event
+struct SseStream {
+    writer: Box<dyn Write + Send>,
+    started: bool,
+    /// Set after a write failed, i.e. the client disconnected
+    closed: bool,
+}
+
+impl SseStream {
+    /// Returns false once the client is gone
+    fn send(&mut self, data: &Value) -> bool {
+        if self.closed {
+            return false;
+        }
+        let result = self.write_head().and_then(|_| self.write_chunk(sse_event(data).as_bytes()));
+        if let Err(e) = result {
+            warn!("⚠️ GGUF server client disconnected: {}", e);
+            self.closed = true;
+        }
+        !self.closed
+    }
+
+    fn finish(mut self) {
+        if self.closed {
+            return;
+        }
+        let result = self
+            .write_head()
+            .and_then(|_| self.write_chunk(SSE_DONE.as_bytes()))
+            .and_then(|_| self.writer.write_all(b"0\r\n\r\n"))
+            .and_then(|_| self.writer.flush());
+        if let Err(e) = result {
+            warn!("⚠️ GGUF server client disconnected: {}", e);
+        }
+    }
+
+    /// Plain JSON error, only possible before anything was streamed
+    fn fail(mut self, error: ApiError) {
+        let body = error_body(&error.message, error.kind).to_string();
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            error.status,
+            reason_phrase(error.status),
+            body.len(),
+            body
+        );
+        if let Err(e) = self.writer.write_all(response.as_bytes()).and_then(|_| self.writer.flush()) {
+            warn!("⚠️ GGUF server client disconnected: {}", e);
+        }
+    }
+
+    fn write_head(&mut self) -> std::io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        self.writer.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nTransfer-Encoding: chunked\r\n\r\n",
+        )
+    }
+
+    fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<()> {
+        write!(self.writer, "{:x}\r\n", data.len())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(b"\r\n")?;
+        self.writer.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        415 => "Unsupported Media Type",
+        _ => "Internal Server Error",
+    }
+}
+
+fn respond_json(request: Request, status: u16, body: &Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body.to_string()).with_status_code(status).with_header(header);
+    if let Err(e) = request.respond(response) {
+        warn!("⚠️ GGUF server response failed: {}", e);
+    }
+}
+
+fn respond_error(request: Request, error: ApiError) {
+    warn!("⚠️ GGUF server {}: {}", error.status, error.message);
+    respond_json(request, error.status, &error_body(&error.message, error.kind));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gguf_openai::{parse_completion_sse_line, SseData};
+    use futures_util::StreamExt;
+
+    const STUB_MODEL: &str = "/models/stub-1b-q4_k_m.gguf";
+    const STUB_PIECES: [&str; 3] = ["Merhaba", ", ", "dünya"];
+
+    /// One pooled model that answers every prompt with `STUB_PIECES`, or fails
+    struct StubPool {
+        fail: bool,
+    }
+
+    impl ModelPool for StubPool {
+        fn models(&self) -> Vec<PooledModel> {
+            vec![PooledModel { path: STUB_MODEL.to_string(), n_ctx: 2048, last_used: 1 }]
+        }
+
+        fn generate(
+            &self,
+            _model_path: &str,
+            _prompt: GgufPrompt,
+            _params: &GenerationParams,
+            on_piece: &mut dyn FnMut(&str),
+        ) -> Result<GenerationOutput, String> {
+            if self.fail {
+                return Err("stub failure".to_string());
+            }
+            let mut output = GenerationOutput { prompt_tokens: 4, ..Default::default() };
+            for piece in STUB_PIECES {
+                on_piece(piece);
+                output.text.push_str(piece);
+            }
+            Ok(output)
+        }
+
+        fn embed(&self, _options: &GgufEmbeddingOptions, _text: &str) -> Result<Vec<f32>, String> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    fn start(fail: bool) -> (RunningServer, String) {
+        let server = spawn_server(Box::new(StubPool { fail }), 0, None).unwrap();
+        let base_url = server.status().base_url.unwrap();
+        (server, base_url)
+    }
+
+    fn stop(server: RunningServer) {
+        server.server.unblock();
+        server.thread.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_client_streams_from_server() {
+        let (server, base_url) = start(false);
+        let client = reqwest::Client::new();
+
+        let models: Value = client.get(format!("{}/v1/models", base_url)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(models["data"][0]["path"], STUB_MODEL);
+        assert_eq!(models["data"][0]["n_ctx"], 2048);
+
+        // The request and the chunk-by-chunk parsing of `chat_with_http_streaming`
+        let response = client
+            .post(format!("{}/v1/completions", base_url))
+            .json(&json!({ "model": "default", "prompt": "Selam", "max_tokens": 16, "temperature": 0.7, "stream": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+        let mut stream = response.bytes_stream();
+        let (mut text, mut done) = (String::new(), false);
+        while let Some(chunk) = stream.next().await {
+            for line in String::from_utf8_lossy(&chunk.unwrap()).lines() {
+                match parse_completion_sse_line(line) {
+                    Some(SseData::Text(piece)) => text.push_str(&piece),
+                    Some(SseData::Done) => done = true,
+                    None => {}
+                }
+            }
+        }
+        assert_eq!(text, STUB_PIECES.concat());
+        assert!(done);
+
+        stop(server);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_browser_requests_and_reports_early_stream_errors() {
+        let (server, base_url) = start(true);
+        let client = reqwest::Client::new();
+        let url = format!("{}/v1/completions", base_url);
+        let body = json!({ "prompt": "Selam", "stream": true });
+
+        // A web page can send text/plain without a CORS preflight
+        let plain = client.post(&url).header("Content-Type", "text/plain").body(body.to_string()).send().await.unwrap();
+        assert_eq!(plain.status(), 415);
+        let cross_site = client.post(&url).header("Origin", "https://attacker.example").json(&body).send().await.unwrap();
+        assert_eq!(cross_site.status(), 403);
+        let rebound = client.post(&url).header("Host", "attacker.example:8765").json(&body).send().await.unwrap();
+        assert_eq!(rebound.status(), 403);
+
+        // Nothing was streamed yet, so a failed chat stream still gets a status and a JSON error
+        let chat = client
+            .post(format!("{}/v1/chat/completions", base_url))
+            .json(&json!({ "messages": [{ "role": "user", "content": "Selam" }], "stream": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(chat.status(), 500);
+        let error: Value = chat.json().await.unwrap();
+        assert_eq!(error["error"]["message"], "stub failure");
+
+        stop(server);
+    }
+}
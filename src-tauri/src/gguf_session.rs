@@ -0,0 +1,163 @@
+// src-tauri/src/gguf_session.rs
+// Sidecar metadata for saved llama.cpp session files
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::gguf_lora::ActiveLora;
+
+/// Bumped whenever the sidecar layout changes (2: LoRA adapters recorded)
+pub const SESSION_FORMAT_VERSION: u32 = 2;
+
+/// Written next to the session file so a restore can check it belongs to the loaded model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMeta {
+    pub format_version: u32,
+    pub model_path: String,
+    pub model_file_name: String,
+    pub model_size_bytes: u64,
+    /// Context length the model was loaded with
+    pub n_ctx: u32,
+    /// KV cache size of the context that was saved
+    pub kv_size: u32,
+    pub n_tokens: usize,
+    /// Adapters the KV cache was computed with, set again on restore
+    #[serde(default)]
+    pub loras: Vec<ActiveLora>,
+    pub saved_at: u64,
+}
+
+impl SessionMeta {
+    /// Describe the currently loaded model; `n_tokens` and `saved_at` are filled in on save
+    pub fn for_model(model_path: &str, n_ctx: u32, kv_size: u32) -> Result<Self, String> {
+        let path = Path::new(model_path);
+        let size = std::fs::metadata(path)
+            .map_err(|e| format!("Model dosyası okunamadı: {}", e))?
+            .len();
+
+        Ok(Self {
+            format_version: SESSION_FORMAT_VERSION,
+            model_path: model_path.to_string(),
+            model_file_name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            model_size_bytes: size,
+            n_ctx,
+            kv_size,
+            n_tokens: 0,
+            loras: Vec::new(),
+            saved_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        })
+    }
+
+    /// Check that a saved session can be restored into `current`.
+    /// The model is matched by file name and size so a moved model file still restores.
+    pub fn check_compatible(&self, current: &SessionMeta) -> Result<(), String> {
+        if self.format_version != SESSION_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported session format version {} (expected {})",
+                self.format_version, SESSION_FORMAT_VERSION
+            ));
+        }
+        if self.model_file_name != current.model_file_name || self.model_size_bytes != current.model_size_bytes {
+            return Err(format!(
+                "Session was saved with a different model: {} ({} bytes), loaded model is {} ({} bytes)",
+                self.model_file_name, self.model_size_bytes, current.model_file_name, current.model_size_bytes
+            ));
+        }
+        if self.n_ctx != current.n_ctx {
+            return Err(format!(
+                "Context size mismatch: session has n_ctx={}, loaded model has n_ctx={}",
+                self.n_ctx, current.n_ctx
+            ));
+        }
+        if self.n_tokens > self.kv_size as usize {
+            return Err(format!(
+                "Corrupt session metadata: {} tokens in a KV cache of {}",
+                self.n_tokens, self.kv_size
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, session_path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(meta_path(session_path), json)
+            .map_err(|e| format!("Session metadata yazılamadı: {}", e))
+    }
+
+    pub fn read(session_path: &str) -> Result<Self, String> {
+        let path = meta_path(session_path);
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Session metadata okunamadı ({}): {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid session metadata: {}", e))
+    }
+}
+
+/// `chat.session` -> `chat.session.json`
+pub fn meta_path(session_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.json", session_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> SessionMeta {
+        SessionMeta {
+            format_version: SESSION_FORMAT_VERSION,
+            model_path: "/models/qwen-7b-q4_k_m.gguf".to_string(),
+            model_file_name: "qwen-7b-q4_k_m.gguf".to_string(),
+            model_size_bytes: 4_683_073_536,
+            n_ctx: 8192,
+            kv_size: 10240,
+            n_tokens: 1500,
+            loras: vec![ActiveLora { name: "fastapi-r16".to_string(), scale: 0.8 }],
+            saved_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_compatible_session() {
+        let saved = meta();
+        let mut current = meta();
+        current.model_path = "/other/dir/qwen-7b-q4_k_m.gguf".to_string();
+        current.kv_size = 4096;
+        assert!(saved.check_compatible(&current).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_mismatches() {
+        let saved = meta();
+
+        let mut other_model = meta();
+        other_model.model_size_bytes += 1;
+        assert!(saved.check_compatible(&other_model).unwrap_err().contains("different model"));
+
+        let mut other_ctx = meta();
+        other_ctx.n_ctx = 4096;
+        assert!(saved.check_compatible(&other_ctx).unwrap_err().contains("Context size mismatch"));
+
+        let mut old_format = meta();
+        old_format.format_version = 0;
+        assert!(old_format.check_compatible(&meta()).is_err());
+    }
+
+    #[test]
+    fn test_meta_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("corex-session-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let session = dir.join("chat.session");
+        let session = session.to_str().unwrap();
+
+        meta().write(session).unwrap();
+        assert_eq!(SessionMeta::read(session).unwrap(), meta());
+        assert!(meta_path(session).ends_with("chat.session.json"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+// src-tauri/src/gguf_speculative.rs
+// Speculative decoding: a small draft model proposes tokens, the target model verifies them in one batch
+
+use llama_cpp_2::context::LlamaContext;
+use llama_cpp_2::llama_batch::LlamaBatch;
+use llama_cpp_2::model::LlamaModel;
+use llama_cpp_2::token::LlamaToken;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::gguf_inference::{evaluate_prompt, GenerationOutput, GenerationParams};
+use crate::gguf_metrics::{elapsed_ms, stop_reason};
+use crate::gguf_sampling::{Candidate, Sampler};
+use crate::stop_sequences::StopMatcher;
+
+/// Draft model settings passed with a GGUF chat request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeculativeConfig {
+    /// Pooled model from the same family as the target (shared vocabulary)
+    pub draft_model_path: String,
+    /// Tokens the draft proposes per round
+    #[serde(default = "default_n_draft")]
+    pub n_draft: u32,
+}
+
+fn default_n_draft() -> u32 {
+    5
+}
+
+/// How well the draft model predicted the target
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SpeculativeStats {
+    pub rounds: u32,
+    pub drafted: u32,
+    pub accepted: u32,
+    pub acceptance_rate: f32,
+}
+
+/// One side of speculative decoding: a model and its context
+pub struct SpeculativeModel<'a, 'ctx> {
+    pub model: &'a LlamaModel,
+    pub context: &'a mut LlamaContext<'ctx>,
+    /// Prompt tokens already in the context's KV cache
+    pub reused: usize,
+}
+
+/// Greedy generation with a draft model.
+///
+/// Each round the draft proposes up to `n_draft` tokens; the target evaluates the
+/// last accepted token plus all proposals in one batch and keeps proposals while
+/// its own greedy choice agrees, then adds its own token at the first mismatch.
+/// The output is therefore identical to plain greedy decoding with the same
+/// penalties. `params.temperature` is ignored.
+///
+/// Like `generate`, the first `reused` prompt tokens must already be in each KV
+/// cache. Afterwards the target cache holds `tokens` + `output.tokens`; the draft
+/// cache holds the first N tokens of that sequence, N being the returned count.
+pub fn generate_speculative(
+    target: SpeculativeModel,
+    draft: SpeculativeModel,
+    tokens: &[LlamaToken],
+    config: &SpeculativeConfig,
+    params: &GenerationParams,
+    on_piece: &mut dyn FnMut(&str),
+) -> Result<(GenerationOutput, usize), String> {
+    let SpeculativeModel { model: target, context: target_ctx, reused: target_reused } = target;
+    let SpeculativeModel { model: draft, context: draft_ctx, reused: draft_reused } = draft;
+    if params.grammar.is_some() {
+        return Err("Speculative decoding grammar kısıtlamalarını desteklemiyor".to_string());
+    }
+
+    let n_draft = config.n_draft.clamp(1, 16) as usize;
+    let max_tokens = params.max_tokens as usize;
+    info!("🏎️ Speculative decoding: n_draft={}", n_draft);
+
+    let started = std::time::Instant::now();
+    evaluate_prompt(target_ctx, tokens, target_reused)?;
+    evaluate_prompt(draft_ctx, tokens, draft_reused)?;
+    let prompt_eval_ms = elapsed_ms(started);
+    let generation_started = std::time::Instant::now();
+
+    let mut sink = TokenSink::new(target, &params.stop, on_piece);
+    sink.output.prompt_tokens = tokens.len();
+    sink.output.reused_tokens = target_reused;
+
+    let mut sampler = Sampler::new(params.sampling.clone(), 0.0);
+    let mut history: Vec<i32> = tokens.iter().map(|t| t.0).collect();
+    let mut seq: Vec<LlamaToken> = tokens.to_vec();
+    // Positions of `seq` currently in each KV cache
+    let mut n_target = tokens.len();
+    let mut n_draft_kv = tokens.len();
+    let mut stats = SpeculativeStats::default();
+
+    let mut target_batch = LlamaBatch::new(n_draft + 1, 1);
+    let mut draft_batch = LlamaBatch::new(n_draft + 1, 1);
+
+    // The first token comes straight from the prompt's logits
+    let mut done = max_tokens == 0 || match sampler.sample(candidates(target_ctx.candidates()), &history) {
+        Some(id) if !target.is_eog_token(LlamaToken::new(id)) => {
+            let token = LlamaToken::new(id);
+            history.push(id);
+            seq.push(token);
+            sink.push(token) || max_tokens <= 1
+        }
+        _ => true,
+    };
+
+    while !done {
+        if params.cancel.as_ref().map(|c| c.is_cancelled()).unwrap_or(false) {
+            info!("🛑 Speculative generation cancelled after {} tokens", sink.output.tokens.len());
+            sink.output.cancelled = true;
+            break;
+        }
+
+        // Bring the draft up to date with everything accepted so far
+        draft_batch.clear();
+        for (pos, token) in seq.iter().enumerate().skip(n_draft_kv) {
+            draft_batch.add(*token, pos as i32, &[0], pos == seq.len() - 1)
+                .map_err(|e| format!("Batch add failed: {:?}", e))?;
+        }
+        draft_ctx.decode(&mut draft_batch)
+            .map_err(|e| format!("Draft decode failed: {:?}", e))?;
+        n_draft_kv = seq.len();
+
+        // Never draft more than can still be emitted
+        let remaining = max_tokens - sink.output.tokens.len();
+        let n_propose = n_draft.min(remaining.saturating_sub(1));
+
+        let mut drafts = Vec::with_capacity(n_propose);
+        let mut draft_decoded = 0;
+        while drafts.len() < n_propose {
+            let Some(id) = argmax(draft_ctx.candidates()) else { break };
+            let token = LlamaToken::new(id);
+            drafts.push(token);
+            if draft.is_eog_token(token) || drafts.len() == n_propose {
+                break;
+            }
+            draft_batch.clear();
+            draft_batch.add(token, (seq.len() + draft_decoded) as i32, &[0], true)
+                .map_err(|e| format!("Batch add failed: {:?}", e))?;
+            draft_ctx.decode(&mut draft_batch)
+                .map_err(|e| format!("Draft decode failed: {:?}", e))?;
+            draft_decoded += 1;
+        }
+
+        // Target scores the last accepted token and every proposal at once
+        target_batch.clear();
+        target_batch.add(seq[seq.len() - 1], n_target as i32, &[0], true)
+            .map_err(|e| format!("Batch add failed: {:?}", e))?;
+        for (j, token) in drafts.iter().enumerate() {
+            target_batch.add(*token, (n_target + 1 + j) as i32, &[0], true)
+                .map_err(|e| format!("Batch add failed: {:?}", e))?;
+        }
+        target_ctx.decode(&mut target_batch)
+            .map_err(|e| format!("Verify decode failed: {:?}", e))?;
+
+        stats.rounds += 1;
+        stats.drafted += drafts.len() as u32;
+
+        let mut accepted = 0;
+        let mut confirmed = 0;
+        for i in 0..=drafts.len() {
+            // Batch positions 0..=i now hold tokens that are part of `seq`
+            confirmed = i + 1;
+            let choice = sampler.sample(candidates(target_ctx.candidates_ith(i as i32)), &history);
+            let Some(id) = choice else {
+                done = true;
+                break;
+            };
+            let token = LlamaToken::new(id);
+            if target.is_eog_token(token) {
+                info!("✅ EOS token found, stopping");
+                done = true;
+                break;
+            }
+
+            history.push(id);
+            seq.push(token);
+            if sink.push(token) || sink.output.tokens.len() >= max_tokens {
+                done = true;
+                break;
+            }
+            if i < drafts.len() && token == drafts[i] {
+                accepted += 1;
+                continue;
+            }
+            break;
+        }
+        stats.accepted += accepted as u32;
+
+        // Drop rejected proposals from both caches
+        n_target += confirmed;
+        target_ctx.clear_kv_cache_seq(Some(0), Some(n_target as u32), None)
+            .map_err(|e| format!("KV cache trim failed: {:?}", e))?;
+        n_draft_kv += accepted.min(draft_decoded);
+        draft_ctx.clear_kv_cache_seq(Some(0), Some(n_draft_kv as u32), None)
+            .map_err(|e| format!("KV cache trim failed: {:?}", e))?;
+    }
+
+    // The last emitted token has not been evaluated by the target yet
+    if n_target < seq.len() {
+        target_batch.clear();
+        for (pos, token) in seq.iter().enumerate().skip(n_target) {
+            target_batch.add(*token, pos as i32, &[0], pos == seq.len() - 1)
+                .map_err(|e| format!("Batch add failed: {:?}", e))?;
+        }
+        target_ctx.decode(&mut target_batch)
+            .map_err(|e| format!("Decode failed: {:?}", e))?;
+    }
+
+    if stats.drafted > 0 {
+        stats.acceptance_rate = stats.accepted as f32 / stats.drafted as f32;
+    }
+    info!(
+        "✅ Speculative decoding: {} tokens in {} rounds, {}/{} drafts accepted ({:.0}%)",
+        sink.output.tokens.len(),
+        stats.rounds,
+        stats.accepted,
+        stats.drafted,
+        stats.acceptance_rate * 100.0
+    );
+
+    let mut output = sink.finish();
+    output.speculative = Some(stats);
+    // Prompt timing covers both models, the draft's work counts as generation time
+    output.metrics.prompt_tokens = tokens.len();
+    output.metrics.prompt_eval_tokens = tokens.len() - target_reused;
+    output.metrics.prompt_eval_ms = prompt_eval_ms;
+    output.metrics.generated_tokens = output.tokens.len();
+    output.metrics.generation_ms = elapsed_ms(generation_started);
+    output.metrics.stop_reason = stop_reason(
+        output.cancelled,
+        output.stop_sequence.is_some(),
+        output.tokens.len(),
+        params.max_tokens,
+    );
+    Ok((output, n_draft_kv))
+}
+
+/// Collects emitted tokens and their text, applying stop strings like `generate`
+struct TokenSink<'a> {
+    model: &'a LlamaModel,
+    decoder: encoding_rs::Decoder,
+    stop: StopMatcher,
+    on_piece: &'a mut dyn FnMut(&str),
+    output: GenerationOutput,
+}
+
+impl<'a> TokenSink<'a> {
+    fn new(model: &'a LlamaModel, stop: &[String], on_piece: &'a mut dyn FnMut(&str)) -> Self {
+        Self {
+            model,
+            decoder: encoding_rs::UTF_8.new_decoder(),
+            stop: StopMatcher::new(stop),
+            on_piece,
+            output: GenerationOutput::default(),
+        }
+    }
+
+    /// Emit one token. Returns true when a stop string was hit.
+    fn push(&mut self, token: LlamaToken) -> bool {
+        if self.output.tokens.is_empty() {
+            self.output.first_token_at = Some(std::time::Instant::now());
+        }
+        self.output.tokens.push(token);
+        let Ok(piece) = self.model.token_to_piece(token, &mut self.decoder, false, None) else {
+            return false;
+        };
+
+        let result = self.stop.push(&piece);
+        self.emit(&result.emit);
+        if let Some(stop) = result.stopped {
+            info!("🛑 Stop sequence {:?} found", stop);
+            self.output.stop_sequence = Some(stop);
+            return true;
+        }
+        false
+    }
+
+    fn emit(&mut self, text: &str) {
+        if !text.is_empty() {
+            (self.on_piece)(text);
+            self.output.text.push_str(text);
+        }
+    }
+
+    fn finish(mut self) -> GenerationOutput {
+        let held = self.stop.finish();
+        self.emit(&held);
+        self.output
+    }
+}
+
+fn candidates(iter: impl Iterator<Item = llama_cpp_2::token::data::LlamaTokenData>) -> Vec<Candidate> {
+    iter.map(|c| Candidate::new(c.id().0, c.logit())).collect()
+}
+
+/// Plain greedy pick for the draft; its quality only affects speed, not output
+fn argmax(iter: impl Iterator<Item = llama_cpp_2::token::data::LlamaTokenData>) -> Option<i32> {
+    iter.max_by(|a, b| a.logit().total_cmp(&b.logit())).map(|c| c.id().0)
+}
@@ -0,0 +1,141 @@
+// src-tauri/src/gguf_vision.rs
+// Image input for multimodal GGUF models: mmproj projector discovery, image decoding, media markers
+
+use base64::{engine::general_purpose, Engine as _};
+use std::path::Path;
+
+/// Best projector for `model_file` among the `candidates` file names of its directory.
+/// Only files with "mmproj" in the name count; the one sharing the most name parts with
+/// the model wins, a lone projector is taken as is.
+pub fn pick_mmproj(model_file: &str, candidates: &[String]) -> Option<String> {
+    let model_parts = name_parts(model_file);
+    let projectors: Vec<&String> = candidates
+        .iter()
+        .filter(|name| {
+            let lower = name.to_lowercase();
+            lower.contains("mmproj") && lower.ends_with(".gguf") && name.as_str() != model_file
+        })
+        .collect();
+
+    let scored: Vec<(usize, &String)> = projectors
+        .iter()
+        .map(|name| (name_parts(name).iter().filter(|part| model_parts.contains(part)).count(), *name))
+        .collect();
+    let best = scored.iter().map(|(score, _)| *score).max()?;
+    if best == 0 && projectors.len() > 1 {
+        return None;
+    }
+    // Ties go to the alphabetically first name, so the choice is stable
+    scored.iter().filter(|(score, _)| *score == best).map(|(_, name)| *name).min().cloned()
+}
+
+/// Lowercase name parts that identify a model, without the projector, quantization and extension noise
+fn name_parts(file_name: &str) -> Vec<String> {
+    let stem = file_name.strip_suffix(".gguf").unwrap_or(file_name).to_lowercase();
+    stem.split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .filter(|part| !matches!(*part, "mmproj" | "model" | "gguf" | "f16" | "f32" | "bf16"))
+        .filter(|part| !(part.starts_with('q') && part[1..].starts_with(|c: char| c.is_ascii_digit())))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Projector file next to `model_path`, if there is one
+pub fn find_mmproj(model_path: &str) -> Option<String> {
+    let path = Path::new(model_path);
+    let model_file = path.file_name()?.to_str()?;
+    let dir = path.parent()?;
+    let candidates: Vec<String> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    pick_mmproj(model_file, &candidates).map(|name| dir.join(name).to_string_lossy().to_string())
+}
+
+/// Image bytes from base64, with or without a `data:image/...;base64,` prefix
+pub fn decode_image(data: &str) -> Result<Vec<u8>, String> {
+    let base64_data = match data.split_once("base64,") {
+        Some((_, encoded)) => encoded,
+        None => data,
+    };
+    let bytes = general_purpose::STANDARD
+        .decode(base64_data.trim())
+        .map_err(|e| format!("Görsel base64 çözülemedi: {}", e))?;
+
+    match image_format(&bytes) {
+        Some("webp") => Err("WebP görseller desteklenmiyor, PNG veya JPEG kullanın".to_string()),
+        Some(_) => Ok(bytes),
+        None => Err("Tanınmayan görsel formatı (PNG, JPEG, GIF veya BMP bekleniyor)".to_string()),
+    }
+}
+
+/// Format of an encoded image by its magic bytes
+pub fn image_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("gif"),
+        [b'B', b'M', ..] => Some("bmp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Place one media marker per image in the prompt. Markers the caller already put in
+/// are kept where they are; without any, the images go before the text.
+pub fn with_media_markers(prompt: &str, n_images: usize, marker: &str) -> Result<String, String> {
+    match prompt.matches(marker).count() {
+        0 => Ok(format!("{}{}", marker.repeat(n_images), prompt)),
+        n if n == n_images => Ok(prompt.to_string()),
+        n => Err(format!("Prompt {} görsel işaretçisi içeriyor ama {} görsel gönderildi", n, n_images)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_pick_mmproj() {
+        let dir = names(&[
+            "Qwen2-VL-7B-Instruct-Q4_K_M.gguf",
+            "mmproj-Qwen2-VL-7B-Instruct-f16.gguf",
+            "llava-v1.6-mistral-7b.Q4_K_M.gguf",
+            "mmproj-llava-v1.6-mistral-7b-f16.gguf",
+        ]);
+        assert_eq!(
+            pick_mmproj("Qwen2-VL-7B-Instruct-Q4_K_M.gguf", &dir).as_deref(),
+            Some("mmproj-Qwen2-VL-7B-Instruct-f16.gguf")
+        );
+        assert_eq!(
+            pick_mmproj("llava-v1.6-mistral-7b.Q4_K_M.gguf", &dir).as_deref(),
+            Some("mmproj-llava-v1.6-mistral-7b-f16.gguf")
+        );
+
+        // A lone projector is used even when its name says nothing about the model
+        let lone = names(&["ggml-model-q4_k.gguf", "mmproj-model-f16.gguf"]);
+        assert_eq!(pick_mmproj("ggml-model-q4_k.gguf", &lone).as_deref(), Some("mmproj-model-f16.gguf"));
+        assert_eq!(pick_mmproj("qwen2.5-coder-7b.gguf", &names(&["qwen2.5-coder-7b.gguf"])), None);
+    }
+
+    #[test]
+    fn test_decode_image() {
+        let png = general_purpose::STANDARD.encode([0x89, b'P', b'N', b'G', 0x0D, 0x0A]);
+        assert_eq!(decode_image(&format!("data:image/png;base64,{}", png)).unwrap()[1], b'P');
+        assert!(decode_image(&png).is_ok());
+        assert!(decode_image("not base64!").is_err());
+        assert!(decode_image(&general_purpose::STANDARD.encode(b"RIFF\0\0\0\0WEBPVP8 ")).is_err());
+    }
+
+    #[test]
+    fn test_media_markers() {
+        let marker = "<__media__>";
+        assert_eq!(with_media_markers("What is this?", 2, marker).unwrap(), "<__media__><__media__>What is this?");
+        assert_eq!(with_media_markers("Compare <__media__> and <__media__>", 2, marker).unwrap(), "Compare <__media__> and <__media__>");
+        assert!(with_media_markers("Only <__media__>", 2, marker).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+// src-tauri/src/gguf_worker.rs
+// One worker thread per pooled GGUF model: requests queue up per model, different models run in parallel
+
+use log::{error, info};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::Instant;
+
+use crate::cancellation::CancelToken;
+use crate::gguf_scheduler::{QueueStats, RequestQueue, ScheduleOptions};
+
+/// Work item executed on the worker thread with exclusive access to its state
+pub type Job<S> = Box<dyn FnOnce(&mut S) + Send>;
+
+struct Queue<S> {
+    jobs: RequestQueue<Job<S>>,
+    /// Set when the last handle is dropped; the thread exits once the queue is drained
+    closed: bool,
+}
+
+struct Shared<S> {
+    queue: Mutex<Queue<S>>,
+    available: Condvar,
+    completed: AtomicU64,
+}
+
+/// Snapshot of a worker's queue, cheap to read while a job is running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct WorkerStatus {
+    pub busy: bool,
+    pub queued: usize,
+    pub completed: u64,
+}
+
+/// Handle to a worker thread owning a state `S`. Cloning shares the same thread;
+/// the thread finishes the queued jobs and exits when the last handle is dropped.
+pub struct Worker<S> {
+    shared: Arc<Shared<S>>,
+    _closer: Arc<CloseOnDrop<S>>,
+}
+
+impl<S> Clone for Worker<S> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone(), _closer: self._closer.clone() }
+    }
+}
+
+struct CloseOnDrop<S>(Arc<Shared<S>>);
+
+impl<S> Drop for CloseOnDrop<S> {
+    fn drop(&mut self) {
+        let mut queue = self.0.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        queue.closed = true;
+        self.0.available.notify_all();
+    }
+}
+
+/// Message from a streaming job back to the caller
+enum Message<T, R> {
+    Item(T),
+    Done(R),
+}
+
+impl<S: Send + 'static> Worker<S> {
+    /// Move `state` onto a new named thread
+    pub fn spawn(name: &str, mut state: S) -> Result<Self, String> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { jobs: RequestQueue::default(), closed: false }),
+            available: Condvar::new(),
+            completed: AtomicU64::new(0),
+        });
+
+        let thread_shared = shared.clone();
+        let thread_name = name.to_string();
+        std::thread::Builder::new()
+            .name(format!("gguf-worker-{}", name))
+            .spawn(move || {
+                while let Some(job) = thread_shared.next_job() {
+                    // A panicking job must not take the model down with it; its reply channel
+                    // is dropped, so the caller gets an error instead of waiting forever
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job(&mut state)));
+                    if result.is_err() {
+                        error!("❌ GGUF worker job panicked: {}", thread_name);
+                    }
+                    thread_shared.finish_job();
+                }
+                info!("🧹 GGUF worker stopped: {}", thread_name);
+            })
+            .map_err(|e| format!("Worker thread başlatılamadı: {}", e))?;
+
+        let closer = Arc::new(CloseOnDrop(shared.clone()));
+        Ok(Self { shared, _closer: closer })
+    }
+
+    /// Queue a job with default priority without waiting for it
+    pub fn submit(&self, job: Job<S>) -> Result<(), String> {
+        self.schedule(job, &ScheduleOptions::default(), None)
+    }
+
+    /// Queue a job by priority class. `cancel` lets a superseding request stop it
+    /// and lets the queue skip it when it is cancelled before it starts.
+    pub fn schedule(&self, job: Job<S>, options: &ScheduleOptions, cancel: Option<Arc<CancelToken>>) -> Result<(), String> {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if queue.closed {
+            return Err("Model worker kapatıldı".to_string());
+        }
+        let superseded = queue.jobs.push(job, options, cancel, Instant::now());
+        if superseded > 0 {
+            info!("♻️ {} stale request(s) superseded ({:?})", superseded, options.supersede_key);
+        }
+        self.shared.available.notify_one();
+        Ok(())
+    }
+
+    /// Run `f` on the worker thread and block until it returns
+    pub fn call<R: Send + 'static>(&self, f: impl FnOnce(&mut S) -> R + Send + 'static) -> Result<R, String> {
+        let (tx, rx) = mpsc::channel();
+        self.submit(Box::new(move |state| {
+            let _ = tx.send(f(state));
+        }))?;
+        rx.recv().map_err(|_| "Model worker isteği tamamlamadı".to_string())
+    }
+
+    /// Like `call`, but scheduled with `options` and `f` can hand intermediate items
+    /// (e.g. decoded pieces) to `on_item`, which runs on the calling thread while the
+    /// job is still going.
+    pub fn call_streaming<T, R>(
+        &self,
+        options: &ScheduleOptions,
+        cancel: Option<Arc<CancelToken>>,
+        f: impl FnOnce(&mut S, &mut dyn FnMut(T)) -> R + Send + 'static,
+        on_item: &mut dyn FnMut(T),
+    ) -> Result<R, String>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.schedule(Box::new(move |state| {
+            let items = tx.clone();
+            let result = f(state, &mut |item| {
+                let _ = items.send(Message::Item(item));
+            });
+            let _ = tx.send(Message::Done(result));
+        }), options, cancel)?;
+
+        for message in rx {
+            match message {
+                Message::Item(item) => on_item(item),
+                Message::Done(result) => return Ok(result),
+            }
+        }
+        Err("Model worker isteği tamamlamadı".to_string())
+    }
+
+    pub fn status(&self) -> WorkerStatus {
+        let queue = self.shared.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        WorkerStatus {
+            busy: queue.jobs.is_running(),
+            queued: queue.jobs.len(),
+            completed: self.shared.completed.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Queue depth and wait times per priority class
+    pub fn queue_stats(&self) -> QueueStats {
+        let queue = self.shared.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        queue.jobs.stats(Instant::now())
+    }
+}
+
+impl<S> Shared<S> {
+    /// Block until a job is available; None once the worker is closed and drained
+    fn next_job(&self) -> Option<Job<S>> {
+        let mut queue = self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        loop {
+            if let Some(job) = queue.jobs.pop(Instant::now()) {
+                return Some(job);
+            }
+            if queue.closed {
+                return None;
+            }
+            queue = self.available.wait(queue).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn finish_job(&self) {
+        self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).jobs.finish();
+        self.completed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_jobs_run_in_order_on_worker_state() {
+        let worker = Worker::spawn("test", Vec::<u32>::new()).unwrap();
+        for i in 0..5 {
+            worker.submit(Box::new(move |log: &mut Vec<u32>| log.push(i))).unwrap();
+        }
+        let log = worker.call(|log| log.clone()).unwrap();
+        assert_eq!(log, vec![0, 1, 2, 3, 4]);
+        assert!(worker.status().completed >= 5);
+    }
+
+    #[test]
+    fn test_streaming_items_reach_caller() {
+        let worker = Worker::spawn("stream", ()).unwrap();
+        let mut pieces = Vec::new();
+        let total = worker
+            .call_streaming(
+                &ScheduleOptions::default(),
+                None,
+                |_, emit: &mut dyn FnMut(String)| {
+                    for piece in ["a", "b", "c"] {
+                        emit(piece.to_string());
+                    }
+                    3
+                },
+                &mut |piece| pieces.push(piece),
+            )
+            .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(pieces, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_workers_run_in_parallel() {
+        let a = Worker::spawn("a", ()).unwrap();
+        let b = Worker::spawn("b", ()).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        // `a` waits for a message that only `b` sends; this deadlocks on a single thread
+        a.submit(Box::new(move |_| {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }))
+        .unwrap();
+        b.call(move |_| tx.send(()).unwrap()).unwrap();
+        a.call(|_| ()).unwrap();
+    }
+
+    #[test]
+    fn test_panicking_job_keeps_worker_alive() {
+        let worker = Worker::spawn("panic", 1u32).unwrap();
+        assert!(worker.call(|_| -> u32 { panic!("boom") }).is_err());
+        assert_eq!(worker.call(|n| *n + 1).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_interactive_jumps_ahead_of_background() {
+        use crate::gguf_scheduler::RequestPriority;
+
+        let worker = Worker::spawn("priority", Vec::<&'static str>::new()).unwrap();
+        let (release, gate) = mpsc::channel::<()>();
+        let (started_tx, started) = mpsc::channel::<()>();
+        // Hold the worker busy so everything else has to queue
+        worker.submit(Box::new(move |_| {
+            started_tx.send(()).unwrap();
+            gate.recv().unwrap();
+        }))
+        .unwrap();
+        started.recv().unwrap();
+
+        let background = ScheduleOptions { priority: RequestPriority::Background, supersede_key: None };
+        let interactive = ScheduleOptions { priority: RequestPriority::Interactive, supersede_key: None };
+        worker.schedule(Box::new(|log| log.push("background")), &background, None).unwrap();
+        worker.schedule(Box::new(|log| log.push("interactive")), &interactive, None).unwrap();
+        assert_eq!(worker.queue_stats().queued, 2);
+
+        // Read the log from the back of the queue
+        let (log_tx, log_rx) = mpsc::channel();
+        worker.schedule(Box::new(move |log| log_tx.send(log.clone()).unwrap()), &background, None).unwrap();
+
+        release.send(()).unwrap();
+        assert_eq!(log_rx.recv().unwrap(), vec!["interactive", "background"]);
+    }
+}
@@ -3,11 +3,35 @@
 // This is the library entry point for Tauri 2.x
 // The main.rs file will call run() from here
 
+pub mod cancellation;
+pub mod chat_template;
 pub mod commands;
 pub mod gguf;
+pub mod gguf_context_shift;
+pub mod gguf_download;
+pub mod gguf_embedding;
+pub mod gguf_fim;
+pub mod gguf_grammar;
+pub mod gguf_header;
+pub mod gguf_inference;
+pub mod gguf_library;
+pub mod gguf_lora;
+pub mod gguf_memory;
+pub mod gguf_metrics;
+pub mod gguf_openai;
+pub mod gguf_pool;
+pub mod gguf_sampling;
+pub mod gguf_scheduler;
+pub mod gguf_server;
+pub mod gguf_session;
+pub mod gguf_speculative;
+pub mod gguf_vision;
+pub mod gguf_worker;
 pub mod oauth;
 pub mod oauth_backend;
+pub mod stop_sequences;
 pub mod streaming;
+pub mod token_count;
 pub mod vector_db;
 pub mod rag_pipeline;
 pub mod tree_sitter_parser;
@@ -3,14 +3,38 @@
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cancellation;
+mod chat_template;
 mod collab; // 🆕 WebSocket collaboration
 mod commands;
 mod gguf;
+mod gguf_context_shift;
+mod gguf_download;
+mod gguf_embedding;
+mod gguf_fim;
+mod gguf_grammar;
+mod gguf_header;
+mod gguf_inference;
+mod gguf_library;
+mod gguf_lora;
+mod gguf_memory;
+mod gguf_metrics;
+mod gguf_openai;
+mod gguf_pool;
+mod gguf_sampling;
+mod gguf_scheduler;
+mod gguf_server;
+mod gguf_session;
+mod gguf_speculative;
+mod gguf_vision;
+mod gguf_worker;
 mod mcp;
 mod oauth;
 mod oauth_backend;
 mod rag_pipeline;
+mod stop_sequences;
 mod streaming;
+mod token_count;
 mod tree_sitter_parser;
 
 mod vector_db; // 🆕 MCP (Model Context Protocol)
@@ -28,7 +52,6 @@ use commands::{
     create_embedding_bge,
     create_file,
     delete_file_index,
-    download_gguf_model,
     execute_terminal_command,
     get_all_files,
     git_log_project,
@@ -51,22 +74,51 @@ use commands::{
 };
 
 use gguf::{
+    benchmark_gguf_model,
+    chat_with_gguf_messages,
     chat_with_gguf_model,
     chat_with_gguf_vision, // 🆕 Vision AI
     check_cuda_support,
+    complete_fim,
     get_gguf_model_status,
+    get_gguf_queue_stats,
     get_gpu_memory_info,
+    list_gguf_loras,
+    load_gguf_lora,
+    load_gguf_mmproj,
     load_gguf_model,
+    load_gguf_session,
+    pin_gguf_model,
     read_gguf_metadata,
+    save_gguf_session,
+    set_gguf_lora_scale,
+    set_gguf_memory_budget,
+    unload_gguf_lora,
     unload_gguf_model,
     GgufState,
 };
 
+use gguf_download::{cancel_download, download_gguf_model};
+
+use gguf_library::{
+    get_model_directories,
+    list_library_models,
+    remove_library_model,
+    scan_model_library,
+    search_library_models,
+    set_model_directories,
+    tag_library_model,
+};
+
+use gguf_server::{get_gguf_server_status, start_gguf_server, stop_gguf_server, GgufServerState};
+
 use mcp::{list_mcp_servers, send_mcp_request, start_mcp_server, stop_mcp_server, McpState};
 
 use oauth::oauth_authenticate;
 use oauth_backend::{exchange_oauth_token, refresh_oauth_token};
 use streaming::{chat_with_http_streaming, chat_with_streaming};
+use cancellation::cancel_generation;
+use token_count::{count_tokens, fit_chat_history};
 
 use std::sync::{Arc, Mutex};
 
@@ -91,6 +143,7 @@ pub fn run() {
     tauri::Builder::default()
         .manage(gguf_state.clone())
         .manage(mcp_state)
+        .manage(GgufServerState::default())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
@@ -127,13 +180,37 @@ pub fn run() {
             commands::execute_command,
             load_gguf_model,
             chat_with_gguf_model,
+            chat_with_gguf_messages,
             chat_with_gguf_vision,
+            complete_fim,
+            benchmark_gguf_model,
             unload_gguf_model,
+            pin_gguf_model,
+            load_gguf_lora,
+            load_gguf_mmproj,
+            set_gguf_lora_scale,
+            list_gguf_loras,
+            unload_gguf_lora,
+            set_gguf_memory_budget,
             get_gguf_model_status,
+            get_gguf_queue_stats,
             get_gpu_memory_info,
             read_gguf_metadata,
+            save_gguf_session,
+            load_gguf_session,
+            start_gguf_server,
+            stop_gguf_server,
+            get_gguf_server_status,
             check_cuda_support,
             download_gguf_model,
+            cancel_download,
+            list_library_models,
+            search_library_models,
+            scan_model_library,
+            get_model_directories,
+            set_model_directories,
+            tag_library_model,
+            remove_library_model,
             get_all_files,
             read_file_content,
             oauth_authenticate,
@@ -141,6 +218,9 @@ pub fn run() {
             refresh_oauth_token,
             chat_with_streaming,
             chat_with_http_streaming,
+            cancel_generation,
+            count_tokens,
+            fit_chat_history,
             // Vector DB commands
             init_vector_db,
             vector_search,
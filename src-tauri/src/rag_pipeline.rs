@@ -4,6 +4,8 @@
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 
+use crate::token_count::{truncate_to_tokens, TokenCounter};
+
 /// Query intent types for context building
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -27,12 +29,27 @@ pub struct ContextSource {
 /// RAG Pipeline for multi-source context building
 pub struct RAGPipeline {
     max_context_tokens: usize,
+    token_counter: Option<TokenCounter>, // 🆕 Target model's tokenizer, estimate_tokens when unset
 }
 
 impl RAGPipeline {
     pub fn new(max_context_tokens: usize) -> Self {
         Self {
             max_context_tokens,
+            token_counter: None,
+        }
+    }
+    
+    /// Budget with the target model's own tokenizer instead of the length estimate
+    pub fn with_token_counter(mut self, counter: TokenCounter) -> Self {
+        self.token_counter = Some(counter);
+        self
+    }
+    
+    pub fn count_tokens(&self, text: &str) -> usize {
+        match &self.token_counter {
+            Some(counter) => counter(text),
+            None => Self::estimate_tokens(text),
         }
     }
     
@@ -120,20 +137,18 @@ impl RAGPipeline {
         context.push_str(&format!("Query: {}\n", query));
         context.push_str(&format!("Intent: {:?}\n\n", intent));
         
-        // Estimate tokens (rough: 1 token ≈ 4 characters)
-        let token_count = context.len() / 4;
-        
-        if token_count > available_tokens {
+        if self.count_tokens(&context) > available_tokens {
             // Truncate if needed
-            let max_chars = available_tokens * 4;
-            context.truncate(max_chars);
+            let count = |text: &str| self.count_tokens(text);
+            let kept = truncate_to_tokens(&context, available_tokens, &count).len();
+            context.truncate(kept);
             context.push_str("\n\n[Context truncated due to token limit]");
         }
         
         Ok((context, sources))
     }
     
-    /// Estimate token count (rough approximation)
+    /// Estimate token count (rough approximation, used when no tokenizer is available)
     pub fn estimate_tokens(text: &str) -> usize {
         // Rough estimation: 1 token ≈ 4 characters
         text.len() / 4
@@ -191,4 +206,11 @@ mod tests {
         // Should be around 14-15 tokens (rough estimate)
         assert!(tokens > 10 && tokens < 20);
     }
+    
+    #[test]
+    fn test_custom_token_counter() {
+        let pipeline = RAGPipeline::new(170_000)
+            .with_token_counter(std::sync::Arc::new(|text: &str| text.split_whitespace().count()));
+        assert_eq!(pipeline.count_tokens("three short words"), 3);
+    }
 }
@@ -0,0 +1,166 @@
+// src-tauri/src/stop_sequences.rs
+// Stop-string detection over streamed text, shared by GGUF decoding and HTTP streaming
+
+/// Outcome of feeding one piece into a `StopMatcher`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StopResult {
+    /// Text that can be emitted now, never contains (part of) a stop string
+    pub emit: String,
+    /// Stop string that ended the generation
+    pub stopped: Option<String>,
+}
+
+/// Finds stop strings in streamed text, including matches split across pieces.
+///
+/// Text that could still turn into a stop string is held back until the next
+/// piece decides it, so nothing belonging to a stop string is ever emitted.
+#[derive(Debug, Clone, Default)]
+pub struct StopMatcher {
+    stops: Vec<String>,
+    pending: String,
+}
+
+impl StopMatcher {
+    pub fn new(stops: &[String]) -> Self {
+        Self {
+            stops: stops.iter().filter(|s| !s.is_empty()).cloned().collect(),
+            pending: String::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stops.is_empty()
+    }
+
+    pub fn push(&mut self, piece: &str) -> StopResult {
+        if self.stops.is_empty() {
+            return StopResult { emit: piece.to_string(), stopped: None };
+        }
+
+        self.pending.push_str(piece);
+
+        // Earliest match wins, the longer stop string on a tie
+        let found = self
+            .stops
+            .iter()
+            .filter_map(|stop| self.pending.find(stop.as_str()).map(|idx| (idx, stop)))
+            .min_by(|a, b| a.0.cmp(&b.0).then(b.1.len().cmp(&a.1.len())));
+
+        if let Some((idx, stop)) = found {
+            let stop = stop.clone();
+            let emit = self.pending[..idx].to_string();
+            self.pending.clear();
+            return StopResult { emit, stopped: Some(stop) };
+        }
+
+        let hold = self.partial_match_len();
+        let split = self.pending.len() - hold;
+        let emit = self.pending[..split].to_string();
+        self.pending.drain(..split);
+        StopResult { emit, stopped: None }
+    }
+
+    /// Release held-back text once the generation ends without a stop string
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Length of the longest suffix of `pending` that is a proper prefix of a stop string
+    fn partial_match_len(&self) -> usize {
+        self.pending
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .find(|&idx| {
+                let suffix = &self.pending[idx..];
+                self.stops.iter().any(|stop| stop.len() > suffix.len() && stop.starts_with(suffix))
+            })
+            .map(|idx| self.pending.len() - idx)
+            .unwrap_or(0)
+    }
+}
+
+/// Cut `text` at the first stop string, for complete (non-streamed) responses
+pub fn truncate_at_stop<'a>(text: &'a str, stops: &[String]) -> &'a str {
+    let end = stops
+        .iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|stop| text.find(stop.as_str()))
+        .min()
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Feed pieces until a stop, returning everything emitted and the stop hit
+    fn run(matcher: &mut StopMatcher, pieces: &[&str]) -> (String, Option<String>) {
+        let mut out = String::new();
+        for piece in pieces {
+            let result = matcher.push(piece);
+            out.push_str(&result.emit);
+            if result.stopped.is_some() {
+                return (out, result.stopped);
+            }
+        }
+        out.push_str(&matcher.finish());
+        (out, None)
+    }
+
+    #[test]
+    fn test_stop_within_one_piece() {
+        let mut matcher = StopMatcher::new(&stops(&["\ndef "]));
+        let (out, stopped) = run(&mut matcher, &["return x\ndef foo", "(): pass"]);
+        assert_eq!(out, "return x");
+        assert_eq!(stopped.as_deref(), Some("\ndef "));
+    }
+
+    #[test]
+    fn test_stop_split_across_pieces() {
+        let mut matcher = StopMatcher::new(&stops(&["</code>"]));
+        let first = matcher.push("x = 1</c");
+        assert_eq!(first.emit, "x = 1");
+        assert!(first.stopped.is_none());
+
+        let second = matcher.push("ode> trailing");
+        assert_eq!(second.emit, "");
+        assert_eq!(second.stopped.as_deref(), Some("</code>"));
+    }
+
+    #[test]
+    fn test_false_partial_is_released() {
+        let mut matcher = StopMatcher::new(&stops(&["###"]));
+        let (out, stopped) = run(&mut matcher, &["a #", "# b", " c"]);
+        assert_eq!(out, "a ## b c");
+        assert!(stopped.is_none());
+    }
+
+    #[test]
+    fn test_held_text_flushed_on_finish() {
+        let mut matcher = StopMatcher::new(&stops(&["User:"]));
+        assert_eq!(matcher.push("done. Us").emit, "done. ");
+        assert_eq!(matcher.finish(), "Us");
+    }
+
+    #[test]
+    fn test_earliest_stop_wins_and_multibyte() {
+        let mut matcher = StopMatcher::new(&stops(&["END", "çık"]));
+        let (out, stopped) = run(&mut matcher, &["merhaba ç", "ık END"]);
+        assert_eq!(out, "merhaba ");
+        assert_eq!(stopped.as_deref(), Some("çık"));
+    }
+
+    #[test]
+    fn test_no_stops_passes_through() {
+        let mut matcher = StopMatcher::new(&stops(&[""]));
+        assert!(matcher.is_empty());
+        assert_eq!(matcher.push("abc").emit, "abc");
+        assert_eq!(truncate_at_stop("a\n\nb", &stops(&["\n\n"])), "a");
+        assert_eq!(truncate_at_stop("abc", &[]), "abc");
+    }
+}
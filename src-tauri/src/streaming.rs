@@ -5,6 +5,8 @@ use tauri::{AppHandle, Emitter, Manager};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 
+use crate::gguf_openai::{parse_completion_sse_line, SseData};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamToken {
     pub token: String,
@@ -17,6 +19,24 @@ pub struct StreamingRequest {
     pub prompt: String,
     pub max_tokens: Option<i32>,
     pub temperature: Option<f32>,
+    #[serde(default)]
+    pub messages: Option<Vec<crate::commands::ChatMessage>>, // 🆕 Set ise prompt yerine chat template ile render edilir
+    #[serde(default)]
+    pub sampling: Option<crate::gguf_sampling::SamplingParams>, // 🆕 GGUF sampler ayarları
+    #[serde(default)]
+    pub generation_id: Option<String>, // 🆕 cancel_generation ile durdurmak için
+    #[serde(default)]
+    pub constraint: Option<crate::gguf_grammar::OutputConstraint>, // 🆕 GBNF grammar / JSON schema
+    #[serde(default)]
+    pub stop: Option<Vec<String>>, // 🆕 Stop sequences
+    #[serde(default)]
+    pub speculative: Option<crate::gguf_speculative::SpeculativeConfig>, // 🆕 Draft model ile speculative decoding
+    #[serde(default)]
+    pub schedule: Option<crate::gguf_scheduler::ScheduleOptions>, // 🆕 Öncelik sınıfı / supersede key
+    #[serde(default)]
+    pub loras: Option<Vec<crate::gguf_lora::LoraRequest>>, // 🆕 İstek bazlı LoRA seçimi
+    #[serde(default)]
+    pub context_shift: Option<crate::gguf_context_shift::ContextShift>, // 🆕 n_ctx dolunca KV cache kaydırma
 }
 
 /// Stream AI response with real-time token emission
@@ -32,10 +52,10 @@ pub async fn chat_with_streaming(
     
     // Check if model is loaded
     let model_path = match &request.model_path {
-        Some(path) => path.clone(),
+        Some(path) => crate::gguf::pool_key(path),
         None => {
             // Fallback to first loaded model if not specified
-            let state = gguf_state.lock().unwrap();
+            let state = crate::gguf::lock_state(&gguf_state);
             match state.models.keys().next() {
                 Some(path) => path.clone(),
                 None => return Err("No GGUF models loaded. Please load a model first.".to_string()),
@@ -44,7 +64,7 @@ pub async fn chat_with_streaming(
     };
 
     {
-        let state = gguf_state.lock().unwrap();
+        let state = crate::gguf::lock_state(&gguf_state);
         if !state.models.contains_key(&model_path) {
             return Err(format!("Model not found in pool: {}", model_path));
         }
@@ -53,35 +73,65 @@ pub async fn chat_with_streaming(
     // Emit start event
     app.emit("stream-start", ()).map_err(|e| e.to_string())?;
     
-    let mut full_response = String::new();
+    let generation = crate::cancellation::register_generation(request.generation_id.clone());
+    crate::cancellation::emit_generation_started(&app, &generation.id);
     
-    // TODO: Implement real llama.cpp streaming
-    // For now, simulate streaming with the existing model
-    let response = crate::gguf::chat_with_gguf_model(
-        gguf_state,
-        model_path, // 🆕 Pass the resolved model path
-        request.prompt.clone(),
-        request.max_tokens.unwrap_or(2000) as u32,
-        request.temperature.unwrap_or(0.7),
-    ).await.map_err(|e| e.to_string())?;
-    
-    // Simulate streaming by splitting response
-    let words: Vec<&str> = response.split_whitespace().collect();
-    
-    for (_i, word) in words.iter().enumerate() {
-        let token = format!("{} ", word);
-        full_response.push_str(&token);
-        
-        // Emit token event
-        let stream_token = StreamToken {
-            token: token.clone(),
-            is_complete: false,
+    // Run the decode loop on a blocking thread and forward every piece as it is decoded
+    let state = gguf_state.inner().clone();
+    let emitter = app.clone();
+    let params = crate::gguf_inference::GenerationParams {
+        max_tokens: request.max_tokens.unwrap_or(2000) as u32,
+        temperature: request.temperature.unwrap_or(0.7),
+        sampling: request.sampling.clone().unwrap_or_default(),
+        grammar: request
+            .constraint
+            .as_ref()
+            .map(crate::gguf_grammar::OutputConstraint::compile)
+            .transpose()?,
+        stop: request.stop.clone().unwrap_or_default(),
+        speculative: request.speculative.clone(),
+        schedule: request.schedule.clone().unwrap_or_default(),
+        loras: request.loras.clone(),
+        cancel: Some(generation.token.clone()),
+        logprobs: None,
+        context_shift: request.context_shift,
+        cold_start: false,
+    };
+    let prompt = request.prompt.clone();
+    let messages = request.messages.clone();
+    
+    let (output, token_count) = tokio::task::spawn_blocking(move || {
+        let mut token_count = 0usize;
+        let gguf_prompt = match messages {
+            Some(messages) => crate::gguf::GgufPrompt::Chat(messages),
+            None => crate::gguf::GgufPrompt::Raw(prompt),
         };
-        
-        app.emit("stream-token", stream_token).map_err(|e| e.to_string())?;
-        
-        // Small delay to simulate streaming
-        tokio::time::sleep(tokio::time::Duration::from_millis(30)).await;
+        let output = crate::gguf::run_gguf_chat(
+            &state,
+            &model_path,
+            gguf_prompt,
+            &params,
+            &mut |piece| {
+                token_count += 1;
+                let stream_token = StreamToken {
+                    token: piece.to_string(),
+                    is_complete: false,
+                };
+                if let Err(e) = emitter.emit("stream-token", stream_token) {
+                    log::error!("❌ Stream token emit failed: {}", e);
+                }
+            },
+        )?;
+        Ok::<_, String>((output, token_count))
+    })
+    .await
+    .map_err(|e| format!("Inference task failed: {}", e))??;
+    
+    crate::gguf::emit_context_shift(&app, &generation.id, &output);
+    crate::gguf::emit_metrics(&app, &generation.id, &output.metrics);
+    let full_response = output.text;
+    if output.cancelled {
+        crate::cancellation::emit_generation_cancelled(&app, &generation.id, &full_response);
     }
     
     // Emit completion event
@@ -92,7 +142,7 @@ pub async fn chat_with_streaming(
     app.emit("stream-token", final_token).map_err(|e| e.to_string())?;
     app.emit("stream-complete", full_response.clone()).map_err(|e| e.to_string())?;
     
-    log::info!("✅ Streaming complete: {} tokens", words.len());
+    log::info!("✅ Streaming complete: {} tokens", token_count);
     
     Ok(full_response)
 }
@@ -114,20 +164,34 @@ pub async fn chat_with_http_streaming(
     // Emit start event
     app.emit("stream-start", ()).map_err(|e| e.to_string())?;
     
-    let body = serde_json::json!({
+    let generation = crate::cancellation::register_generation(request.generation_id.clone());
+    crate::cancellation::emit_generation_started(&app, &generation.id);
+    
+    let stop = request.stop.clone().unwrap_or_default();
+    let mut body = serde_json::json!({
         "model": "default",
         "prompt": request.prompt,
         "max_tokens": request.max_tokens.unwrap_or(2000),
         "temperature": request.temperature.unwrap_or(0.7),
         "stream": true
     });
+    if !stop.is_empty() {
+        body["stop"] = serde_json::json!(stop);
+    }
     
-    let response = client
+    let request_future = client
         .post(format!("{}/v1/completions", base_url))
         .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
+        .send();
+    
+    let response = tokio::select! {
+        response = request_future => response.map_err(|e| format!("HTTP request failed: {}", e))?,
+        _ = generation.token.cancelled() => {
+            log::info!("🛑 HTTP generation cancelled before the first byte");
+            crate::cancellation::emit_generation_cancelled(&app, &generation.id, "");
+            return Ok(String::new());
+        }
+    };
     
     if !response.status().is_success() {
         return Err(format!("HTTP error: {}", response.status()));
@@ -135,35 +199,64 @@ pub async fn chat_with_http_streaming(
     
     let mut stream = response.bytes_stream();
     let mut full_response = String::new();
+    let mut cancelled = false;
+    // Also enforced here, for servers that ignore the `stop` field
+    let mut stop_matcher = crate::stop_sequences::StopMatcher::new(&stop);
+    let mut stopped = false;
     
-    while let Some(chunk) = stream.next().await {
+    while !stopped {
+        // Dropping the stream on cancel closes the connection, so the server stops generating too
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = generation.token.cancelled() => {
+                cancelled = true;
+                break;
+            }
+        };
+        let Some(chunk) = chunk else { break };
         let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
         let text = String::from_utf8_lossy(&chunk);
         
         // Parse SSE format
         for line in text.lines() {
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    break;
-                }
+            let token = match parse_completion_sse_line(line) {
+                Some(SseData::Text(token)) => token,
+                Some(SseData::Done) => break,
+                None => continue,
+            };
+            let result = stop_matcher.push(&token);
+            if !result.emit.is_empty() {
+                full_response.push_str(&result.emit);
                 
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                    if let Some(token) = json["choices"][0]["text"].as_str() {
-                        full_response.push_str(token);
-                        
-                        let stream_token = StreamToken {
-                            token: token.to_string(),
-                            is_complete: false,
-                        };
-                        
-                        app.emit("stream-token", stream_token).map_err(|e| e.to_string())?;
-                    }
-                }
+                let stream_token = StreamToken {
+                    token: result.emit,
+                    is_complete: false,
+                };
+                
+                app.emit("stream-token", stream_token).map_err(|e| e.to_string())?;
             }
+            if result.stopped.is_some() {
+                // Dropping the stream closes the connection
+                stopped = true;
+                break;
+            }
+        }
+    }
+    
+    if !stopped {
+        let held = stop_matcher.finish();
+        if !held.is_empty() {
+            full_response.push_str(&held);
+            app.emit("stream-token", StreamToken { token: held, is_complete: false })
+                .map_err(|e| e.to_string())?;
         }
     }
     
+    if cancelled {
+        log::info!("🛑 HTTP generation cancelled: {}", generation.id);
+        crate::cancellation::emit_generation_cancelled(&app, &generation.id, &full_response);
+    }
+    
     // Emit completion
     let final_token = StreamToken {
         token: String::new(),
@@ -0,0 +1,228 @@
+// src-tauri/src/token_count.rs
+// Exact token counts: the pooled GGUF model's tokenizer, or bundled BPE tables for HTTP models
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::commands::ChatMessage;
+use crate::gguf::{gguf_token_counter, pool_key, GgufState};
+
+/// Tokens a chat format adds around every message (role markers, separators)
+pub const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Counts the tokens of a text with one specific tokenizer
+pub type TokenCounter = Arc<dyn Fn(&str) -> usize + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerKind {
+    /// The pooled GGUF model's own vocabulary
+    Gguf,
+    /// tiktoken o200k_base: GPT-4o, GPT-4.1, GPT-5, o-series
+    O200k,
+    /// tiktoken cl100k_base: GPT-4, GPT-3.5, text-embedding-3
+    Cl100k,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCount {
+    pub tokens: usize,
+    /// False when the model's own tokenizer is unavailable and cl100k_base stood in for it
+    pub exact: bool,
+    pub tokenizer: TokenizerKind,
+}
+
+/// Bundled BPE table of an HTTP model; None for models whose tokenizer is not bundled
+pub fn bpe_for_model(model: &str) -> Option<TokenizerKind> {
+    // "openai/gpt-4o-mini" -> "gpt-4o-mini"
+    let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    let o200k = ["gpt-4o", "chatgpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"];
+    let cl100k = ["gpt-4", "gpt-3.5", "text-embedding-3", "text-embedding-ada-002"];
+    if o200k.iter().any(|p| name.starts_with(p)) {
+        Some(TokenizerKind::O200k)
+    } else if cl100k.iter().any(|p| name.starts_with(p)) {
+        Some(TokenizerKind::Cl100k)
+    } else {
+        None
+    }
+}
+
+fn count_bpe(text: &str, kind: TokenizerKind) -> usize {
+    match kind {
+        TokenizerKind::O200k => tiktoken_rs::o200k_base_singleton().encode_ordinary(text).len(),
+        _ => tiktoken_rs::cl100k_base_singleton().encode_ordinary(text).len(),
+    }
+}
+
+/// Counter for an HTTP model: exact for OpenAI models, cl100k_base as a close estimate otherwise
+pub fn http_token_counter(model: &str) -> (TokenCounter, TokenizerKind, bool) {
+    let (kind, exact) = match bpe_for_model(model) {
+        Some(kind) => (kind, true),
+        None => (TokenizerKind::Cl100k, false),
+    };
+    (Arc::new(move |text: &str| count_bpe(text, kind)), kind, exact)
+}
+
+/// Result of fitting a conversation into a token budget
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryFit {
+    pub messages: Vec<ChatMessage>,
+    /// Tokens of the kept messages, including the per-message overhead
+    pub tokens: usize,
+    /// Older messages left out to stay within the budget
+    pub dropped: usize,
+}
+
+/// Keep the leading system messages and as many of the newest messages as fit into
+/// `max_tokens`. The last message is always kept; if even that does not fit, the
+/// overflow is reported by `check_context_fits`.
+pub fn fit_history(messages: &[ChatMessage], max_tokens: usize, count: &dyn Fn(&str) -> usize) -> HistoryFit {
+    let cost = |m: &ChatMessage| count(&m.content) + MESSAGE_OVERHEAD_TOKENS;
+    let n_system = messages.iter().take_while(|m| m.role == "system").count();
+    let (system, rest) = messages.split_at(n_system);
+
+    let mut tokens: usize = system.iter().map(cost).sum();
+    let mut kept = 0;
+    for (i, message) in rest.iter().enumerate().rev() {
+        let message_tokens = cost(message);
+        if i + 1 < rest.len() && tokens + message_tokens > max_tokens {
+            break;
+        }
+        tokens += message_tokens;
+        kept += 1;
+    }
+
+    let mut fitted = system.to_vec();
+    fitted.extend_from_slice(&rest[rest.len() - kept..]);
+    HistoryFit { messages: fitted, tokens, dropped: rest.len() - kept }
+}
+
+/// Fail before sending when the prompt and the reply cannot both fit into the context window
+pub fn check_context_fits(prompt_tokens: usize, max_output_tokens: usize, context_window: usize) -> Result<(), String> {
+    if prompt_tokens + max_output_tokens > context_window {
+        return Err(format!(
+            "Context aşıldı: prompt {} + yanıt {} token, model context'i {} token",
+            prompt_tokens, max_output_tokens, context_window
+        ));
+    }
+    Ok(())
+}
+
+/// Longest prefix of `text` within `max_tokens`, cut at a line break when there is one
+pub fn truncate_to_tokens<'a>(text: &'a str, max_tokens: usize, count: &dyn Fn(&str) -> usize) -> &'a str {
+    if count(text) <= max_tokens {
+        return text;
+    }
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+    // Binary search for the last boundary whose prefix still fits
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if count(&text[..boundaries[mid]]) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    let prefix = &text[..boundaries[low]];
+    match prefix.rfind('\n') {
+        Some(nl) if nl > 0 => &prefix[..nl + 1],
+        _ => prefix,
+    }
+}
+
+/// Counter for `model`: a pooled GGUF model by path or library ID, otherwise an HTTP model by name
+pub fn token_counter(state: &Mutex<GgufState>, model: &str) -> (TokenCounter, TokenizerKind, bool) {
+    match gguf_token_counter(state, &pool_key(model)) {
+        Some(counter) => (counter, TokenizerKind::Gguf, true),
+        None => http_token_counter(model),
+    }
+}
+
+/// 🔢 Count the tokens of `text` with `model`'s tokenizer
+#[tauri::command]
+pub async fn count_tokens(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    text: String,
+    model: String,
+) -> Result<TokenCount, String> {
+    let (counter, tokenizer, exact) = token_counter(&state, &model);
+    let tokens = tokio::task::spawn_blocking(move || counter(&text))
+        .await
+        .map_err(|e| format!("Token sayımı başarısız: {}", e))?;
+    Ok(TokenCount { tokens, exact, tokenizer })
+}
+
+/// ✂️ Drop the oldest messages until the conversation fits into `max_tokens` of `model`
+#[tauri::command]
+pub async fn fit_chat_history(
+    state: State<'_, Arc<Mutex<GgufState>>>,
+    messages: Vec<ChatMessage>,
+    model: String,
+    max_tokens: usize,
+) -> Result<HistoryFit, String> {
+    let (counter, _, _) = token_counter(&state, &model);
+    tokio::task::spawn_blocking(move || fit_history(&messages, max_tokens, &*counter))
+        .await
+        .map_err(|e| format!("Token sayımı başarısız: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: content.to_string() }
+    }
+
+    #[test]
+    fn test_bpe_for_model() {
+        assert_eq!(bpe_for_model("gpt-4o-mini"), Some(TokenizerKind::O200k));
+        assert_eq!(bpe_for_model("openai/o3-mini"), Some(TokenizerKind::O200k));
+        assert_eq!(bpe_for_model("gpt-4-turbo"), Some(TokenizerKind::Cl100k));
+        assert_eq!(bpe_for_model("claude-3-5-sonnet"), None);
+        assert_eq!(bpe_for_model("qwen2.5-coder:7b"), None);
+    }
+
+    #[test]
+    fn test_fit_history_keeps_system_and_newest() {
+        let messages = vec![
+            message("system", "be brief"),
+            message("user", "one two three four"),
+            message("assistant", "five six"),
+            message("user", "seven"),
+        ];
+        // system 2+4, newest 1+4, previous 2+4 -> 17; the oldest user message would need 8 more
+        let fit = fit_history(&messages, 20, &words);
+        assert_eq!(fit.dropped, 1);
+        assert_eq!(fit.tokens, 17);
+        assert_eq!(fit.messages.len(), 3);
+        assert_eq!(fit.messages[0].role, "system");
+        assert_eq!(fit.messages[2].content, "seven");
+
+        // The last message stays even when it alone is over budget
+        let fit = fit_history(&messages, 1, &words);
+        assert_eq!(fit.messages.len(), 2);
+        assert!(check_context_fits(fit.tokens, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_context_check() {
+        assert!(check_context_fits(3000, 1000, 4096).is_ok());
+        let err = check_context_fits(3500, 1000, 4096).unwrap_err();
+        assert!(err.contains("4096"));
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = "alpha beta\ngamma delta\nepsilon";
+        assert_eq!(truncate_to_tokens(text, 10, &words), text);
+        assert_eq!(truncate_to_tokens(text, 3, &words), "alpha beta\n");
+        assert_eq!(truncate_to_tokens("one two three", 2, &words), "one two ");
+    }
+}
@@ -791,7 +791,7 @@ export default function GGUFModelBrowser({ onModelSelect }: GGUFModelBrowserProp
 
       // 🆕 Model yüklendikten sonra metadata'yı otomatik oku
       try {
-        const metadata = await invoke<any>('read_gguf_metadata', { path: ggufConfig.modelPath });
+        const metadata = await invoke<any>('read_gguf_metadata', { modelPath: ggufConfig.modelPath });
         setModelMetadata(metadata);
         console.log('📊 Metadata otomatik okundu:', Object.keys(metadata).length, 'alan');
       } catch (error) {
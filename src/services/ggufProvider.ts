@@ -13,6 +13,33 @@ export interface GgufModelConfig {
   minP?: number;
 }
 
+// Bir GGUF üretiminin süreleri (ms) ve hızları
+export interface GgufGenerationMetrics {
+  load_ms: number;
+  queue_ms: number;
+  prompt_tokens: number;
+  prompt_eval_tokens: number;
+  prompt_eval_ms: number;
+  prompt_tokens_per_second: number;
+  generated_tokens: number;
+  generation_ms: number;
+  generation_tokens_per_second: number;
+  time_to_first_token_ms: number | null;
+  total_ms: number;
+  stop_reason: 'eos' | 'max_tokens' | 'stop_sequence' | 'cancelled';
+}
+
+// chat_with_gguf_model / chat_with_gguf_messages / chat_with_gguf_vision yanıtı
+export interface GgufChatResponse {
+  content: string;
+  prompt_tokens: number;
+  cached_prompt_tokens: number;
+  completion_tokens: number;
+  cancelled: boolean;
+  stop_sequence: string | null;
+  metrics: GgufGenerationMetrics;
+}
+
 export interface GgufModelStatus {
   loaded: boolean;
   model_path: string | null;
@@ -51,7 +78,7 @@ export async function chatWithGgufModel(
 
   try {
     // Normal text-only chat - artık model_path iletiliyor
-    const response = await invoke<string>('chat_with_gguf_model', {
+    const { content: response } = await invoke<GgufChatResponse>('chat_with_gguf_model', {
       modelPath, // 🆕 backend'e ilet
       prompt,
       maxTokens,
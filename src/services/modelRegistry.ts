@@ -67,7 +67,7 @@ export async function readModelMetadata(modelPath: string): Promise<ModelMetadat
     console.log('📖 Reading model metadata:', modelPath);
     
     // Rust backend'den metadata oku
-    const rawMetadata = await invoke<Record<string, any>>('read_gguf_metadata', { path: modelPath });
+    const rawMetadata = await invoke<Record<string, any>>('read_gguf_metadata', { modelPath });
     
     console.log('📊 Raw metadata keys:', Object.keys(rawMetadata).length);
     
@@ -59,7 +59,7 @@ export const ragService: RAGService = {
                 content: `Commit: ${commit.message}\nAuthor: ${commit.author}\nDate: ${new Date(commit.timestamp).toLocaleDateString()}`,
                 chunkType: 'Commit',
                 symbolName: commit.author,
-                endpoint
+                embedding: { endpoint }
             });
             console.log("✅ Commit indexed:", commit.hash);
         } catch (error) {
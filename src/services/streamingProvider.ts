@@ -1,5 +1,6 @@
 // Streaming AI Response Provider
 import { invoke } from "@tauri-apps/api/core";
+import type { GgufChatResponse } from "./ggufProvider";
 
 export interface StreamingConfig {
   onToken?: (token: string) => void;
@@ -17,7 +18,7 @@ export async function chatWithStreaming(
   config: StreamingConfig
 ): Promise<string> {
   try {
-    const fullResponse = await invoke<string>('chat_with_gguf_model', {
+    const { content: fullResponse } = await invoke<GgufChatResponse>('chat_with_gguf_model', {
       modelPath,
       prompt,
       maxTokens,
@@ -60,7 +61,7 @@ export async function chatWithChunkedStreaming(
   config: StreamingConfig
 ): Promise<string> {
   try {
-    const fullResponse = await invoke<string>('chat_with_gguf_model', {
+    const { content: fullResponse } = await invoke<GgufChatResponse>('chat_with_gguf_model', {
       modelPath,
       prompt,
       maxTokens,